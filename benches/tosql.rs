@@ -0,0 +1,136 @@
+/*
+   run with cargo bench --bench tosql
+   the workload from examples/tosql.rs: 100k rows of
+   {"id":1,"first_name":"Audy","last_name":"Taborre","lat":-17.3058881,"long":31.5655424}
+   in one big array, turned into INSERT statements. the statements are only built, not
+   printed, so that the numbers are about getting the tags across
+
+   compares sending every tag and token through the channel on its own (the way it
+   used to work) with sending them in batches
+*/
+
+use hamberder::{lexer, parser, parser::Tag, pipeline};
+use std::time::{Duration, Instant};
+
+const ROWS: usize = 100_000;
+const RUNS: usize = 5;
+
+fn input() -> String {
+    const FIRST: [&str; 4] = ["Audy", "Bert", "Cleo", "Dana"];
+    const LAST: [&str; 4] = ["Taborre", "Ng", "Okafor", "Lindqvist"];
+    let mut json = String::from("[");
+    for id in 1..=ROWS {
+        if id > 1 {
+            json.push_str(",\n");
+        }
+        json.push_str(&format!(
+            "{{\"id\":{},\"first_name\":\"{}\",\"last_name\":\"{}\",\"lat\":{:.7},\"long\":{:.7}}}",
+            id,
+            FIRST[id % 4],
+            LAST[id % 3],
+            -17.3058881 + (id % 1000) as f64 * 0.001,
+            31.5655424 - (id % 1000) as f64 * 0.001
+        ));
+    }
+    json.push(']');
+    json
+}
+
+// the same walk over the tags as the example, minus the printing
+fn to_sql(tags: impl Iterator<Item = Tag>) -> usize {
+    let mut statements = 0;
+    let mut values = Vec::new();
+    for tag in tags {
+        match tag {
+            Tag::Number(value) | Tag::StringLiteral(value) => values.push(value),
+            Tag::EndObject => {
+                let statement = format!(
+                    "INSERT INTO \"my_table\" VALUES ({}, \"{}\", \"{}\", {}, {});",
+                    values[0], values[1], values[2], values[3], values[4]
+                );
+                std::hint::black_box(statement);
+                values.clear();
+                statements += 1;
+            }
+            _ => {}
+        }
+    }
+    statements
+}
+
+// the source arrives in 8 KiB chunks, the way parse_file(...) reads it
+fn chunks(json: &str) -> lexer::UTF8Source {
+    let (str_tx, str_rx) = std::sync::mpsc::channel();
+    let mut rest = json;
+    while !rest.is_empty() {
+        let mut end = rest.len().min(8 * 1024);
+        while !rest.is_char_boundary(end) {
+            end += 1;
+        }
+        str_tx.send(rest[..end].to_string()).unwrap();
+        rest = &rest[end..];
+    }
+    str_rx
+}
+
+fn pipeline(json: &str, batch_size: usize) -> usize {
+    let options = pipeline::PipelineOptions {
+        tag_capacity: None,
+        batch_size,
+        ..Default::default()
+    };
+    let (tag_sink, _) = hamberder::parse_cancellable(chunks(json), options);
+    to_sql(tag_sink.into_iter())
+}
+
+fn stages(json: &str) -> usize {
+    let (token_tx, token_rx) = std::sync::mpsc::channel();
+    let (tag_tx, tag_rx) = std::sync::mpsc::channel();
+    lexer::lex(chunks(json), token_tx);
+    parser::parse(token_rx, tag_tx);
+    to_sql(tag_rx.into_iter())
+}
+
+fn batched_stages(json: &str) -> usize {
+    let (token_tx, token_rx) = std::sync::mpsc::channel();
+    let (tag_tx, tag_rx) = std::sync::mpsc::channel();
+    lexer::lex_batches(chunks(json), token_tx, Default::default());
+    parser::parse_batches(
+        token_rx,
+        tag_tx,
+        Default::default(),
+        pipeline::DEFAULT_BATCH_SIZE,
+    );
+    to_sql(pipeline::BatchReceiver::new(tag_rx).into_iter())
+}
+
+fn bench(name: &str, json: &str, run: impl Fn(&str) -> usize) -> Duration {
+    let mut times: Vec<Duration> = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            assert_eq!(run(json), ROWS);
+            start.elapsed()
+        })
+        .collect();
+    times.sort();
+    let median = times[RUNS / 2];
+    println!("{:<40} {:>10.1?} (median of {})", name, median, RUNS);
+    median
+}
+
+fn main() {
+    let json = input();
+    println!("{} rows, {} bytes\n", ROWS, json.len());
+    let one = bench("parse, one tag at a time", &json, |json| pipeline(json, 1));
+    let batched = bench("parse, batched", &json, |json| {
+        pipeline(json, pipeline::DEFAULT_BATCH_SIZE)
+    });
+    let stage = bench("lex + parse stages, one at a time", &json, stages);
+    let batched_stage = bench("lex + parse stages, batched", &json, batched_stages);
+    println!();
+    println!(
+        "batching is {:.1}x as fast for parse and {:.1}x for the stages",
+        one.as_secs_f64() / batched.as_secs_f64(),
+        stage.as_secs_f64() / batched_stage.as_secs_f64()
+    );
+}
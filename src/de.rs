@@ -0,0 +1,450 @@
+// a serde Deserializer that reads straight from the tag stream, so #[derive(Deserialize)]
+// types can be filled from parse(...), parse_file(...) or a PullParser without walking
+// the tags by hand. only built with the serde feature
+//
+// a plain Tag doesn't know where it came from, so a value of the wrong type from
+// parse(...), parse_file(...), parse_cancellable(...) or a PullParser is an
+// Error::Data without a position. syntax errors always have one. to get positions for
+// the rest too, hand in positioned tags: parse_positioned(...),
+// parse_file_positioned(...) or PullParser::into_positioned(). from_str(...),
+// from_reader(...) and from_file(...) already do
+use crate::{
+    error::Error,
+    parser::{Number, OutOfRange, PositionedTag, Tag, TagInfo},
+};
+use serde::de::{self, DeserializeOwned, IntoDeserializer};
+use std::{fmt, marker::PhantomData};
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        Error::Data(message.to_string(), None)
+    }
+}
+
+// anything that comes out of a parser: plain tags, or tags along with their position.
+// errors only know where they happened with the latter, except for syntax errors, which
+// always do
+pub trait TagItem {
+    fn into_parts(self) -> (Tag, Option<TagInfo>);
+}
+
+impl TagItem for Tag {
+    fn into_parts(self) -> (Tag, Option<TagInfo>) {
+        (self, None)
+    }
+}
+
+impl TagItem for PositionedTag {
+    fn into_parts(self) -> (Tag, Option<TagInfo>) {
+        (self.0, Some(self.1))
+    }
+}
+
+pub struct Deserializer<I> {
+    tags: I,
+    peeked: Option<(Tag, Option<TagInfo>)>,
+    // where the last tag that was taken came from, for errors that don't know
+    last_info: Option<TagInfo>,
+}
+
+impl<I, T> Deserializer<I>
+where
+    I: Iterator<Item = T>,
+    T: TagItem,
+{
+    pub fn new(tags: I) -> Deserializer<I> {
+        Deserializer {
+            tags,
+            peeked: None,
+            last_info: None,
+        }
+    }
+
+    // comments don't matter here, and an error in the stream ends the deserialization
+    fn peek(&mut self) -> Result<Option<&Tag>, Error> {
+        while self.peeked.is_none() {
+            match self.tags.next().map(TagItem::into_parts) {
+                Some((Tag::Comment(_), _)) => {}
+                Some((Tag::Error(error, info), _)) => return Err(Error::from_syntax(error, info)),
+                Some(parts) => self.peeked = Some(parts),
+                None => return Ok(None),
+            }
+        }
+        Ok(self.peeked.as_ref().map(|(tag, _)| tag))
+    }
+
+    fn next(&mut self) -> Result<Tag, Error> {
+        self.peek()?;
+        match self.peeked.take() {
+            Some((tag, info)) => {
+                if info.is_some() {
+                    self.last_info = info;
+                }
+                Ok(tag)
+            }
+            None => Err(self.error("unexpected end of the tag stream")),
+        }
+    }
+
+    fn error(&self, message: &str) -> Error {
+        Error::Data(message.to_string(), self.last_info.clone())
+    }
+
+    // errors from Deserialize impls don't know where they happened, but we do
+    fn locate(&self, error: Error) -> Error {
+        match error {
+            Error::Data(message, None) => Error::Data(message, self.last_info.clone()),
+            error => error,
+        }
+    }
+
+    // takes the rest of the document, if there is any, in case it has an error in it
+    fn end(&mut self) -> Result<(), Error> {
+        match self.peek()? {
+            None | Some(Tag::EndDocument(_)) => Ok(()),
+            Some(_) => {
+                self.next()?;
+                Err(self.error("expected the end of the document"))
+            }
+        }
+    }
+
+    // steps over a whole value without building anything
+    fn skip_value(&mut self) -> Result<(), Error> {
+        let mut depth = 0usize;
+        loop {
+            match self.next()? {
+                Tag::BeginArray | Tag::BeginObject => depth += 1,
+                Tag::EndArray | Tag::EndObject => depth = depth.saturating_sub(1),
+                Tag::ObjectKey(_) => continue,
+                _ => {}
+            }
+            if depth == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    fn expect_end(&mut self, end: Tag, message: &str) -> Result<(), Error> {
+        if self.next()? == end {
+            Ok(())
+        } else {
+            Err(self.error(message))
+        }
+    }
+}
+
+// deserializes one value from the tags. it has to be the whole document. Error::Data
+// only has a position if the tags have theirs
+pub fn from_tags<T, I>(tags: I) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+    I: IntoIterator,
+    I::Item: TagItem,
+{
+    let mut de = Deserializer::new(tags.into_iter());
+    let value = T::deserialize(&mut de).map_err(|e| de.locate(e))?;
+    de.end()?;
+    Ok(value)
+}
+
+// from_str(...), from_reader(...) and from_file(...) all parse with positions, so
+// values of the wrong type say where they are
+pub fn from_str<T: DeserializeOwned>(source: &str) -> Result<T, Error> {
+    from_tags(crate::parse_str(source).into_positioned())
+}
+
+pub fn from_reader<T: DeserializeOwned, R: std::io::Read>(reader: R) -> Result<T, Error> {
+    from_tags(crate::parse_reader(reader).into_positioned())
+}
+
+pub fn from_file<T: DeserializeOwned>(file_path: &str) -> Result<T, Error> {
+    let f = std::fs::File::open(file_path)?;
+    from_reader(std::io::BufReader::new(f))
+}
+
+// deserializes the elements of a top-level array one at a time, as the tags come in,
+// without ever holding the whole array. the first error ends the iteration. like
+// from_tags(...), give it positioned tags for errors that say where they are
+pub fn iter_array<T, I>(tags: I) -> ArrayIter<T, I::IntoIter>
+where
+    T: DeserializeOwned,
+    I: IntoIterator,
+    I::Item: TagItem,
+{
+    ArrayIter {
+        de: Deserializer::new(tags.into_iter()),
+        started: false,
+        finished: false,
+        element: PhantomData,
+    }
+}
+
+pub struct ArrayIter<T, I> {
+    de: Deserializer<I>,
+    started: bool,
+    finished: bool,
+    element: PhantomData<T>,
+}
+
+impl<T, I> ArrayIter<T, I>
+where
+    T: DeserializeOwned,
+    I: Iterator,
+    I::Item: TagItem,
+{
+    fn next_element(&mut self) -> Result<Option<T>, Error> {
+        if !self.started {
+            self.started = true;
+            if self.de.next()? != Tag::BeginArray {
+                return Err(self.de.error("expected an array"));
+            }
+        }
+        if let Some(Tag::EndArray) = self.de.peek()? {
+            self.de.next()?;
+            self.de.end()?;
+            return Ok(None);
+        }
+        T::deserialize(&mut self.de)
+            .map(Some)
+            .map_err(|e| self.de.locate(e))
+    }
+}
+
+impl<T, I> Iterator for ArrayIter<T, I>
+where
+    T: DeserializeOwned,
+    I: Iterator,
+    I::Item: TagItem,
+{
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Result<T, Error>> {
+        if self.finished {
+            return None;
+        }
+        let element = self.next_element();
+        if !matches!(element, Ok(Some(_))) {
+            self.finished = true;
+        }
+        element.transpose()
+    }
+}
+
+fn visit_number<'de, V: de::Visitor<'de>>(
+    number: Number,
+    lexeme: &str,
+    visitor: V,
+) -> Result<V::Value, Error> {
+    match number {
+        Number::Int(value) => visitor.visit_i64(value),
+        Number::UInt(value) => visitor.visit_u64(value),
+        Number::Float(value) => visitor.visit_f64(value),
+        Number::BigNumber(_) => match lexeme.parse::<f64>() {
+            Ok(value) if value.is_finite() => visitor.visit_f64(value),
+            _ => Err(de::Error::custom(format!("the number {} is out of range", lexeme))),
+        },
+    }
+}
+
+impl<'de, I, T> de::Deserializer<'de> for &mut Deserializer<I>
+where
+    I: Iterator<Item = T>,
+    T: TagItem,
+{
+    type Error = Error;
+
+    fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.next()? {
+            Tag::NullLiteral => visitor.visit_unit(),
+            Tag::TrueLiteral => visitor.visit_bool(true),
+            Tag::FalseLiteral => visitor.visit_bool(false),
+            Tag::StringLiteral(value) => visitor.visit_string(value),
+            Tag::Number(lexeme) => {
+                // integers too big for a u64 can still go into a float
+                let number = Number::from_lexeme(&lexeme, OutOfRange::KeepAsString)
+                    .map_err(|kind| self.error(&kind.to_string()))?;
+                visit_number(number, &lexeme, visitor)
+            }
+            Tag::TypedNumber(number, lexeme) => visit_number(number, &lexeme, visitor),
+            Tag::BeginArray => {
+                let mut seq = Seq { de: self, done: false };
+                let value = visitor.visit_seq(&mut seq)?;
+                if !seq.done {
+                    seq.de
+                        .expect_end(Tag::EndArray, "the array has more elements than expected")?;
+                }
+                Ok(value)
+            }
+            Tag::BeginObject => {
+                let mut map = Map { de: self, done: false };
+                let value = visitor.visit_map(&mut map)?;
+                if !map.done {
+                    map.de
+                        .expect_end(Tag::EndObject, "the object has more members than expected")?;
+                }
+                Ok(value)
+            }
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if let Some(Tag::NullLiteral) = self.peek()? {
+            self.next()?;
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    // a unit variant is a string, anything else an object with the variant as its only key
+    fn deserialize_enum<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.next()? {
+            Tag::StringLiteral(variant) => visitor.visit_enum(variant.into_deserializer()),
+            Tag::BeginObject => {
+                let value = visitor.visit_enum(Enum { de: self })?;
+                self.expect_end(Tag::EndObject, "expected an object with a single key")?;
+                Ok(value)
+            }
+            _ => Err(self.error("expected a string or an object with a single key")),
+        }
+    }
+
+    fn deserialize_ignored_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.skip_value()?;
+        visitor.visit_unit()
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct identifier
+    }
+}
+
+struct Seq<'a, I> {
+    de: &'a mut Deserializer<I>,
+    done: bool,
+}
+
+impl<'de, I, T> de::SeqAccess<'de> for Seq<'_, I>
+where
+    I: Iterator<Item = T>,
+    T: TagItem,
+{
+    type Error = Error;
+
+    fn next_element_seed<S: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: S,
+    ) -> Result<Option<S::Value>, Error> {
+        if self.done {
+            return Ok(None);
+        }
+        if let Some(Tag::EndArray) = self.de.peek()? {
+            self.de.next()?;
+            self.done = true;
+            return Ok(None);
+        }
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+}
+
+struct Map<'a, I> {
+    de: &'a mut Deserializer<I>,
+    done: bool,
+}
+
+impl<'de, I, T> de::MapAccess<'de> for Map<'_, I>
+where
+    I: Iterator<Item = T>,
+    T: TagItem,
+{
+    type Error = Error;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        if self.done {
+            return Ok(None);
+        }
+        match self.de.next()? {
+            Tag::ObjectKey(key) => seed.deserialize(key.into_deserializer()).map(Some),
+            Tag::EndObject => {
+                self.done = true;
+                Ok(None)
+            }
+            _ => Err(self.de.error("expected a key or the end of the object")),
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        seed.deserialize(&mut *self.de)
+    }
+}
+
+struct Enum<'a, I> {
+    de: &'a mut Deserializer<I>,
+}
+
+impl<'de, 'a, I, T> de::EnumAccess<'de> for Enum<'a, I>
+where
+    I: Iterator<Item = T>,
+    T: TagItem,
+{
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        match self.de.next()? {
+            Tag::ObjectKey(variant) => {
+                let variant: de::value::StringDeserializer<Error> = variant.into_deserializer();
+                let value = seed.deserialize(variant)?;
+                Ok((value, self))
+            }
+            _ => Err(self.de.error("expected the name of a variant")),
+        }
+    }
+}
+
+impl<'de, 'a, I, T> de::VariantAccess<'de> for Enum<'a, I>
+where
+    I: Iterator<Item = T>,
+    T: TagItem,
+{
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        de::Deserialize::deserialize(&mut *self.de)
+    }
+
+    fn newtype_variant_seed<S: de::DeserializeSeed<'de>>(self, seed: S) -> Result<S::Value, Error> {
+        seed.deserialize(&mut *self.de)
+    }
+
+    fn tuple_variant<V: de::Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_any(&mut *self.de, visitor)
+    }
+
+    fn struct_variant<V: de::Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_any(&mut *self.de, visitor)
+    }
+}
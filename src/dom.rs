@@ -0,0 +1,259 @@
+// an owned tree of a whole document, for when random access to a small document is
+// handier than walking the tags. only built with the dom feature
+use crate::{
+    error::Error,
+    parser::{Number, OutOfRange, Tag},
+};
+use std::{ops::Index, slice};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    // the text of the number, as in Tag::Number, so nothing gets lost on the way back.
+    // as_i64(), as_u64() and as_f64() convert it
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    // the keys in the order they came in. duplicates are kept, unless the parser was
+    // told otherwise (see ParserOptions::duplicate_keys)
+    Object(Vec<(String, Value)>),
+}
+
+// what indexing hands out for keys and indices that aren't there
+static NULL: Value = Value::Null;
+
+impl Value {
+    // builds the tree from a TagSink, a PullParser or any other iterator of tags. it has
+    // to be a whole document: an error in the stream, or anything after the value but
+    // an EndDocument, is an Err
+    pub fn from_tags<I: IntoIterator<Item = Tag>>(tags: I) -> Result<Value, Error> {
+        let mut tags = tags.into_iter().filter(|tag| !matches!(tag, Tag::Comment(_)));
+        // the arrays and objects that are still open, with the key their value goes
+        // under in the one around them
+        let mut stack: Vec<(Open, Option<String>)> = Vec::new();
+        let mut key = None;
+        let root = loop {
+            let tag = tags.next().ok_or_else(|| malformed("the tags end before the value does"))?;
+            let value = match tag {
+                Tag::BeginArray => {
+                    stack.push((Open::Array(Vec::new()), key.take()));
+                    continue;
+                }
+                Tag::BeginObject => {
+                    stack.push((Open::Object(Vec::new()), key.take()));
+                    continue;
+                }
+                Tag::ObjectKey(name) => {
+                    match stack.last() {
+                        Some((Open::Object(_), _)) if key.is_none() => key = Some(name),
+                        _ => return Err(malformed("a key where a value should be")),
+                    }
+                    continue;
+                }
+                Tag::EndArray => match stack.pop() {
+                    Some((Open::Array(values), outer)) => {
+                        key = outer;
+                        Value::Array(values)
+                    }
+                    _ => return Err(malformed("EndArray without an array to close")),
+                },
+                Tag::EndObject => match stack.pop() {
+                    Some((Open::Object(members), outer)) if key.is_none() => {
+                        key = outer;
+                        Value::Object(members)
+                    }
+                    _ => return Err(malformed("EndObject without an object to close")),
+                },
+                Tag::StringLiteral(value) => Value::String(value),
+                Tag::Number(lexeme) | Tag::TypedNumber(_, lexeme) => Value::Number(lexeme),
+                Tag::TrueLiteral => Value::Bool(true),
+                Tag::FalseLiteral => Value::Bool(false),
+                Tag::NullLiteral => Value::Null,
+                Tag::Error(error, info) => return Err(Error::from_syntax(error, info)),
+                Tag::Recovered => return Err(malformed("a value was lost to an error")),
+                Tag::EndDocument(_) | Tag::Comment(_) => {
+                    return Err(malformed("the document ends before the value does"))
+                }
+            };
+            match stack.last_mut() {
+                None => break value,
+                Some((Open::Array(values), _)) => values.push(value),
+                Some((Open::Object(members), _)) => match key.take() {
+                    Some(key) => members.push((key, value)),
+                    None => return Err(malformed("a value in an object without a key")),
+                },
+            }
+        };
+        // whatever is left of the document, in case there's an error in it
+        match tags.next() {
+            None | Some(Tag::EndDocument(_)) => Ok(root),
+            Some(Tag::Error(error, info)) => Err(Error::from_syntax(error, info)),
+            Some(_) => Err(malformed("expected the end of the document")),
+        }
+    }
+
+    // the tags the parser would have produced for this value, so it can be fed into
+    // anything that takes them, like a writer::TagWriter
+    pub fn to_tags(&self) -> Tags<'_> {
+        Tags {
+            next: Some(self),
+            stack: Vec::new(),
+        }
+    }
+
+    // the value of a key. with duplicate keys, the last one wins like it does in most
+    // json libraries
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members
+                .iter()
+                .rev()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn get_index(&self, index: usize) -> Option<&Value> {
+        match self {
+            Value::Array(values) => values.get(index),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == Value::Null
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_number(&self) -> Option<Number> {
+        match self {
+            Value::Number(lexeme) => Number::from_lexeme(lexeme, OutOfRange::KeepAsString).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        self.as_number()?.as_i64()
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        self.as_number()?.as_u64()
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        self.as_number()?.as_f64()
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Value>> {
+        match self {
+            Value::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&Vec<(String, Value)>> {
+        match self {
+            Value::Object(members) => Some(members),
+            _ => None,
+        }
+    }
+}
+
+// an array or object that from_tags() is still filling
+enum Open {
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+fn malformed(message: &str) -> Error {
+    Error::Data(message.to_string(), None)
+}
+
+// value["config"][0] never panics. a key or index that isn't there, or a value that
+// isn't an object or array, gives Value::Null
+impl Index<&str> for Value {
+    type Output = Value;
+
+    fn index(&self, key: &str) -> &Value {
+        self.get(key).unwrap_or(&NULL)
+    }
+}
+
+impl Index<usize> for Value {
+    type Output = Value;
+
+    fn index(&self, index: usize) -> &Value {
+        self.get_index(index).unwrap_or(&NULL)
+    }
+}
+
+enum Opened<'a> {
+    Array(slice::Iter<'a, Value>),
+    Object(slice::Iter<'a, (String, Value)>),
+}
+
+// hands out the tags of a Value one at a time, without recursing
+pub struct Tags<'a> {
+    // the value whose tags come next, if it's known already
+    next: Option<&'a Value>,
+    stack: Vec<Opened<'a>>,
+}
+
+impl Iterator for Tags<'_> {
+    type Item = Tag;
+
+    fn next(&mut self) -> Option<Tag> {
+        let value = match self.next.take() {
+            Some(value) => value,
+            None => match self.stack.last_mut()? {
+                Opened::Array(values) => match values.next() {
+                    Some(value) => value,
+                    None => {
+                        self.stack.pop();
+                        return Some(Tag::EndArray);
+                    }
+                },
+                Opened::Object(members) => match members.next() {
+                    Some((key, value)) => {
+                        self.next = Some(value);
+                        return Some(Tag::ObjectKey(key.clone()));
+                    }
+                    None => {
+                        self.stack.pop();
+                        return Some(Tag::EndObject);
+                    }
+                },
+            },
+        };
+        Some(match value {
+            Value::Null => Tag::NullLiteral,
+            Value::Bool(true) => Tag::TrueLiteral,
+            Value::Bool(false) => Tag::FalseLiteral,
+            Value::Number(lexeme) => Tag::Number(lexeme.clone()),
+            Value::String(value) => Tag::StringLiteral(value.clone()),
+            Value::Array(values) => {
+                self.stack.push(Opened::Array(values.iter()));
+                Tag::BeginArray
+            }
+            Value::Object(members) => {
+                self.stack.push(Opened::Object(members.iter()));
+                Tag::BeginObject
+            }
+        })
+    }
+}
@@ -0,0 +1,564 @@
+use crate::lexer::{ErrorInfo, TokenInfo};
+use crate::parser::Tag;
+use std::{collections::VecDeque, fmt, io};
+
+// what went wrong, so callers don't have to pick apart the message. every kind has a
+// code (see code(...)) that stays the same from one release to the next, even if the
+// wording of the message changes. Display gives the message that ends up in ErrorInfo
+#[derive(Clone, PartialEq, std::fmt::Debug)]
+pub enum ErrorKind {
+    UnexpectedChar(char),
+    // a true, false or null that went wrong halfway through
+    UnrecognizedToken,
+    InvalidEscape(char),
+    // a \u followed by something that isn't a hexadecimal digit
+    InvalidUnicodeEscape(char),
+    // a backslash right at the end of a string
+    IncompleteEscape,
+    // a \u with fewer than 4 hexadecimal digits after it
+    IncompleteUnicodeEscape,
+    // the same for json5's \xHH
+    InvalidHexEscape(char),
+    IncompleteHexEscape,
+    // the 4 hexadecimal digits of a \u that's half of a surrogate pair without the
+    // other half
+    UnpairedSurrogate(String),
+    UnterminatedString,
+    MultilineString,
+    UnexpectedWhitespace,
+    // the byte offset of the broken sequence
+    InvalidUtf8(usize),
+    TruncatedUtf8(usize),
+    EmptySource,
+    // the reader of a ReadSource failed. the kind of the io::Error, and its message
+    ReadFailed(io::ErrorKind, String),
+    RecordSeparatorInString,
+    RecordSeparatorInComment,
+    // a 0x without any digits after it
+    MissingHexDigits,
+    InvalidCommentStart,
+    UnexpectedComment,
+    UnterminatedComment,
+    // the limits in LexerOptions, along with the limit that was hit
+    StringTooLong(usize),
+    NumberTooLong(usize),
+    SourceTooLarge(usize),
+    TooManyTokens(usize),
+    CommentTooLong(usize),
+    // the key that's missing its colon or its value
+    MissingColon(String),
+    MissingValue(String),
+    // a missing comma between two members of an object or two elements of an array
+    MissingObjectComma,
+    MissingArrayComma,
+    ExpectedKeyValuePair,
+    // after a comma in an object, something that isn't a key
+    MissingKeyValuePair,
+    ExpectedArrayValue,
+    UnexpectedObjectComma,
+    UnexpectedArrayComma,
+    // the '}' or ']' right after the comma
+    TrailingComma(char),
+    UnexpectedEof,
+    // in DocumentMode::Lines and DocumentMode::Records
+    UnexpectedEndOfLine,
+    UnexpectedEndOfRecord,
+    LeadingZero,
+    MissingFraction,
+    MissingExponentDigits,
+    // the sign that isn't followed by any digits
+    MissingDigits(char),
+    // the digits after the 0x
+    HexOutOfRange(String),
+    // the number as written, with NumberMode::Typed(OutOfRange::Error)
+    NumberOutOfRange(String),
+    NotANumber(String),
+    MaxDepthExceeded,
+    // the key, along with the line and offset it was first seen at
+    DuplicateKey(String, usize, usize),
+    MissingRecordSeparator,
+    ExpectedValue,
+    TrailingToken,
+    // for errors that come from somewhere else, like a TokenSource that isn't fed by
+    // our lexer
+    Other(String),
+    // a state the lexer or parser should never get into, like a string token without
+    // its quotes. reported instead of panicking
+    Internal(String),
+}
+
+impl ErrorKind {
+    // the ones the lexer stops at for good, even in the multi-document modes
+    pub(crate) fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            ErrorKind::ReadFailed(_, _)
+                | ErrorKind::StringTooLong(_)
+                | ErrorKind::NumberTooLong(_)
+                | ErrorKind::SourceTooLarge(_)
+                | ErrorKind::TooManyTokens(_)
+                | ErrorKind::CommentTooLong(_)
+        )
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ErrorKind::UnexpectedChar(_) => "HB0001",
+            ErrorKind::UnrecognizedToken => "HB0002",
+            ErrorKind::InvalidEscape(_) => "HB0003",
+            ErrorKind::InvalidUnicodeEscape(_) => "HB0004",
+            ErrorKind::IncompleteEscape => "HB0005",
+            ErrorKind::IncompleteUnicodeEscape => "HB0006",
+            ErrorKind::UnpairedSurrogate(_) => "HB0007",
+            ErrorKind::UnterminatedString => "HB0008",
+            ErrorKind::MultilineString => "HB0009",
+            ErrorKind::UnexpectedWhitespace => "HB0010",
+            ErrorKind::InvalidUtf8(_) => "HB0011",
+            ErrorKind::TruncatedUtf8(_) => "HB0012",
+            ErrorKind::EmptySource => "HB0013",
+            ErrorKind::ReadFailed(_, _) => "HB0014",
+            ErrorKind::RecordSeparatorInString => "HB0015",
+            ErrorKind::RecordSeparatorInComment => "HB0016",
+            ErrorKind::MissingHexDigits => "HB0017",
+            ErrorKind::InvalidCommentStart => "HB0018",
+            ErrorKind::UnexpectedComment => "HB0019",
+            ErrorKind::UnterminatedComment => "HB0020",
+            ErrorKind::StringTooLong(_) => "HB0021",
+            ErrorKind::NumberTooLong(_) => "HB0022",
+            ErrorKind::SourceTooLarge(_) => "HB0023",
+            ErrorKind::TooManyTokens(_) => "HB0024",
+            ErrorKind::CommentTooLong(_) => "HB0025",
+            ErrorKind::MissingColon(_) => "HB0026",
+            ErrorKind::MissingValue(_) => "HB0027",
+            ErrorKind::MissingObjectComma => "HB0028",
+            ErrorKind::MissingArrayComma => "HB0029",
+            ErrorKind::ExpectedKeyValuePair => "HB0030",
+            ErrorKind::MissingKeyValuePair => "HB0031",
+            ErrorKind::ExpectedArrayValue => "HB0032",
+            ErrorKind::UnexpectedObjectComma => "HB0033",
+            ErrorKind::UnexpectedArrayComma => "HB0034",
+            ErrorKind::TrailingComma(_) => "HB0035",
+            ErrorKind::UnexpectedEof => "HB0036",
+            ErrorKind::UnexpectedEndOfLine => "HB0037",
+            ErrorKind::UnexpectedEndOfRecord => "HB0038",
+            ErrorKind::LeadingZero => "HB0039",
+            ErrorKind::MissingFraction => "HB0040",
+            ErrorKind::MissingExponentDigits => "HB0041",
+            ErrorKind::MissingDigits(_) => "HB0042",
+            ErrorKind::HexOutOfRange(_) => "HB0043",
+            ErrorKind::NumberOutOfRange(_) => "HB0044",
+            ErrorKind::NotANumber(_) => "HB0045",
+            ErrorKind::MaxDepthExceeded => "HB0046",
+            ErrorKind::DuplicateKey(_, _, _) => "HB0047",
+            ErrorKind::MissingRecordSeparator => "HB0048",
+            ErrorKind::ExpectedValue => "HB0049",
+            ErrorKind::TrailingToken => "HB0050",
+            ErrorKind::Other(_) => "HB0051",
+            ErrorKind::Internal(_) => "HB0052",
+            ErrorKind::InvalidHexEscape(_) => "HB0053",
+            ErrorKind::IncompleteHexEscape => "HB0054",
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::UnexpectedChar(c) => {
+                write!(f, "Encountered an unexpected character '{}'", c)
+            }
+            ErrorKind::UnrecognizedToken => write!(f, "Unrecognized token"),
+            ErrorKind::InvalidEscape(c) => write!(f, "Unsupported escaped character '{}'. Only 'b', 'f', 'n', 'r', 't', 'u<4-digit hex>', '\\', or '\"' are allowed", c),
+            ErrorKind::InvalidUnicodeEscape(c) => write!(f, "The character '{}' is not valid in this context. Only hexadecimal digits (0-9, a-f, A-F) may follow \\u", c),
+            ErrorKind::IncompleteEscape => write!(f, "Incomplete escape sequence"),
+            ErrorKind::IncompleteUnicodeEscape => {
+                write!(f, "Expected 4 hexadecimal digits after \\u")
+            }
+            ErrorKind::InvalidHexEscape(c) => write!(f, "The character '{}' is not valid in this context. Only hexadecimal digits (0-9, a-f, A-F) may follow \\x", c),
+            ErrorKind::IncompleteHexEscape => write!(f, "Expected 2 hexadecimal digits after \\x"),
+            ErrorKind::UnpairedSurrogate(hex) => {
+                let half = match u32::from_str_radix(hex, 16) {
+                    Ok(0xD800..=0xDBFF) => "high",
+                    _ => "low",
+                };
+                write!(f, "Unpaired UTF-16 {} surrogate '\\u{}'", half, hex)
+            }
+            ErrorKind::UnterminatedString => {
+                write!(f, "Encountered end of stream inside a string")
+            }
+            ErrorKind::MultilineString => write!(f, "Multi-line strings are not allowed"),
+            ErrorKind::UnexpectedWhitespace => write!(f, "Whitespace is not allowed here"),
+            ErrorKind::InvalidUtf8(offset) => {
+                write!(f, "Invalid UTF-8 sequence at byte offset {}", offset)
+            }
+            ErrorKind::TruncatedUtf8(offset) => write!(
+                f,
+                "The source ends in the middle of a UTF-8 sequence at byte offset {}",
+                offset
+            ),
+            ErrorKind::EmptySource => write!(f, "Source cannot be empty"),
+            ErrorKind::ReadFailed(_, reason) => {
+                write!(f, "Failed to read from the source: {}", reason)
+            }
+            ErrorKind::RecordSeparatorInString => {
+                write!(f, "Encountered a record separator inside a string")
+            }
+            ErrorKind::RecordSeparatorInComment => {
+                write!(f, "Encountered a record separator inside a comment")
+            }
+            ErrorKind::MissingHexDigits => write!(f, "Expected hexadecimal digits after '0x'"),
+            ErrorKind::InvalidCommentStart => write!(f, "Expected '/' or '*' after '/'"),
+            ErrorKind::UnexpectedComment => write!(f, "A comment is not allowed here"),
+            ErrorKind::UnterminatedComment => {
+                write!(f, "Encountered end of stream inside a comment")
+            }
+            ErrorKind::StringTooLong(max) => {
+                write!(f, "The string exceeds the maximum length of {} bytes", max)
+            }
+            ErrorKind::NumberTooLong(max) => {
+                write!(f, "The number exceeds the maximum length of {} digits", max)
+            }
+            ErrorKind::SourceTooLarge(max) => {
+                write!(f, "The source exceeds the maximum size of {} bytes", max)
+            }
+            ErrorKind::TooManyTokens(max) => {
+                write!(f, "The source exceeds the maximum of {} tokens", max)
+            }
+            ErrorKind::CommentTooLong(max) => {
+                write!(f, "The comment exceeds the maximum length of {} bytes", max)
+            }
+            ErrorKind::MissingColon(key) => write!(f, "Expected ':' after the key '{}'", key),
+            ErrorKind::MissingValue(key) => {
+                write!(f, "A value is required after `\"{}\": `", key)
+            }
+            ErrorKind::MissingObjectComma => write!(f, "Expected comma or closing curly brace"),
+            ErrorKind::MissingArrayComma => write!(f, "Expected comma or closing bracket"),
+            ErrorKind::ExpectedKeyValuePair => {
+                write!(f, "Expected key-value pair or closing curly brace")
+            }
+            ErrorKind::MissingKeyValuePair => write!(f, "Expected another key-value pair"),
+            ErrorKind::ExpectedArrayValue => write!(f, "Expected value or closing bracket"),
+            ErrorKind::UnexpectedObjectComma => {
+                write!(f, "Syntax error. Encountered unexpected ',' in object")
+            }
+            ErrorKind::UnexpectedArrayComma => {
+                write!(f, "Syntax error. Encountered unexpected ',' in array")
+            }
+            ErrorKind::TrailingComma('}') => write!(
+                f,
+                "Expected another key-value pair after comma, but found a '}}'"
+            ),
+            ErrorKind::TrailingComma(closer) => write!(
+                f,
+                "Expected another value after comma, but found a '{}'",
+                closer
+            ),
+            ErrorKind::UnexpectedEof => {
+                write!(f, "Encountered end of stream, but more tokens expected")
+            }
+            ErrorKind::UnexpectedEndOfLine => {
+                write!(f, "Encountered end of line, but more tokens expected")
+            }
+            ErrorKind::UnexpectedEndOfRecord => {
+                write!(f, "Encountered end of record, but more tokens expected")
+            }
+            ErrorKind::LeadingZero => write!(f, "Leading zeros are not allowed"),
+            ErrorKind::MissingFraction => {
+                write!(f, "Syntax error. Expected fractional digits after '.'")
+            }
+            ErrorKind::MissingExponentDigits => {
+                write!(f, "Syntax error. Expected digits after exponent character")
+            }
+            ErrorKind::MissingDigits(sign) => {
+                write!(f, "Syntax error. Expected digits after '{}'", sign)
+            }
+            ErrorKind::HexOutOfRange(digits) => {
+                write!(f, "The hexadecimal number 0x{} is out of range", digits)
+            }
+            ErrorKind::NumberOutOfRange(lexeme) => {
+                write!(f, "The number {} is out of range", lexeme)
+            }
+            ErrorKind::NotANumber(lexeme) => write!(f, "'{}' is not a number", lexeme),
+            ErrorKind::MaxDepthExceeded => write!(f, "Maximum nesting depth exceeded"),
+            ErrorKind::DuplicateKey(key, line, offset) => write!(
+                f,
+                "Duplicate key '{}', first seen on line {} at offset {}",
+                key, line, offset
+            ),
+            ErrorKind::MissingRecordSeparator => write!(f, "Expected a record separator"),
+            ErrorKind::ExpectedValue => write!(f, "Expected a value"),
+            ErrorKind::TrailingToken => write!(f, "Unexpected token after document end"),
+            ErrorKind::Other(message) => write!(f, "{}", message),
+            ErrorKind::Internal(message) => write!(f, "Internal error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ErrorKind {}
+
+// everything that can go wrong, in one place. syntax errors show up in the tag stream
+// as a Tag::Error, since the tags before them are still good. Error::from_tag(...)
+// turns one of those into this
+#[derive(std::fmt::Debug)]
+pub enum Error {
+    // opening or reading the source failed
+    Io(io::Error),
+    // the source isn't valid UTF-8. the kind is InvalidUtf8 or TruncatedUtf8
+    Utf8(ErrorInfo, TokenInfo),
+    Syntax(ErrorInfo, TokenInfo),
+    // the source is fine, but doesn't fit the type it's being deserialized into. with
+    // positioned tags, it knows which tag it was about
+    Data(String, Option<TokenInfo>),
+    // the tags handed to a writer::TagWriter don't add up to a json document
+    Write(String),
+    // a select::select() expression that isn't valid, or not in the subset that works
+    // on a stream
+    Selector(String),
+    // the parse was stopped before it got to the end of the source
+    Cancelled,
+    // a bug, see ErrorKind::Internal
+    Internal(String),
+}
+
+impl Error {
+    // None for anything but a Tag::Error
+    pub fn from_tag(tag: &Tag) -> Option<Error> {
+        match tag {
+            Tag::Error(error, info) => Some(Error::from_syntax(error.clone(), info.clone())),
+            _ => None,
+        }
+    }
+
+    pub fn from_syntax(error: ErrorInfo, info: TokenInfo) -> Error {
+        match &error.kind {
+            ErrorKind::InvalidUtf8(_) | ErrorKind::TruncatedUtf8(_) => Error::Utf8(error, info),
+            ErrorKind::ReadFailed(kind, _) => Error::Io(io::Error::new(*kind, error.message)),
+            ErrorKind::Internal(message) => Error::Internal(message.clone()),
+            _ => Error::Syntax(error, info),
+        }
+    }
+
+    // only for the errors that came from the tag stream
+    pub fn kind(&self) -> Option<&ErrorKind> {
+        match self {
+            Error::Utf8(error, _) | Error::Syntax(error, _) => Some(&error.kind),
+            _ => None,
+        }
+    }
+
+    pub fn info(&self) -> Option<&TokenInfo> {
+        match self {
+            Error::Utf8(_, info) | Error::Syntax(_, info) => Some(info),
+            Error::Data(_, info) => info.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "Failed to read from the source: {}", e),
+            Error::Utf8(error, info) | Error::Syntax(error, info) => write!(
+                f,
+                "{} at line {}, column {}",
+                error.message, info.line_number, info.char_position
+            ),
+            Error::Data(message, Some(info)) => write!(
+                f,
+                "{} at line {}, column {}",
+                message, info.line_number, info.char_position
+            ),
+            Error::Data(message, None) => write!(f, "{}", message),
+            Error::Write(message) => write!(f, "Can't write the tags as json: {}", message),
+            Error::Selector(message) => write!(f, "Invalid selector: {}", message),
+            Error::Cancelled => write!(f, "The parse was cancelled"),
+            Error::Internal(message) => write!(f, "Internal error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Utf8(error, _) | Error::Syntax(error, _) => Some(&error.kind),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+#[derive(Clone, std::fmt::Debug)]
+pub struct RenderOptions {
+    // how many lines to show before and after the one the error is on
+    pub context_lines: usize,
+    // ANSI escape codes, for printing to a terminal
+    pub color: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            context_lines: 2,
+            color: false,
+        }
+    }
+}
+
+// shows an error the way rustc does: the message with its code, where it is, and the
+// line it's on with the offending span underlined, along with a few lines around it.
+// the TokenInfo is the one that came with the Tag::Error
+pub fn render_error(source: &str, error: &ErrorInfo, info: &TokenInfo) -> String {
+    render_error_with_options(source, error, info, &RenderOptions::default())
+}
+
+pub fn render_error_with_options(
+    source: &str,
+    error: &ErrorInfo,
+    info: &TokenInfo,
+    options: &RenderOptions,
+) -> String {
+    render_lines(SourceLines::new(source), error, info, options)
+}
+
+// the lines of a source along with their line numbers and where they start, counting
+// line breaks the same way the lexer does
+struct SourceLines<'a> {
+    rest: Option<&'a str>,
+    number: usize,
+    start: usize,
+}
+
+impl<'a> SourceLines<'a> {
+    fn new(source: &'a str) -> SourceLines<'a> {
+        SourceLines {
+            rest: Some(source),
+            number: 0,
+            start: 0,
+        }
+    }
+}
+
+impl<'a> Iterator for SourceLines<'a> {
+    type Item = (usize, usize, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = self.rest?;
+        // the last line is handed out even if it's empty, since the source might end
+        // in an error right there
+        let (line, break_len) = match rest.find(['\r', '\n']) {
+            Some(end) if rest[end..].starts_with("\r\n") => (&rest[..end], 2),
+            Some(end) => (&rest[..end], 1),
+            None => (rest, 0),
+        };
+        self.rest = match break_len {
+            0 => None,
+            _ => Some(&rest[line.len() + break_len..]),
+        };
+        self.number += 1;
+        let item = (self.number, self.start, line);
+        self.start += line.chars().count() + break_len;
+        Some(item)
+    }
+}
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+fn paint(text: &str, color: &str, options: &RenderOptions) -> String {
+    if options.color {
+        format!("{}{}{}", color, text, RESET)
+    } else {
+        text.to_string()
+    }
+}
+
+// does the actual rendering for render_error(...) and RecentLines. the lines come with
+// their line numbers and the position of their first character, in order. if the one
+// the error is on isn't among them, there's no snippet
+pub(crate) fn render_lines<'a>(
+    lines: impl Iterator<Item = (usize, usize, &'a str)>,
+    error: &ErrorInfo,
+    info: &TokenInfo,
+    options: &RenderOptions,
+) -> String {
+    let mut before = VecDeque::new();
+    let mut error_line = None;
+    let mut after = Vec::new();
+    for (number, start, text) in lines {
+        if error_line.is_some() {
+            if after.len() == options.context_lines {
+                break;
+            }
+            after.push((number, text));
+        } else if info.start >= start && info.start <= start + text.chars().count() {
+            error_line = Some((number, start, text));
+        } else {
+            before.push_back((number, text));
+            if before.len() > options.context_lines {
+                before.pop_front();
+            }
+        }
+    }
+
+    let mut rendered = format!(
+        "{}: {}\n",
+        paint(&format!("error[{}]", error.kind.code()), RED, options),
+        paint(&error.message, BOLD, options)
+    );
+    let (number, start, text) = match error_line {
+        Some(error_line) => error_line,
+        None => {
+            // the TokenInfo is all there is to go by
+            let column = info.char_position.saturating_sub(info.length).max(1);
+            rendered.push_str(&format!(
+                " {} line {}, column {}\n",
+                paint("-->", BLUE, options),
+                info.line_number,
+                column
+            ));
+            return rendered;
+        }
+    };
+    let column = info.start - start;
+    let width = after.last().map_or(number, |(last, _)| *last).to_string().len();
+    let gutter = |label: &str| paint(&format!("{:>width$} |", label, width = width), BLUE, options);
+    rendered.push_str(&format!(
+        "{}{} line {}, column {}\n",
+        " ".repeat(width),
+        paint("-->", BLUE, options),
+        number,
+        column + 1
+    ));
+    rendered.push_str(&format!("{}\n", gutter("")));
+    for (number, text) in before {
+        rendered.push_str(&format!("{} {}\n", gutter(&number.to_string()), text));
+    }
+    rendered.push_str(&format!("{} {}\n", gutter(&number.to_string()), text));
+    // tabs stay tabs so the carets line up
+    let indent: String = text
+        .chars()
+        .take(column)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    // a span that goes on past the end of the line only gets underlined up to there
+    let carets = info.length.min(text.chars().count().saturating_sub(column)).max(1);
+    rendered.push_str(&format!(
+        "{} {}{}\n",
+        gutter(""),
+        indent,
+        paint(&"^".repeat(carets), RED, options)
+    ));
+    for (number, text) in after {
+        rendered.push_str(&format!("{} {}\n", gutter(&number.to_string()), text));
+    }
+    rendered
+}
@@ -1,4 +1,13 @@
-use std::{sync::mpsc, thread};
+use crate::{
+    error::{ErrorKind, RenderOptions},
+    pipeline::CancelHandle,
+};
+use std::{
+    collections::VecDeque,
+    io::Read,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
 
 #[derive(PartialEq, std::fmt::Debug, Clone)]
 pub struct TokenInfo {
@@ -10,11 +19,23 @@ pub struct TokenInfo {
 
 #[derive(PartialEq, std::fmt::Debug, Clone)]
 pub struct ErrorInfo {
+    // the kind, spelled out
     pub message: String,
     pub fragment: Option<String>,
+    pub kind: ErrorKind,
 }
 
-#[derive(PartialEq, std::fmt::Debug)]
+impl From<ErrorKind> for ErrorInfo {
+    fn from(kind: ErrorKind) -> Self {
+        ErrorInfo {
+            message: kind.to_string(),
+            fragment: None,
+            kind,
+        }
+    }
+}
+
+#[derive(PartialEq, std::fmt::Debug, Clone)]
 pub enum MaybeToken {
     // todo: list all valid tokens
     FalseLiteral,
@@ -32,12 +53,145 @@ pub enum MaybeToken {
     RightBracket,
     Comma,
     Colon,
+    // only in Dialect::Json5. a hexadecimal integer, without the 0x
+    HexInteger(String),
+    // only in Dialect::Json5. an unquoted object key, or Infinity/NaN. true, false and
+    // null still come out as their literal tokens
+    Identifier(String),
+    // a // or /* */ comment, delimiters and all. only with LexerOptions::comments or in
+    // Dialect::Json5
+    Comment(String),
+    // RS (0x1E), which starts every record of an RFC 7464 JSON text sequence. only
+    // recognized once set_record_separators(true) was called
+    RecordSeparator,
     Error(ErrorInfo),
 }
 
+#[derive(Clone, Copy, Default, PartialEq, std::fmt::Debug)]
+pub enum Dialect {
+    // strict rfc 8259
+    #[default]
+    Json,
+    // https://json5.org, for hand-edited files. adds comments, trailing commas, single
+    // quoted strings, unquoted keys, hexadecimal numbers, leading/trailing decimal
+    // points, leading '+', Infinity/NaN, and line continuations and the extra escapes
+    // (\v, \0, \xHH and any other character but a digit) in strings
+    Json5,
+}
+
+// limits for sources that can't be trusted. they're all off by default. hitting one
+// produces an error and stops the lexer for good, even if it would otherwise resume
+// after errors
+#[derive(Clone, Default, std::fmt::Debug)]
+pub struct LexerOptions {
+    // in bytes, as written in the source and without the quotes
+    pub max_string_len: Option<usize>,
+    // the longest run of digits. a number's integer, fraction and exponent parts are
+    // lexed separately, so each of them gets checked on its own
+    pub max_number_len: Option<usize>,
+    pub max_total_bytes: Option<usize>,
+    // how many tokens the whole source may consist of
+    pub max_tokens: Option<usize>,
+    // the parser looks at this too, since some of the extra syntax is its business
+    pub dialect: Dialect,
+    // lex // and /* */ comments, as in JSONC. Dialect::Json5 always does. they count
+    // against max_string_len
+    pub comments: bool,
+    // keep this many of the most recent lines of the source around (see RecentLines),
+    // so errors can be shown in context even when the source came in over a channel.
+    // 0 turns it off
+    pub recent_lines: usize,
+}
+
+// the last few lines the lexer has seen, with the line the lexer is on as the last
+// one. once the lexer stops at an error, the rest of the line the error is on still
+// gets added, as far as the chunk it came in goes
+#[derive(Clone, Default, std::fmt::Debug)]
+pub struct RecentLines {
+    capacity: usize,
+    // the line number, the position of the line's first character in the source, and
+    // the line itself
+    lines: VecDeque<(usize, usize, String)>,
+    chars_seen: usize,
+    after_carriage_return: bool,
+    // set once the line the lexer stopped on is complete
+    complete: bool,
+}
+
+impl RecentLines {
+    pub fn new(capacity: usize) -> RecentLines {
+        let mut lines = VecDeque::new();
+        lines.push_back((1, 0, String::new()));
+        RecentLines {
+            capacity: capacity.max(1),
+            lines,
+            chars_seen: 0,
+            after_carriage_return: false,
+            complete: false,
+        }
+    }
+
+    pub fn lines(&self) -> impl Iterator<Item = (usize, usize, &str)> {
+        self.lines
+            .iter()
+            .map(|(number, start, text)| (*number, *start, text.as_str()))
+    }
+
+    // line breaks are counted the way the lexer counts them: CR, LF and CRLF all work
+    fn push(&mut self, source_char: char) {
+        let after_carriage_return = std::mem::replace(
+            &mut self.after_carriage_return,
+            is_carriage_return(source_char),
+        );
+        self.chars_seen += 1;
+        if is_linefeed(source_char) && after_carriage_return {
+            // the line after a CRLF starts a character later
+            if let Some(line) = self.lines.back_mut() {
+                line.1 = self.chars_seen;
+            }
+        } else if is_linefeed(source_char) || is_carriage_return(source_char) {
+            let number = self.lines.back().map_or(0, |line| line.0) + 1;
+            self.lines.push_back((number, self.chars_seen, String::new()));
+            while self.lines.len() > self.capacity {
+                self.lines.pop_front();
+            }
+        } else if let Some(line) = self.lines.back_mut() {
+            line.2.push(source_char);
+        }
+    }
+
+    // after the lexer has stopped. returns false once the line is complete
+    fn complete_line(&mut self, source_char: char) -> bool {
+        if is_linefeed(source_char) || is_carriage_return(source_char) {
+            self.complete = true;
+        }
+        if self.complete {
+            return false;
+        }
+        self.push(source_char);
+        true
+    }
+
+    // shows the error along with the lines around it, as far as they're still here.
+    // see error::render_error(...)
+    pub fn render_error(
+        &self,
+        error: &ErrorInfo,
+        info: &TokenInfo,
+        options: &RenderOptions,
+    ) -> String {
+        crate::error::render_lines(self.lines(), error, info, options)
+    }
+}
+
 pub type UTF8Source = mpsc::Receiver<String>;
+// raw bytes that don't have to line up with character boundaries. the lexer decodes
+// them as UTF-8 itself
+pub type ByteSource = mpsc::Receiver<Vec<u8>>;
 pub type TokenPair = (MaybeToken, TokenInfo);
 pub type TokenSink = mpsc::Sender<TokenPair>;
+// see lex_batches(...)
+pub type TokenBatchSink = mpsc::Sender<Vec<TokenPair>>;
 
 fn is_carriage_return(c: char) -> bool {
     c == '\r'
@@ -47,357 +201,1245 @@ fn is_linefeed(c: char) -> bool {
     c == '\n'
 }
 
-fn is_double_quote(c: char) -> bool {
-    c == '"'
-}
-
 fn is_backslash(c: char) -> bool {
     c == '\\'
 }
 
+fn is_record_separator(c: char) -> bool {
+    c == '\u{1e}'
+}
+
 fn is_digit(c: char) -> bool {
-    c == '0'
-        || c == '1'
-        || c == '2'
-        || c == '3'
-        || c == '4'
-        || c == '5'
-        || c == '6'
-        || c == '7'
-        || c == '8'
-        || c == '9'
-}
-
-pub fn lex(utf8_source: UTF8Source, lex_output_sink: TokenSink) -> () {
-    thread::spawn(move || {
-        // all this state is not very rust-esque but that's to figure out later.
-        // i can't just look ahead at the rest of the string and capture as much
-        // info as i want, because it might not exist yet in the stream. but a
-        // lot of this begs to be moved to the actual matching phase
-        let mut expected_to_match: &str = "";
-        let mut current_token: Option<MaybeToken> = None;
-        let mut current_token_info = TokenInfo {
-            line_number: 1,
-            char_position: 1,
-            start: 0,
-            length: 0,
-        };
-        let mut temp_string = String::from("");
-        let mut expect_possible_linefeed = false;
-        let mut expect_escaped_char = false;
-        let mut expected_hex_digits = 0;
-        let mut forbid_whitespace = false;
-        let mut last_was_whitespace = false;
-        for source_string in utf8_source {
-            for source_char in source_string.chars() {
-                if let Some(MaybeToken::StringLiteral(s)) = &mut current_token {
-                    if expected_hex_digits > 0 {
-                        // must take care here. rfc 8259 says they can be upper or lowercase
-                        // which implies that mixing is fine
-                        match source_char {
-                            'a' | 'A' | 'b' | 'B' | 'c' | 'C' | 'd' | 'D' | 'e' | 'E' | 'f'
-                            | 'F' | '0' | '1' | '2' | '3' | '4' | '5' | '6' | '7' | '8' | '9' => {
-                                expected_hex_digits -= 1;
-                                s.push(source_char);
-                                current_token_info.length += 1;
-                                current_token_info.char_position += 1;
-                            }
-                            _ => {
-                                lex_output_sink
-                                    .send((MaybeToken::Error(ErrorInfo {
-                                        message: format!("The character '{}' is not valid in this context. Only hexadecimal digits (0-9, a-f, A-F) may follow \\u", source_char),
-                                        fragment: None
-                                    }), current_token_info))
-                                .unwrap();
-                                return;
-                            }
-                        }
+    c.is_ascii_digit()
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || c == '$'
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+// where we are inside a comment
+#[derive(Clone, Copy, PartialEq)]
+enum Comment {
+    // just saw the '/' that opens it
+    Slash,
+    Line,
+    Block,
+    // a '*' inside a block comment, which might be about to close it
+    BlockStar,
+}
+
+// the lexer is a state machine that gets fed one chunk of the source at a time and
+// queues up the tokens it finds along the way. it doesn't care whether it runs on its
+// own thread (see lex(...)) or on the caller's (see Tokens)
+pub struct Lexer {
+    // all this state is not very rust-esque but that's to figure out later.
+    // i can't just look ahead at the rest of the string and capture as much
+    // info as i want, because it might not exist yet in the stream. but a
+    // lot of this begs to be moved to the actual matching phase
+    expected_to_match: &'static str,
+    current_token: Option<MaybeToken>,
+    current_token_info: TokenInfo,
+    // what's been matched of true, false or null so far, for the error if it turns
+    // out to be something else
+    temp_string: String,
+    expect_possible_linefeed: bool,
+    expect_escaped_char: bool,
+    expected_hex_digits: usize,
+    // the 'u' or (only in json5) 'x' the hex digits belong to
+    hex_escape: char,
+    // in json5, set right after a \0, which mustn't be followed by a digit
+    after_null_escape: bool,
+    // where the escape sequence being lexed starts in the string, so a broken one can
+    // be taken out again
+    escape_start: usize,
+    forbid_whitespace: bool,
+    last_was_whitespace: bool,
+    // the quote the current string started with. only ever a ' in Dialect::Json5
+    string_quote: char,
+    comment: Option<Comment>,
+    comment_text: String,
+    // whether the last token was part of a number and nothing came in between. in
+    // Dialect::Json5 that's what tells an exponent from the start of an identifier
+    last_token_numeric: bool,
+    // the start of a character that got split up between two byte chunks
+    pending_bytes: Vec<u8>,
+    // how many bytes of the source have been lexed so far. only used to point at
+    // invalid UTF-8
+    byte_offset: usize,
+    // same thing, but counting characters. lets us find our place again after
+    // skipping the rest of a line
+    chars_seen: usize,
+    resume_at_next_line: bool,
+    record_separators: bool,
+    recover: bool,
+    // set after an error when the lexer resumes at the next line or record
+    skipping: bool,
+    // set once an error was emitted or the end of the stream was handled. nothing
+    // after that point gets lexed
+    finished: bool,
+    options: LexerOptions,
+    tokens_seen: usize,
+    recent_lines: Option<RecentLines>,
+    output: VecDeque<TokenPair>,
+}
+
+impl Default for Lexer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lexer {
+    pub fn new() -> Lexer {
+        Self::with_options(LexerOptions::default())
+    }
+
+    pub fn with_options(options: LexerOptions) -> Lexer {
+        Lexer {
+            expected_to_match: "",
+            current_token: None,
+            current_token_info: TokenInfo {
+                line_number: 1,
+                char_position: 1,
+                start: 0,
+                length: 0,
+            },
+            temp_string: String::from(""),
+            expect_possible_linefeed: false,
+            expect_escaped_char: false,
+            expected_hex_digits: 0,
+            hex_escape: 'u',
+            after_null_escape: false,
+            escape_start: 0,
+            forbid_whitespace: false,
+            last_was_whitespace: false,
+            string_quote: '"',
+            comment: None,
+            comment_text: String::new(),
+            last_token_numeric: false,
+            pending_bytes: Vec::new(),
+            byte_offset: 0,
+            chars_seen: 0,
+            resume_at_next_line: false,
+            record_separators: false,
+            recover: false,
+            skipping: false,
+            finished: false,
+            recent_lines: match options.recent_lines {
+                0 => None,
+                capacity => Some(RecentLines::new(capacity)),
+            },
+            options,
+            tokens_seen: 0,
+            output: VecDeque::new(),
+        }
+    }
+
+    // takes the next token the lexer has found so far, if any
+    pub fn next_token(&mut self) -> Option<TokenPair> {
+        self.output.pop_front()
+    }
+
+    // true once the lexer won't produce any more tokens, either because it ran into
+    // an error or because finish() was called
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    // the lines the lexer has seen last, if LexerOptions::recent_lines asked for them
+    pub fn recent_lines(&self) -> Option<&RecentLines> {
+        self.recent_lines.as_ref()
+    }
+
+    pub fn push_str(&mut self, chunk: &str) {
+        for source_char in chunk.chars() {
+            if self.finished {
+                // the line the lexer stopped on is still worth showing in full
+                if let Some(recent_lines) = &mut self.recent_lines {
+                    if recent_lines.complete_line(source_char) {
                         continue;
                     }
-                    if expect_escaped_char {
-                        expect_escaped_char = false;
-                        match source_char {
-                            '"' | 'b' | '/' | '\\' | 'f' | 'n' | 'r' | 't' => {
-                                s.push(source_char);
-                                current_token_info.length += 1;
-                                current_token_info.char_position += 1;
-                            }
-                            'u' => {
-                                s.push(source_char);
-                                current_token_info.length += 1;
-                                current_token_info.char_position += 1;
-                                expected_hex_digits = 4;
-                            }
-                            _ => {
-                                lex_output_sink
-                                .send((MaybeToken::Error(ErrorInfo {
-                                    message: format!("Unsupported escaped character '{}'. Only 'b', 'f', 'n', 'r', 't', 'u<4-digit hex>', '\', or '\"' are allowed", source_char),
-                                    fragment: None
-                                }), current_token_info))
-                            .unwrap();
-                                return;
-                            }
+                }
+                return;
+            }
+            if let Some(max) = self.options.max_total_bytes {
+                if self.byte_offset + source_char.len_utf8() > max {
+                    self.fail_fatally(ErrorKind::SourceTooLarge(max));
+                    return;
+                }
+            }
+            if let Some(recent_lines) = &mut self.recent_lines {
+                recent_lines.push(source_char);
+            }
+            self.push_char(source_char);
+            self.byte_offset += source_char.len_utf8();
+            self.chars_seen += 1;
+        }
+    }
+
+    pub fn push_bytes(&mut self, chunk: &[u8]) {
+        let mut chunk = chunk;
+        // first finish off a character the last chunk ended in the middle of. it can
+        // only be missing up to 3 more bytes, so just go one byte at a time
+        let mut pending = std::mem::take(&mut self.pending_bytes);
+        while !pending.is_empty() && !chunk.is_empty() {
+            pending.push(chunk[0]);
+            match std::str::from_utf8(&pending) {
+                Ok(s) => {
+                    chunk = &chunk[1..];
+                    self.push_str(s);
+                    pending.clear();
+                }
+                Err(e) => match e.error_len() {
+                    None => chunk = &chunk[1..],
+                    Some(invalid_len) => {
+                        // the byte we just added doesn't belong to the sequence, so it
+                        // stays in the chunk
+                        self.skip_invalid_utf8(invalid_len);
+                        pending.clear();
+                    }
+                },
+            }
+        }
+        if !pending.is_empty() {
+            // still incomplete and we ran out of bytes
+            self.pending_bytes = pending;
+            return;
+        }
+        while !self.finished {
+            match std::str::from_utf8(chunk) {
+                Ok(s) => {
+                    self.push_str(s);
+                    return;
+                }
+                Err(e) => {
+                    let (valid, rest) = chunk.split_at(e.valid_up_to());
+                    self.push_str(std::str::from_utf8(valid).unwrap_or_default());
+                    match e.error_len() {
+                        Some(invalid_len) => {
+                            self.skip_invalid_utf8(invalid_len);
+                            chunk = &rest[invalid_len..];
+                        }
+                        None => {
+                            // the chunk just ends in the middle of a character
+                            self.pending_bytes = rest.to_vec();
+                            return;
                         }
-                        continue;
                     }
-                    if is_double_quote(source_char) {
-                        s.push(source_char);
-                        current_token_info.length += 1;
-                        current_token_info.char_position += 1;
-                        // we're finishing the string. send it
-                        lex_output_sink
-                            .send((current_token.take().unwrap(), current_token_info.clone()))
-                            .unwrap();
-                        // advance for the next token
-                        current_token_info.start += current_token_info.length;
-                    } else if is_linefeed(source_char) || is_carriage_return(source_char) {
-                        lex_output_sink
-                            .send((
-                                MaybeToken::Error(ErrorInfo {
-                                    message: String::from("Multi-line strings are not allowed"),
-                                    fragment: None,
-                                }),
-                                current_token_info,
-                            ))
-                            .unwrap();
-                        return;
-                    } else if is_backslash(source_char) {
-                        expect_escaped_char = true;
-                        s.push(source_char);
-                        current_token_info.length += 1;
-                        current_token_info.char_position += 1;
-                    } else {
-                        s.push(source_char);
-                        current_token_info.length += 1;
-                        current_token_info.char_position += 1;
+                }
+            }
+        }
+    }
+
+    // reports an invalid sequence and, if the lexer resumes after errors, steps over it.
+    // it takes up a single character position, like a replacement character would
+    fn skip_invalid_utf8(&mut self, invalid_len: usize) {
+        if let Some(recent_lines) = &mut self.recent_lines {
+            recent_lines.push(char::REPLACEMENT_CHARACTER);
+        }
+        self.fail_with(ErrorKind::InvalidUtf8(self.byte_offset));
+        if self.recover && !self.finished && !self.skipping {
+            if self.comment.is_some()
+                || matches!(self.current_token, Some(MaybeToken::StringLiteral(_)))
+            {
+                self.push_char(char::REPLACEMENT_CHARACTER);
+            } else {
+                // it ends whatever token came before it, like whitespace would
+                match &self.current_token {
+                    Some(MaybeToken::Identifier(_)) => self.send_identifier(),
+                    Some(MaybeToken::HexInteger(n)) if n.is_empty() => self.abandon_token(),
+                    Some(MaybeToken::Integer(_)) | Some(MaybeToken::HexInteger(_)) => {
+                        self.send_current_token()
                     }
-                    continue;
-                } else if let Some(MaybeToken::Integer(n)) = &mut current_token {
-                    if is_digit(source_char) {
-                        n.push(source_char);
-                        current_token_info.length += 1;
-                        current_token_info.char_position += 1;
-                        continue;
-                    } else {
-                        lex_output_sink
-                            .send((current_token.take().unwrap(), current_token_info.clone()))
-                            .unwrap();
-                        // start a new token
-                        current_token_info.start += current_token_info.length;
-                        current_token_info.length = 0;
+                    _ if !self.expected_to_match.is_empty() => self.abandon_token(),
+                    _ => {}
+                }
+                self.current_token_info.start += 1;
+                self.current_token_info.char_position += 1;
+            }
+        }
+        self.byte_offset += invalid_len;
+        self.chars_seen += 1;
+    }
+
+    // instead of stopping at the first error, skip the rest of the line it's on and
+    // carry on with the next one. that's how line-delimited formats like NDJSON recover
+    pub fn set_resume_at_next_line(&mut self, resume: bool) {
+        self.resume_at_next_line = resume;
+    }
+
+    // treat RS as the start of a new record, as in RFC 7464 JSON text sequences. a
+    // record separator can't be part of any token, so it also cuts off whatever token
+    // came before it, and after an error lexing picks up again at the next one
+    pub fn set_record_separators(&mut self, record_separators: bool) {
+        self.record_separators = record_separators;
+    }
+
+    // instead of stopping or skipping ahead after an error, patch things up as well as
+    // possible and keep lexing right where the error was: a broken escape sequence is
+    // left out, a string is closed at the end of its line or of the stream, a broken
+    // literal or a stray character is dropped. the limits in LexerOptions still stop
+    // the lexer for good
+    pub fn set_recover(&mut self, recover: bool) {
+        self.recover = recover;
+    }
+
+    // the line after an error starts here, so everything is back to normal
+    fn resume_on_new_line(&mut self, newline_char: char) {
+        self.skipping = false;
+        self.expect_possible_linefeed = is_carriage_return(newline_char);
+        self.current_token_info.line_number += 1;
+        self.current_token_info.char_position = 1;
+        self.current_token_info.start = self.chars_seen + 1;
+        self.current_token_info.length = 0;
+    }
+
+    // emits an error at the current position and either stops lexing or skips ahead
+    // to the next line. when recovering, it's up to the caller to repair the state
+    fn fail(&mut self, error: ErrorInfo) {
+        if self.finished || self.skipping {
+            return;
+        }
+        self.output.push_back((
+            MaybeToken::Error(error),
+            self.current_token_info.clone(),
+        ));
+        if self.recover {
+            return;
+        }
+        if self.resume_at_next_line || self.record_separators {
+            // forget about whatever token we were in the middle of
+            self.skipping = true;
+            self.current_token = None;
+            self.expected_to_match = "";
+            self.expect_escaped_char = false;
+            self.expected_hex_digits = 0;
+            self.after_null_escape = false;
+            self.forbid_whitespace = false;
+            self.last_was_whitespace = false;
+            self.comment = None;
+            self.comment_text.clear();
+        } else {
+            self.finished = true;
+        }
+    }
+
+    // for the limits in LexerOptions and a source that can't be read. unlike fail(...)
+    // this never resumes, and it's reported even while skipping a broken line
+    fn fail_fatally(&mut self, kind: ErrorKind) {
+        if self.finished {
+            return;
+        }
+        self.output.push_back((
+            MaybeToken::Error(ErrorInfo::from(kind)),
+            self.current_token_info.clone(),
+        ));
+        self.finished = true;
+    }
+
+    // when recovering, forgets about a broken token along with the characters it
+    // consisted of so far
+    fn abandon_token(&mut self) {
+        let consumed = self.current_token_info.length - self.expected_to_match.len();
+        self.current_token = None;
+        self.expected_to_match = "";
+        self.current_token_info.start += consumed;
+        self.current_token_info.length = 0;
+    }
+
+    // when recovering, closes a string that's missing its closing quote, as if it had
+    // been there
+    fn close_string(&mut self) {
+        let quote = self.string_quote;
+        if let Some(MaybeToken::StringLiteral(s)) = &mut self.current_token {
+            if self.expect_escaped_char || self.expected_hex_digits > 0 {
+                s.truncate(self.escape_start);
+                self.expect_escaped_char = false;
+                self.expected_hex_digits = 0;
+            }
+            s.push(quote);
+        }
+        self.send_current_token();
+    }
+
+    // when recovering, takes a broken escape sequence back out of the string
+    fn drop_escape(&mut self) {
+        if let Some(MaybeToken::StringLiteral(s)) = &mut self.current_token {
+            s.truncate(self.escape_start);
+        }
+        self.expect_escaped_char = false;
+        self.expected_hex_digits = 0;
+        self.after_null_escape = false;
+    }
+
+    // every token goes out through here so that they can be counted
+    fn push_token(&mut self, token: MaybeToken) {
+        if let Some(max) = self.options.max_tokens {
+            if self.tokens_seen >= max {
+                self.fail_fatally(ErrorKind::TooManyTokens(max));
+                return;
+            }
+        }
+        self.tokens_seen += 1;
+        self.last_token_numeric = matches!(
+            token,
+            MaybeToken::Integer(_) | MaybeToken::HexInteger(_) | MaybeToken::Dot
+        );
+        self.output
+            .push_back((token, self.current_token_info.clone()));
+    }
+
+    fn fail_with(&mut self, kind: ErrorKind) {
+        self.fail(ErrorInfo::from(kind));
+    }
+
+    // sends the token that was being built and moves the position past it
+    fn send_current_token(&mut self) {
+        if let Some(token) = self.current_token.take() {
+            self.push_token(token);
+        }
+        self.current_token_info.start += self.current_token_info.length;
+        self.current_token_info.length = 0;
+    }
+
+    // keeps track of the position while stepping over the rest of a broken line or
+    // record. line breaks still count so that the next record's positions are right
+    fn skip_char(&mut self, source_char: char) {
+        if is_linefeed(source_char) || is_carriage_return(source_char) {
+            if self.resume_at_next_line {
+                self.resume_on_new_line(source_char);
+                return;
+            }
+            if !(self.expect_possible_linefeed && is_linefeed(source_char)) {
+                self.current_token_info.line_number += 1;
+                self.current_token_info.char_position = 1;
+            }
+            self.expect_possible_linefeed = is_carriage_return(source_char);
+        } else {
+            self.expect_possible_linefeed = false;
+            self.current_token_info.char_position += 1;
+        }
+    }
+
+    // whatever was going on before the record separator is over. a number is complete,
+    // but anything else that's unfinished means the record got cut off
+    fn start_record(&mut self) {
+        if !self.skipping {
+            if let Some(MaybeToken::Integer(_) | MaybeToken::Identifier(_)) = &self.current_token {
+                self.send_current_token();
+            } else if let Some(MaybeToken::StringLiteral(_)) = &self.current_token {
+                self.fail_with(ErrorKind::RecordSeparatorInString);
+            } else if !self.expected_to_match.is_empty() {
+                let take_string = std::mem::take(&mut self.temp_string);
+                self.fail(ErrorInfo {
+                    message: ErrorKind::UnrecognizedToken.to_string(),
+                    fragment: Some(take_string),
+                    kind: ErrorKind::UnrecognizedToken,
+                });
+            } else if self.comment == Some(Comment::Line) {
+                self.end_comment();
+            } else if self.comment.is_some() {
+                self.fail_with(ErrorKind::RecordSeparatorInComment);
+            }
+        }
+        self.skipping = false;
+        self.current_token = None;
+        self.expected_to_match = "";
+        self.expect_possible_linefeed = false;
+        self.expect_escaped_char = false;
+        self.expected_hex_digits = 0;
+        self.after_null_escape = false;
+        self.forbid_whitespace = false;
+        self.last_was_whitespace = false;
+        self.comment = None;
+        self.comment_text.clear();
+        self.current_token_info.start = self.chars_seen;
+        self.current_token_info.length = 1;
+        self.current_token_info.char_position += 1;
+        self.push_token(MaybeToken::RecordSeparator);
+        self.current_token_info.start += 1;
+        self.current_token_info.length = 0;
+    }
+
+    // true, false and null are identifiers too, as far as lexing goes
+    fn send_identifier(&mut self) {
+        if let Some(MaybeToken::Identifier(name)) = &self.current_token {
+            let literal = match name.as_str() {
+                "true" => Some(MaybeToken::TrueLiteral),
+                "false" => Some(MaybeToken::FalseLiteral),
+                "null" => Some(MaybeToken::NullLiteral),
+                _ => None,
+            };
+            if literal.is_some() {
+                self.current_token = literal;
+            }
+        }
+        self.send_current_token();
+    }
+
+    // returns false once a line comment ends, since the line break still needs to be
+    // handled as usual
+    fn push_comment_char(&mut self, comment: Comment, source_char: char) -> bool {
+        let is_newline = is_linefeed(source_char) || is_carriage_return(source_char);
+        if comment == Comment::Line && is_newline {
+            self.end_comment();
+            return false;
+        }
+        // the LF of a CRLF doesn't start another line
+        let crlf = self.expect_possible_linefeed && is_linefeed(source_char);
+        self.expect_possible_linefeed = false;
+        let next = match comment {
+            Comment::Slash => match source_char {
+                '/' => Some(Comment::Line),
+                '*' => Some(Comment::Block),
+                _ => {
+                    self.fail_with(ErrorKind::InvalidCommentStart);
+                    if self.recover {
+                        self.comment = None;
+                        self.comment_text.clear();
+                        self.current_token_info.start += 1;
+                        self.current_token_info.length = 0;
+                        self.push_char(source_char);
                     }
+                    return true;
                 }
-                if expect_possible_linefeed {
-                    if is_linefeed(source_char) {
-                        // we're in a CRLF, just skip the char
-                        current_token_info.start += 1;
-                        if current_token_info.char_position != 1 {
-                            panic!("this shouldn't happen");
-                        }
-                        continue;
-                    } // else, ok for some reason there's only a CR. json doesn't seem to forbid that so..
-                    expect_possible_linefeed = false;
+            },
+            Comment::Line => Some(Comment::Line),
+            Comment::Block | Comment::BlockStar if source_char == '*' => Some(Comment::BlockStar),
+            Comment::BlockStar if source_char == '/' => None,
+            Comment::Block | Comment::BlockStar => Some(Comment::Block),
+        };
+        if let Some(max) = self.options.max_string_len {
+            if self.comment_text.len() + source_char.len_utf8() > max {
+                self.fail_fatally(ErrorKind::CommentTooLong(max));
+                return true;
+            }
+        }
+        self.comment_text.push(source_char);
+        self.current_token_info.length += 1;
+        if !is_newline {
+            self.current_token_info.char_position += 1;
+        } else if !crlf {
+            self.current_token_info.line_number += 1;
+            self.current_token_info.char_position = 1;
+            self.expect_possible_linefeed = is_carriage_return(source_char);
+        }
+        self.comment = next;
+        if next.is_none() {
+            self.end_comment();
+        }
+        true
+    }
+
+    fn end_comment(&mut self) {
+        self.comment = None;
+        let text = std::mem::take(&mut self.comment_text);
+        self.push_token(MaybeToken::Comment(text));
+        self.current_token_info.start += self.current_token_info.length;
+        self.current_token_info.length = 0;
+    }
+
+    // checks whether adding the character to the string or number being lexed would
+    // make it longer than allowed, and reports it if so
+    fn exceeds_length_limit(&mut self, source_char: char) -> bool {
+        let message = match &self.current_token {
+            Some(MaybeToken::StringLiteral(s)) => match self.options.max_string_len {
+                // the closing quote doesn't count, and s starts with the opening one
+                Some(max)
+                    if (source_char != self.string_quote || self.expect_escaped_char)
+                        && s.len() - 1 + source_char.len_utf8() > max =>
+                {
+                    ErrorKind::StringTooLong(max)
                 }
-                if expected_to_match.len() > 0 {
-                    if expected_to_match.chars().next().unwrap() == source_char {
-                        if expected_to_match.len() == 1 {
-                            expected_to_match = "";
-                            lex_output_sink
-                                .send((current_token.take().unwrap(), current_token_info.clone()))
-                                .unwrap();
-                            // start over
-                            current_token_info.start += current_token_info.length;
-                        } else {
-                            expected_to_match = &expected_to_match[1..];
-                        }
-                    } else {
-                        // not a match. looks like an error
-                        let mut take_string = String::from("");
-                        std::mem::swap(&mut temp_string, &mut take_string);
-                        lex_output_sink
-                            .send((
-                                MaybeToken::Error(ErrorInfo {
-                                    message: String::from("Unrecognized token"),
-                                    fragment: Some(take_string),
-                                }),
-                                current_token_info,
-                            ))
-                            .unwrap();
-                        return;
+                _ => return false,
+            },
+            Some(MaybeToken::Integer(n)) | Some(MaybeToken::HexInteger(n)) => match self
+                .options
+                .max_number_len
+            {
+                Some(max)
+                    if (is_digit(source_char)
+                        || (source_char.is_ascii_hexdigit()
+                            && matches!(self.current_token, Some(MaybeToken::HexInteger(_)))))
+                        && n.len() >= max =>
+                {
+                    ErrorKind::NumberTooLong(max)
+                }
+                _ => return false,
+            },
+            _ => return false,
+        };
+        self.fail_fatally(message);
+        true
+    }
+
+    fn push_char(&mut self, source_char: char) {
+        if self.record_separators && is_record_separator(source_char) {
+            self.start_record();
+            return;
+        }
+        if self.skipping {
+            self.skip_char(source_char);
+            return;
+        }
+        if self.exceeds_length_limit(source_char) {
+            return;
+        }
+        let json5 = self.options.dialect == Dialect::Json5;
+        if let Some(MaybeToken::StringLiteral(s)) = &mut self.current_token {
+            if self.expect_possible_linefeed {
+                // a line continuation that started with a CR
+                self.expect_possible_linefeed = false;
+                if is_linefeed(source_char) {
+                    s.push(source_char);
+                    self.current_token_info.length += 1;
+                    return;
+                }
+            }
+            if self.expected_hex_digits > 0 {
+                // must take care here. rfc 8259 says they can be upper or lowercase
+                // which implies that mixing is fine
+                if source_char.is_ascii_hexdigit() {
+                    self.expected_hex_digits -= 1;
+                    s.push(source_char);
+                    self.current_token_info.length += 1;
+                    self.current_token_info.char_position += 1;
+                } else if self.hex_escape == 'x' {
+                    self.fail_with(ErrorKind::InvalidHexEscape(source_char));
+                    if self.recover {
+                        self.drop_escape();
+                        self.push_char(source_char);
                     }
-                    current_token_info.char_position += 1;
-                    continue;
-                } else if let None = &current_token {
-                    let mut keep_going = true;
-                    if source_char == 'f' {
-                        expected_to_match = "alse";
-                        current_token = Some(MaybeToken::FalseLiteral);
-                        current_token_info.length = "false".len();
-                    } else if source_char == 't' {
-                        expected_to_match = "rue";
-                        current_token = Some(MaybeToken::TrueLiteral);
-                        current_token_info.length = "true".len();
-                    } else if source_char == 'n' {
-                        expected_to_match = "ull";
-                        current_token = Some(MaybeToken::NullLiteral);
-                        current_token_info.length = "null".len();
-                    } else {
-                        keep_going = false;
+                } else {
+                    self.fail_with(ErrorKind::InvalidUnicodeEscape(source_char));
+                    if self.recover {
+                        self.drop_escape();
+                        self.push_char(source_char);
                     }
-                    if keep_going {
-                        current_token_info.char_position += 1;
-                        continue;
+                }
+                return;
+            }
+            if self.after_null_escape {
+                self.after_null_escape = false;
+                // it'd look like an octal escape, which json5 doesn't have
+                if source_char.is_ascii_digit() {
+                    self.fail_with(ErrorKind::InvalidEscape(source_char));
+                    if self.recover {
+                        self.drop_escape();
+                        self.push_char(source_char);
                     }
+                    return;
                 }
-
-                let mut matched_simple_token: Option<MaybeToken> = None;
-                let mut found_whitespace = false;
+            }
+            if self.expect_escaped_char {
+                self.expect_escaped_char = false;
                 match source_char {
-                    '"' => {
-                        if current_token.is_some() {
-                            // send the old token first
-                            lex_output_sink
-                                .send((current_token.take().unwrap(), current_token_info.clone()))
-                                .unwrap();
-                            current_token_info.start += current_token_info.length;
-                        }
-                        // start a new string token
-                        current_token = Some(MaybeToken::StringLiteral(String::from("\"")));
-                        current_token_info.char_position += 1;
-                        current_token_info.length = 1;
-                        continue;
-                    }
-                    '\r' => {
-                        found_whitespace = true;
-                        expect_possible_linefeed = true;
-                        current_token_info.line_number += 1;
-                        current_token_info.char_position = 1;
-                        current_token_info.start += 1;
+                    '"' | 'b' | '/' | '\\' | 'f' | 'n' | 'r' | 't' => {
+                        s.push(source_char);
+                        self.current_token_info.length += 1;
+                        self.current_token_info.char_position += 1;
                     }
-                    '\n' => {
-                        found_whitespace = true;
-                        current_token_info.line_number += 1;
-                        current_token_info.char_position = 1;
-                        current_token_info.start += 1;
+                    'u' => {
+                        s.push(source_char);
+                        self.current_token_info.length += 1;
+                        self.current_token_info.char_position += 1;
+                        self.expected_hex_digits = 4;
+                        self.hex_escape = 'u';
                     }
-                    ' ' | '\t' => {
-                        found_whitespace = true;
-                        current_token_info.char_position += 1;
-                        current_token_info.start += 1;
+                    'x' if json5 => {
+                        s.push(source_char);
+                        self.current_token_info.length += 1;
+                        self.current_token_info.char_position += 1;
+                        self.expected_hex_digits = 2;
+                        self.hex_escape = 'x';
                     }
-                    '-' => {
-                        matched_simple_token = Some(MaybeToken::MinusSign);
-                        forbid_whitespace = true;
+                    '0' if json5 => {
+                        s.push(source_char);
+                        self.current_token_info.length += 1;
+                        self.current_token_info.char_position += 1;
+                        self.after_null_escape = true;
                     }
-                    '+' => {
-                        matched_simple_token = Some(MaybeToken::PlusSign);
-                        forbid_whitespace = true;
+                    '\n' | '\r' if json5 => {
+                        // a line continuation. the parser drops it along with the
+                        // backslash
+                        s.push(source_char);
+                        self.current_token_info.length += 1;
+                        self.current_token_info.line_number += 1;
+                        self.current_token_info.char_position = 1;
+                        self.expect_possible_linefeed = is_carriage_return(source_char);
                     }
-                    '{' => matched_simple_token = Some(MaybeToken::LeftCurly),
-                    '}' => matched_simple_token = Some(MaybeToken::RightCurly),
-                    '[' => matched_simple_token = Some(MaybeToken::LeftBracket),
-                    ']' => matched_simple_token = Some(MaybeToken::RightBracket),
-                    ',' => matched_simple_token = Some(MaybeToken::Comma),
-                    'e' | 'E' => {
-                        // something like 123 E123 is invalid
-                        if last_was_whitespace {
-                            found_whitespace = true; // force to fail
-                        }
-                        matched_simple_token = Some(MaybeToken::Exponent);
-                        forbid_whitespace = true;
+                    // \v, \' and any other character but a digit. the parser works out
+                    // what they stand for
+                    c if json5 && !c.is_ascii_digit() => {
+                        s.push(source_char);
+                        self.current_token_info.length += 1;
+                        self.current_token_info.char_position += 1;
                     }
-                    '.' => {
-                        // something like 456 .789 is invalid
-                        if last_was_whitespace {
-                            found_whitespace = true; // force to fail
+                    _ => {
+                        self.fail_with(ErrorKind::InvalidEscape(source_char));
+                        if self.recover {
+                            self.drop_escape();
+                            self.push_char(source_char);
                         }
-                        matched_simple_token = Some(MaybeToken::Dot);
-                        forbid_whitespace = true;
                     }
-                    ':' => matched_simple_token = Some(MaybeToken::Colon),
-                    _ => matched_simple_token = None,
                 }
+                return;
+            }
+            if source_char == self.string_quote {
+                s.push(source_char);
+                self.current_token_info.length += 1;
+                self.current_token_info.char_position += 1;
+                // we're finishing the string. send it
+                self.send_current_token();
+            } else if is_linefeed(source_char) || is_carriage_return(source_char) {
+                self.fail_with(ErrorKind::MultilineString);
+                if self.recover {
+                    self.close_string();
+                    self.push_char(source_char);
+                } else if self.skipping {
+                    // the line the error is on already ended right here
+                    self.skip_char(source_char);
+                }
+            } else {
+                if is_backslash(source_char) {
+                    self.expect_escaped_char = true;
+                    self.escape_start = s.len();
+                }
+                s.push(source_char);
+                self.current_token_info.length += 1;
+                self.current_token_info.char_position += 1;
+            }
+            return;
+        } else if let Some(MaybeToken::Integer(n)) = &mut self.current_token {
+            if is_digit(source_char) {
+                n.push(source_char);
+                self.current_token_info.length += 1;
+                self.current_token_info.char_position += 1;
+                return;
+            } else if json5 && n == "0" && (source_char == 'x' || source_char == 'X') {
+                self.current_token = Some(MaybeToken::HexInteger(String::new()));
+                self.current_token_info.length += 1;
+                self.current_token_info.char_position += 1;
+                return;
+            } else {
+                // the number is finished. we're on a new token
+                self.send_current_token();
+            }
+        } else if let Some(MaybeToken::HexInteger(n)) = &mut self.current_token {
+            if source_char.is_ascii_hexdigit() {
+                n.push(source_char);
+                self.current_token_info.length += 1;
+                self.current_token_info.char_position += 1;
+                return;
+            } else if n.is_empty() {
+                self.fail_with(ErrorKind::MissingHexDigits);
+                if self.recover {
+                    self.abandon_token();
+                    self.push_char(source_char);
+                }
+                return;
+            } else {
+                self.send_current_token();
+            }
+        } else if let Some(MaybeToken::Identifier(name)) = &mut self.current_token {
+            if is_identifier_char(source_char) {
+                name.push(source_char);
+                self.current_token_info.length += 1;
+                self.current_token_info.char_position += 1;
+                return;
+            } else {
+                self.send_identifier();
+            }
+        }
+        if let Some(comment) = self.comment {
+            if self.push_comment_char(comment, source_char) {
+                return;
+            }
+        }
+        if self.expect_possible_linefeed {
+            self.expect_possible_linefeed = false;
+            if is_linefeed(source_char) {
+                // we're in a CRLF, just skip the char. any LF after this one starts
+                // another line
+                self.current_token_info.start += 1;
+                return;
+            } // else, ok for some reason there's only a CR. json doesn't seem to forbid that so..
+        }
+        if let Some(expected_char) = self.expected_to_match.chars().next() {
+            if expected_char == source_char {
+                if self.expected_to_match.len() == 1 {
+                    self.expected_to_match = "";
+                    self.temp_string.clear();
+                    // start over
+                    self.send_current_token();
+                } else {
+                    self.expected_to_match = &self.expected_to_match[1..];
+                    self.temp_string.push(source_char);
+                }
+            } else {
+                // not a match. looks like an error
+                self.temp_string.push(source_char);
+                let take_string = std::mem::take(&mut self.temp_string);
+                self.fail(ErrorInfo {
+                    message: ErrorKind::UnrecognizedToken.to_string(),
+                    fragment: Some(take_string),
+                    kind: ErrorKind::UnrecognizedToken,
+                });
+                if self.recover {
+                    self.abandon_token();
+                    self.push_char(source_char);
+                }
+                return;
+            }
+            self.current_token_info.char_position += 1;
+            return;
+        } else if json5
+            && is_identifier_start(source_char)
+            && !(self.last_token_numeric && (source_char == 'e' || source_char == 'E'))
+        {
+            self.current_token = Some(MaybeToken::Identifier(source_char.to_string()));
+            self.current_token_info.length = 1;
+            self.current_token_info.char_position += 1;
+            self.forbid_whitespace = false;
+            self.last_was_whitespace = false;
+            return;
+        } else if self.current_token.is_none() {
+            let literal = match source_char {
+                'f' => Some((MaybeToken::FalseLiteral, "false")),
+                't' => Some((MaybeToken::TrueLiteral, "true")),
+                'n' => Some((MaybeToken::NullLiteral, "null")),
+                _ => None,
+            };
+            if let Some((token, text)) = literal {
+                self.expected_to_match = &text[1..];
+                self.temp_string.clear();
+                self.temp_string.push(source_char);
+                self.current_token = Some(token);
+                self.current_token_info.length = text.len();
+                self.current_token_info.char_position += 1;
+                return;
+            }
+        }
 
-                if found_whitespace {
-                    if forbid_whitespace {
-                        lex_output_sink
-                            .send((
-                                MaybeToken::Error(ErrorInfo {
-                                    message: String::from("Whitespace is not allowed here"),
-                                    fragment: None,
-                                }),
-                                current_token_info,
-                            ))
-                            .unwrap();
-                        return;
-                    } else {
-                        last_was_whitespace = true;
-                        continue;
+        let mut found_whitespace = false;
+        let matched_simple_token = match source_char {
+            '"' | '\'' if source_char == '"' || json5 => {
+                if self.current_token.is_some() {
+                    // send the old token first
+                    self.send_current_token();
+                }
+                // start a new string token
+                self.current_token = Some(MaybeToken::StringLiteral(source_char.to_string()));
+                self.string_quote = source_char;
+                self.current_token_info.char_position += 1;
+                self.current_token_info.length = 1;
+                return;
+            }
+            '/' if json5 || self.options.comments => {
+                if self.forbid_whitespace {
+                    self.fail_with(ErrorKind::UnexpectedComment);
+                    if self.recover {
+                        self.forbid_whitespace = false;
+                        self.push_char(source_char);
                     }
+                    return;
                 }
-
-                if let Some(mst) = matched_simple_token {
-                    current_token = None; // make sure this is unset. we don't need it
-                    current_token_info.length = 1;
-                    current_token_info.char_position += 1;
-                    lex_output_sink
-                        .send((mst, current_token_info.clone()))
-                        .unwrap();
-                    current_token_info.start += 1; // advance and
-                    current_token_info.length = 0; // reset
-                    continue;
+                self.comment = Some(Comment::Slash);
+                self.comment_text.push(source_char);
+                self.last_was_whitespace = true;
+                self.last_token_numeric = false;
+                self.current_token_info.char_position += 1;
+                self.current_token_info.length = 1;
+                return;
+            }
+            '\r' => {
+                found_whitespace = true;
+                self.expect_possible_linefeed = true;
+                self.current_token_info.line_number += 1;
+                self.current_token_info.char_position = 1;
+                self.current_token_info.start += 1;
+                None
+            }
+            '\n' => {
+                found_whitespace = true;
+                self.current_token_info.line_number += 1;
+                self.current_token_info.char_position = 1;
+                self.current_token_info.start += 1;
+                None
+            }
+            ' ' | '\t' => {
+                found_whitespace = true;
+                self.current_token_info.char_position += 1;
+                self.current_token_info.start += 1;
+                None
+            }
+            '-' => {
+                self.forbid_whitespace = true;
+                Some(MaybeToken::MinusSign)
+            }
+            '+' => {
+                self.forbid_whitespace = true;
+                Some(MaybeToken::PlusSign)
+            }
+            '{' | '}' | '[' | ']' | ',' | ':' => {
+                // none of these can be part of a number, so whatever came before is
+                // over. the parser reports a dangling sign if there was one
+                self.forbid_whitespace = false;
+                match source_char {
+                    '{' => Some(MaybeToken::LeftCurly),
+                    '}' => Some(MaybeToken::RightCurly),
+                    '[' => Some(MaybeToken::LeftBracket),
+                    ']' => Some(MaybeToken::RightBracket),
+                    ',' => Some(MaybeToken::Comma),
+                    _ => Some(MaybeToken::Colon),
                 }
+            }
+            'e' | 'E' => {
+                // something like 123 E123 is invalid
+                if self.last_was_whitespace {
+                    found_whitespace = true; // force to fail
+                }
+                self.forbid_whitespace = true;
+                Some(MaybeToken::Exponent)
+            }
+            '.' => {
+                // something like 456 .789 is invalid. json5 allows .789 on its own,
+                // so the parser has to check that a fraction belongs to the number
+                if self.last_was_whitespace && !json5 {
+                    found_whitespace = true; // force to fail
+                }
+                // and so is 456. 789, except that in json5 456. is a number on its own
+                self.forbid_whitespace = !json5;
+                Some(MaybeToken::Dot)
+            }
+            _ => None,
+        };
 
-                if is_digit(source_char) {
-                    last_was_whitespace = false;
-                    forbid_whitespace = false;
-                    // looks like a number...
-                    current_token_info.char_position += 1;
-                    current_token_info.length += 1;
-                    let mut tmp_str = String::new();
-                    tmp_str.push(source_char);
-                    current_token = Some(MaybeToken::Integer(tmp_str));
-                } else {
-                    lex_output_sink
-                        .send((
-                            MaybeToken::Error(ErrorInfo {
-                                message: format!(
-                                    "Encountered an unexpected character '{}'",
-                                    source_char
-                                ),
-                                fragment: None,
-                            }),
-                            current_token_info,
-                        ))
-                        .unwrap();
-                    return;
+        if found_whitespace {
+            if self.forbid_whitespace {
+                self.fail_with(ErrorKind::UnexpectedWhitespace);
+                if self.recover {
+                    self.forbid_whitespace = false;
+                    self.last_was_whitespace = true;
+                    self.last_token_numeric = false;
+                    if matched_simple_token.is_some() {
+                        // the e or . that came after the whitespace gets dropped
+                        self.current_token_info.start += 1;
+                        self.current_token_info.char_position += 1;
+                    }
+                } else if self.skipping
+                    && self.resume_at_next_line
+                    && self.current_token_info.char_position == 1
+                {
+                    // the offending whitespace was a line break, so we're already on
+                    // the next line
+                    self.skipping = false;
+                    self.current_token_info.start = self.chars_seen + 1;
                 }
+            } else {
+                self.last_was_whitespace = true;
+                self.last_token_numeric = false;
+            }
+            return;
+        }
+
+        if let Some(mst) = matched_simple_token {
+            self.current_token = None; // make sure this is unset. we don't need it
+            self.current_token_info.length = 1;
+            self.current_token_info.char_position += 1;
+            self.push_token(mst);
+            self.current_token_info.start += 1; // advance and
+            self.current_token_info.length = 0; // reset
+            return;
+        }
+
+        if is_digit(source_char) {
+            self.last_was_whitespace = false;
+            self.forbid_whitespace = false;
+            // looks like a number...
+            self.current_token_info.char_position += 1;
+            self.current_token_info.length += 1;
+            let mut tmp_str = String::new();
+            tmp_str.push(source_char);
+            self.current_token = Some(MaybeToken::Integer(tmp_str));
+        } else {
+            self.fail_with(ErrorKind::UnexpectedChar(source_char));
+            if self.recover {
+                self.current_token_info.start += 1;
+                self.current_token_info.char_position += 1;
             }
         }
+    }
 
+    // called once the source has run dry
+    pub fn finish(&mut self) {
+        if self.finished {
+            return;
+        }
+        if self.skipping {
+            // the error has been reported already
+            self.finished = true;
+            return;
+        }
+        if !self.pending_bytes.is_empty() {
+            self.fail_with(ErrorKind::TruncatedUtf8(self.byte_offset));
+            return;
+        }
         // the stream might have ended while we were constructing certain tokens.
         // if it's a quoted string, that's an error
         // but on the off-chance it's a digit, ok, send it
-        if let Some(MaybeToken::Integer(_)) = &current_token {
-            // the number is finished. we're on a new token
-            lex_output_sink
-                .send((current_token.take().unwrap(), current_token_info.clone()))
-                .unwrap();
-            return;
+        if let Some(MaybeToken::Integer(_)) = &self.current_token {
+            self.send_current_token();
+        } else if let Some(MaybeToken::HexInteger(n)) = &self.current_token {
+            if n.is_empty() {
+                self.fail_with(ErrorKind::MissingHexDigits);
+            } else {
+                self.send_current_token();
+            }
+        } else if let Some(MaybeToken::Identifier(_)) = &self.current_token {
+            self.send_identifier();
+        } else if self.comment == Some(Comment::Line) {
+            self.end_comment();
+        } else if self.comment.is_some() {
+            self.fail_with(ErrorKind::UnterminatedComment);
+        } else if let Some(MaybeToken::StringLiteral(_)) = &self.current_token {
+            self.fail_with(ErrorKind::UnterminatedString);
+            if self.recover {
+                self.close_string();
+            }
+        } else if !self.expected_to_match.is_empty() {
+            let take_string = std::mem::take(&mut self.temp_string);
+            self.fail(ErrorInfo {
+                message: ErrorKind::UnrecognizedToken.to_string(),
+                fragment: Some(take_string),
+                kind: ErrorKind::UnrecognizedToken,
+            });
+        } else if self.current_token_info.line_number == 1
+            && self.current_token_info.char_position == 1
+        {
+            // todo: fix the error-handling. i don't like this...
+            self.fail_with(ErrorKind::EmptySource);
         }
+        self.finished = true;
+    }
+}
 
-        if current_token_info.line_number == 1 && current_token_info.char_position == 1 {
-            // todo: fix the error-handling. i don't like this...
-            lex_output_sink
-                .send((
-                    MaybeToken::Error(ErrorInfo {
-                        message: String::from("Source cannot be empty"),
-                        fragment: None,
-                    }),
-                    current_token_info,
-                ))
-                .unwrap();
-            return;
+// anything the lexer can be fed from. feed(...) pushes the next chunk into the lexer
+// and returns false once there's nothing left
+pub trait ChunkSource {
+    fn feed(&mut self, lexer: &mut Lexer) -> bool;
+
+    // called once before a parse that can be cancelled. a source that might sit there
+    // waiting for its next chunk has to make sure that cancel() gets feed(...) out of
+    // that. the parse stops before the next feed(...) anyway
+    fn wake_on_cancel(&mut self, _cancel: &CancelHandle) {}
+}
+
+// the channel is swapped for one that a thread forwards its chunks to, and that
+// cancel() can put an empty chunk into. that chunk does nothing but wake the lexer up.
+// the forwarding thread keeps one chunk at a time, so a bounded channel stays bounded.
+// it exits once the producer sends something else or goes away
+fn forward_until_cancelled<T: Default + Send + 'static>(
+    rx: &mut mpsc::Receiver<T>,
+    cancel: &CancelHandle,
+) {
+    let (tx, forwarded) = mpsc::sync_channel(1);
+    let source = std::mem::replace(rx, forwarded);
+    // the waker mustn't keep the channel open after the source is over
+    let waker = Arc::new(Mutex::new(Some(tx.clone())));
+    let wake = waker.clone();
+    cancel.on_cancel(move || {
+        if let Ok(tx) = wake.lock() {
+            if let Some(tx) = tx.as_ref() {
+                let _ = tx.try_send(T::default());
+            }
+        }
+    });
+    thread::spawn(move || {
+        for chunk in source.iter() {
+            if tx.send(chunk).is_err() {
+                break;
+            }
+        }
+        if let Ok(mut waker) = waker.lock() {
+            waker.take();
+        }
+    });
+}
+
+impl ChunkSource for UTF8Source {
+    fn feed(&mut self, lexer: &mut Lexer) -> bool {
+        match self.recv() {
+            Ok(chunk) => {
+                lexer.push_str(&chunk);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn wake_on_cancel(&mut self, cancel: &CancelHandle) {
+        forward_until_cancelled(self, cancel);
+    }
+}
+
+impl ChunkSource for ByteSource {
+    fn feed(&mut self, lexer: &mut Lexer) -> bool {
+        match self.recv() {
+            Ok(chunk) => {
+                lexer.push_bytes(&chunk);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn wake_on_cancel(&mut self, cancel: &CancelHandle) {
+        forward_until_cancelled(self, cancel);
+    }
+}
+
+// how much of the source the lexer gets at a time, from a reader or from something
+// that's already in memory
+const CHUNK_SIZE: usize = 8 * 1024;
+
+// a string that's already fully in memory still goes in a chunk at a time, so the
+// first tags come out before the rest of it has been lexed
+impl ChunkSource for &str {
+    fn feed(&mut self, lexer: &mut Lexer) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        let mut end = self.len().min(CHUNK_SIZE);
+        while !self.is_char_boundary(end) {
+            end += 1;
+        }
+        lexer.push_str(&self[..end]);
+        *self = &self[end..];
+        true
+    }
+}
+
+// push_bytes(...) takes care of a character that's split between two chunks
+impl ChunkSource for &[u8] {
+    fn feed(&mut self, lexer: &mut Lexer) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        let end = self.len().min(CHUNK_SIZE);
+        lexer.push_bytes(&self[..end]);
+        *self = &self[end..];
+        true
+    }
+}
+
+pub struct ReadSource<R> {
+    reader: R,
+}
+
+impl<R: Read> ReadSource<R> {
+    pub fn new(reader: R) -> ReadSource<R> {
+        ReadSource { reader }
+    }
+}
+
+impl<R: Read> ChunkSource for ReadSource<R> {
+    fn feed(&mut self, lexer: &mut Lexer) -> bool {
+        let mut buffer = [0; CHUNK_SIZE];
+        match self.reader.read(&mut buffer) {
+            Ok(0) => false,
+            Ok(len) => {
+                lexer.push_bytes(&buffer[..len]);
+                true
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => true,
+            Err(e) => {
+                // there's no picking up after this one, whatever mode the lexer is in
+                lexer.fail_fatally(ErrorKind::ReadFailed(e.kind(), e.to_string()));
+                false
+            }
+        }
+    }
+}
+
+// pulls tokens out of a lexer on the caller's thread, feeding it from the source
+// whenever it runs out
+pub struct Tokens<S> {
+    lexer: Lexer,
+    source: S,
+}
+
+impl<S: ChunkSource> Tokens<S> {
+    pub fn new(source: S) -> Tokens<S> {
+        Self::with_lexer(Lexer::new(), source)
+    }
+
+    // for when the lexer needs some setting up first
+    pub fn with_lexer(lexer: Lexer, source: S) -> Tokens<S> {
+        Tokens { lexer, source }
+    }
+
+    pub fn lexer(&self) -> &Lexer {
+        &self.lexer
+    }
+}
+
+impl<S: ChunkSource> Iterator for Tokens<S> {
+    type Item = TokenPair;
+
+    fn next(&mut self) -> Option<TokenPair> {
+        loop {
+            if let Some(token_pair) = self.lexer.next_token() {
+                return Some(token_pair);
+            }
+            if self.lexer.is_finished() {
+                return None;
+            }
+            if !self.source.feed(&mut self.lexer) {
+                self.lexer.finish();
+            }
+        }
+    }
+}
+
+pub fn lex(utf8_source: UTF8Source, lex_output_sink: TokenSink) {
+    lex_with_options(utf8_source, lex_output_sink, LexerOptions::default());
+}
+
+pub fn lex_with_options(utf8_source: UTF8Source, lex_output_sink: TokenSink, options: LexerOptions) {
+    thread::spawn(move || {
+        for token_pair in Tokens::with_lexer(Lexer::with_options(options), utf8_source) {
+            if lex_output_sink.send(token_pair).is_err() {
+                // nobody is listening anymore
+                return;
+            }
+        }
+    });
+}
+
+// like lex_with_options(...), but every chunk of the source goes out as a single batch
+// of tokens. that's a lot less channel traffic when the tokens are small. see
+// parser::parse_batches(...) for the other end
+pub fn lex_batches(utf8_source: UTF8Source, lex_output_sink: TokenBatchSink, options: LexerOptions) {
+    thread::spawn(move || {
+        let mut source = utf8_source;
+        let mut lexer = Lexer::with_options(options);
+        loop {
+            let batch: Vec<TokenPair> = std::iter::from_fn(|| lexer.next_token()).collect();
+            if !batch.is_empty() && lex_output_sink.send(batch).is_err() {
+                // nobody is listening anymore
+                return;
+            }
+            if lexer.is_finished() {
+                return;
+            }
+            if !source.feed(&mut lexer) {
+                lexer.finish();
+            }
         }
     });
 }
@@ -1,34 +1,172 @@
+#[cfg(feature = "serde")]
+pub mod de;
+#[cfg(feature = "dom")]
+pub mod dom;
+pub mod error;
 pub mod lexer;
 pub mod parser;
+pub mod path;
+pub mod pipeline;
+#[cfg(feature = "serde")]
+pub mod ser;
+pub mod select;
+pub mod writer;
 
+pub use error::Error;
+
+// lexing and parsing share a single background thread here. use parse_str(...) or
+// parse_reader(...) to do all the work on the calling thread instead
 pub fn parse(utf8_source: lexer::UTF8Source) -> parser::TagSink {
-    let (token_tx, token_rx) = std::sync::mpsc::channel();
-    let (tag_tx, tag_rx) = std::sync::mpsc::channel();
-    lexer::lex(utf8_source, token_tx);
-    parser::parse(token_rx, tag_tx);
-    tag_rx
-}
-
-use std::error::Error;
-
-// note: while lexing and parsing always happen in the background, parse_file(...)
-// blocks until the entire file has been passed to the lexer so that it can return
-// a success/failure flag for reading the contents of the file
-pub fn parse_file(file_path: &str) -> Result<parser::TagSink,Box<dyn Error>> {
-    use std::{fs::File, io::BufReader, io::Read};
-    let f = File::open(&file_path)?;
-    let (string_tx, string_rx) = std::sync::mpsc::channel();
-    let mut reader = BufReader::new(f);
-    let tag_sink = parse(string_rx);
-    loop {
-        const SOME_ARBITRARY_CAPACITY: usize = 8 * 1024;
-        let mut line: Vec<u8> = vec![0; SOME_ARBITRARY_CAPACITY];
-        let len = reader.read(&mut line)?;
-        if len > 0 {
-            string_tx.send(String::from_utf8(line)?)?;
-        } else {
-            break;
-        }
-    }
-    Ok(tag_sink)
+    parse_with_options(utf8_source, parser::ParserOptions::default())
+}
+
+// same as parse(...), but the chunks can be split up anywhere, even in the middle
+// of a character
+pub fn parse_bytes(byte_source: lexer::ByteSource) -> parser::TagSink {
+    parse_with_options(byte_source, parser::ParserOptions::default())
+}
+
+// like parse(...) and parse_bytes(...), but every tag comes with the span of the
+// source it was parsed from. works with any kind of source
+pub fn parse_positioned<S: lexer::ChunkSource + Send + 'static>(
+    source: S,
+) -> parser::PositionedTagSink {
+    parse_positioned_with_options(source, parser::ParserOptions::default())
+}
+
+// takes ParserOptions or pipeline::PipelineOptions, like parse_cancellable(...)
+pub fn parse_positioned_with_options<S: lexer::ChunkSource + Send + 'static>(
+    source: S,
+    options: impl Into<pipeline::PipelineOptions>,
+) -> parser::PositionedTagSink {
+    pipeline::spawn(source, options.into(), |tag, info| (tag, info.clone())).0
+}
+
+// like parse_positioned(...), but every tag comes with its path in the document as a
+// JSON Pointer (RFC 6901) instead, like /config/2. path::with_paths(...) does the same
+// for tags from anywhere else
+pub fn parse_with_paths<S: lexer::ChunkSource + Send + 'static>(source: S) -> path::PathTagSink {
+    parse_with_paths_with_options(source, parser::ParserOptions::default())
+}
+
+// takes ParserOptions or pipeline::PipelineOptions, like parse_positioned_with_options(...)
+pub fn parse_with_paths_with_options<S: lexer::ChunkSource + Send + 'static>(
+    source: S,
+    options: impl Into<pipeline::PipelineOptions>,
+) -> path::PathTagSink {
+    let mut tracker = path::PathTracker::new();
+    pipeline::spawn(source, options.into(), move |tag, _| {
+        tracker.update(&tag);
+        (tag, tracker.shared_pointer())
+    })
+    .0
+}
+
+// takes any kind of source, just like parse_positioned(...)
+pub fn parse_with_options<S: lexer::ChunkSource + Send + 'static>(
+    source: S,
+    options: parser::ParserOptions,
+) -> parser::TagSink {
+    parse_cancellable(source, options).0
+}
+
+// like parse_with_options(...), but the parse can be stopped early and waited for. see
+// pipeline::ParseHandle
+// pipeline::PipelineOptions sets how many tags can pile up in the sink, plain
+// ParserOptions get the default
+pub fn parse_cancellable<S: lexer::ChunkSource + Send + 'static>(
+    source: S,
+    options: impl Into<pipeline::PipelineOptions>,
+) -> (parser::TagSink, pipeline::ParseHandle) {
+    pipeline::spawn(source, options.into(), |tag, _| tag)
+}
+
+// parses JSON Lines/NDJSON, i.e. one document per line. a Tag::EndDocument follows
+// every line that isn't blank, and a broken line doesn't stop the lines after it
+// from being parsed
+pub fn parse_ndjson<S: lexer::ChunkSource + Send + 'static>(source: S) -> parser::TagSink {
+    parse_with_options(
+        source,
+        parser::ParserOptions {
+            documents: parser::DocumentMode::Lines,
+            ..Default::default()
+        },
+    )
+}
+
+// parses an RFC 7464 JSON text sequence (application/json-seq), i.e. one document per
+// record, each starting with an RS character. a Tag::EndDocument follows every record
+// and a broken or truncated record doesn't stop the ones after it from being parsed
+pub fn parse_json_seq<S: lexer::ChunkSource + Send + 'static>(source: S) -> parser::TagSink {
+    parse_with_options(
+        source,
+        parser::ParserOptions {
+            documents: parser::DocumentMode::Records,
+            ..Default::default()
+        },
+    )
+}
+
+// for hand-edited files in the json5 dialect: comments, trailing commas, unquoted keys
+// and so on. see lexer::Dialect for the full list
+pub fn parse_json5<S: lexer::ChunkSource + Send + 'static>(source: S) -> parser::TagSink {
+    parse_with_options(
+        source,
+        parser::ParserOptions {
+            lexer: lexer::LexerOptions {
+                dialect: lexer::Dialect::Json5,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+}
+
+// for JSONC files like VS Code's settings.json: strict json plus comments and trailing
+// commas. the comments are dropped, see ParserOptions::emit_comments to keep them
+pub fn parse_jsonc<S: lexer::ChunkSource + Send + 'static>(source: S) -> parser::TagSink {
+    parse_with_options(
+        source,
+        parser::ParserOptions {
+            lexer: lexer::LexerOptions {
+                comments: true,
+                ..Default::default()
+            },
+            trailing_commas: true,
+            ..Default::default()
+        },
+    )
+}
+
+pub fn parse_str(source: &str) -> parser::PullParser<&str> {
+    parser::PullParser::new(source)
+}
+
+pub fn parse_reader<R: std::io::Read>(reader: R) -> parser::PullParser<lexer::ReadSource<R>> {
+    parser::PullParser::new(lexer::ReadSource::new(reader))
+}
+
+// the file is read on the background thread, a chunk at a time as the parser gets to
+// it, so memory use doesn't grow with the size of the file. only opening it can fail
+// here, a failed read shows up in the tag stream
+pub fn parse_file(file_path: &str) -> Result<parser::TagSink, Error> {
+    parse_file_cancellable(file_path).map(|(tag_sink, _)| tag_sink)
+}
+
+// like parse_file(...), but every tag comes with its span, like parse_positioned(...).
+// handy for de::iter_array(...) over a big file, whose errors then say where they are
+pub fn parse_file_positioned(file_path: &str) -> Result<parser::PositionedTagSink, Error> {
+    let f = std::fs::File::open(file_path)?;
+    let source = lexer::ReadSource::new(std::io::BufReader::new(f));
+    Ok(parse_positioned(source))
+}
+
+// like parse_file(...), along with a handle to stop the parse early or wait for it. a
+// failed read also shows up in what ParseHandle::join() returns
+pub fn parse_file_cancellable(
+    file_path: &str,
+) -> Result<(parser::TagSink, pipeline::ParseHandle), Error> {
+    let f = std::fs::File::open(file_path)?;
+    let source = lexer::ReadSource::new(std::io::BufReader::new(f));
+    Ok(parse_cancellable(source, parser::ParserOptions::default()))
 }
@@ -1,5 +1,10 @@
-use crate::lexer;
-use std::{sync::mpsc, thread};
+use crate::{error::ErrorKind, lexer, pipeline};
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::TryFrom,
+    sync::mpsc,
+    thread,
+};
 
 pub type TagInfo = lexer::TokenInfo;
 pub type ErrorInfo = lexer::ErrorInfo;
@@ -12,611 +17,1830 @@ pub enum Tag {
     EndArray,
     ObjectKey(String),
     StringLiteral(String),
+    // the number in json notation. the only exceptions are json5's Infinity and NaN,
+    // which json can't express. they come through as Infinity, -Infinity, NaN and
+    // -NaN, and writer::TagWriter refuses to write them
     Number(String),
     TrueLiteral,
     FalseLiteral,
     NullLiteral,
     Error(ErrorInfo, TagInfo),
+    // instead of Number in NumberMode::Typed. the string is the number as written in
+    // the source (with the exponent always as 'E' and hex always as 0x), for anyone who
+    // needs every digit
+    TypedNumber(Number, String),
+    // only with ParserOptions::emit_comments. the whole comment, including the // or
+    // the /* */ around it
+    Comment(String),
+    // only in the multi-document modes. sent after every document, even broken ones,
+    // along with its index (starting at 0)
+    EndDocument(usize),
+    // only with ParserOptions::recover. stands in for a value that was missing or
+    // couldn't be parsed, right after the Error about it, so that arrays and objects
+    // still come out whole. a tree with one of these in it is only partially there
+    Recovered,
 }
 
 pub type TokenPair = lexer::TokenPair;
 pub type TokenSource = mpsc::Receiver<TokenPair>;
 pub type TokenIter<'a> = mpsc::Iter<'a, TokenPair>;
+// what lexer::lex_batches(...) sends
+pub type TokenBatchSource = mpsc::Receiver<Vec<TokenPair>>;
 pub type TagSource = mpsc::Sender<Tag>;
-// convenience types for outside users
-pub type TagSink = mpsc::Receiver<Tag>;
+// convenience types for outside users. the background parses in lib.rs send their tags
+// in batches, but a TagSink hands them out one at a time
+pub type TagSink = pipeline::BatchReceiver<Tag>;
 pub type TagVec = Vec<Tag>;
+// a tag along with the span of the source it came from. for numbers that's the whole
+// number even though the lexer hands it over in pieces
+pub type PositionedTag = (Tag, TagInfo);
+pub type PositionedTagSource = mpsc::Sender<PositionedTag>;
+pub type PositionedTagSink = pipeline::BatchReceiver<PositionedTag>;
 
-fn strip_string_quotes(s: &str) -> &str {
-    let len = s.len();
-    if len == 2 {
-        ""
-    } else if len > 2 {
-        &s[1..len - 1]
-    } else {
-        panic!("Something went dreadfully wrong in the lexer");
-    }
-}
-
-fn require_object_kv_pair(
-    key: (&String, &lexer::TokenInfo),
-    rest: &mut TokenIter,
-    sink: &TagSource,
-) -> (bool,Option<TokenPair>) {
-    let stripped_name = strip_string_quotes(key.0).to_string();
-    sink.send(Tag::ObjectKey(stripped_name.clone())).unwrap();
-    let next_token = require_next_token(rest, sink, &key.1);
-    if next_token.is_ok() {
-        let next_token = next_token.unwrap();
-        if let (lexer::MaybeToken::Colon, info) = &next_token {
-            let hopefully_value_token = require_next_token(rest, sink, info);
-            if hopefully_value_token.is_ok() {
-                let result = try_value(&hopefully_value_token.unwrap(), rest, sink);
-                if result.is_err() {
-                    sink.send(Tag::Error(
-                        ErrorInfo {
-                            message: format!("A value is required after `\"{}\": `", stripped_name),
-                            fragment: None,
-                        },
-                        next_token.1,
-                    ))
-                    .unwrap();
-                    return (false,None);
-                }
-                let result = result.unwrap();
-                if !result.0 {
-                    sink.send(Tag::Error(
-                        ErrorInfo {
-                            message: format!("A value is required after `\"{}\": `", stripped_name),
-                            fragment: None,
-                        },
-                        next_token.1,
-                    ))
-                    .unwrap();
-                }
-                return result;
+#[derive(Clone, PartialEq, std::fmt::Debug)]
+pub enum Number {
+    Int(i64),
+    // only for integers too big for an i64
+    UInt(u64),
+    Float(f64),
+    // too big for any of the above, see OutOfRange::KeepAsString
+    BigNumber(String),
+}
+
+// what to do with numbers that don't fit into an i64, u64 or (finite) f64
+#[derive(Clone, Copy, PartialEq, std::fmt::Debug)]
+pub enum OutOfRange {
+    Error,
+    // clamp to the closest value that fits
+    Saturate,
+    KeepAsString,
+}
+
+impl Number {
+    // works on the text of a Tag::Number, so it's also handy when not using typed mode.
+    // json5 numbers as they're written (0x1F, +.5, 5. and so on) work too
+    pub fn from_lexeme(lexeme: &str, out_of_range: OutOfRange) -> Result<Number, ErrorKind> {
+        let negative = lexeme.starts_with('-');
+        let unsigned = lexeme.strip_prefix(['-', '+']).unwrap_or(lexeme);
+        if let Some(digits) = unsigned.strip_prefix("0x").or_else(|| unsigned.strip_prefix("0X")) {
+            let decimal = hex_to_decimal(lexeme).ok_or_else(|| ErrorKind::NotANumber(lexeme.to_string()))?;
+            return match Number::from_lexeme(&decimal, OutOfRange::Error) {
+                Ok(number) => Ok(number),
+                Err(_) => match out_of_range {
+                    OutOfRange::Error => Err(ErrorKind::HexOutOfRange(digits.to_string())),
+                    OutOfRange::Saturate => Number::from_lexeme(&decimal, out_of_range),
+                    OutOfRange::KeepAsString => Ok(Number::BigNumber(lexeme.to_string())),
+                },
+            };
+        }
+        if matches!(unsigned, "Infinity" | "NaN") {
+            // json5 has these. they're floats like any other, not out of range
+            return lexeme
+                .parse::<f64>()
+                .map(Number::Float)
+                .map_err(|_| ErrorKind::NotANumber(lexeme.to_string()));
+        }
+        let saturated = if lexeme.contains(['.', 'e', 'E']) {
+            match lexeme.parse::<f64>() {
+                Ok(value) if value.is_finite() => return Ok(Number::Float(value)),
+                Ok(_) => Number::Float(if negative { f64::MIN } else { f64::MAX }),
+                Err(_) => return Err(ErrorKind::NotANumber(lexeme.to_string())),
+            }
+        } else if let Ok(value) = lexeme.parse::<i64>() {
+            return Ok(Number::Int(value));
+        } else if let Ok(value) = lexeme.parse::<u64>() {
+            return Ok(Number::UInt(value));
+        } else if !unsigned.is_empty() && unsigned.chars().all(|c| c.is_ascii_digit()) {
+            if negative {
+                Number::Int(i64::MIN)
+            } else {
+                Number::UInt(u64::MAX)
             }
         } else {
-            sink.send(Tag::Error(
-                ErrorInfo {
-                    message: format!("Expected ':' after the key '{}'", stripped_name),
-                    fragment: None,
-                },
-                next_token.1,
-            ))
-            .unwrap();
+            return Err(ErrorKind::NotANumber(lexeme.to_string()));
+        };
+        match out_of_range {
+            OutOfRange::Error => Err(ErrorKind::NumberOutOfRange(lexeme.to_string())),
+            OutOfRange::Saturate => Ok(saturated),
+            OutOfRange::KeepAsString => Ok(Number::BigNumber(lexeme.to_string())),
+        }
+    }
+
+    // these only succeed if the conversion doesn't lose anything
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Number::Int(value) => Some(*value),
+            Number::UInt(value) => i64::try_from(*value).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Number::Int(value) => u64::try_from(*value).ok(),
+            Number::UInt(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            // casting back saturates, and i64::MAX/u64::MAX round up to a power of two,
+            // so anything that ends up there can't have made the round trip
+            Number::Int(value) => {
+                let float = *value as f64;
+                (float < i64::MAX as f64 && float as i64 == *value).then_some(float)
+            }
+            Number::UInt(value) => {
+                let float = *value as f64;
+                (float < u64::MAX as f64 && float as u64 == *value).then_some(float)
+            }
+            Number::Float(value) => Some(*value),
+            _ => None,
         }
     }
-    (false,None)
 }
 
-fn try_object(start_token: &TokenPair, rest: &mut TokenIter, sink: &TagSource) -> Result<bool, ()> {
-    if let lexer::MaybeToken::LeftCurly = &start_token.0 {
-        if sink.send(Tag::BeginObject).is_err() {
+// a json5 hexadecimal integer like -0x1F in decimal, however many digits it has. None
+// if it isn't one
+pub(crate) fn hex_to_decimal(lexeme: &str) -> Option<String> {
+    let (sign, unsigned) = match lexeme.strip_prefix('-') {
+        Some(unsigned) => ("-", unsigned),
+        None => ("", lexeme.strip_prefix('+').unwrap_or(lexeme)),
+    };
+    let digits = unsigned.strip_prefix("0x").or_else(|| unsigned.strip_prefix("0X"))?;
+    if digits.is_empty() {
+        return None;
+    }
+    // base 10^9, the lowest part first
+    let mut parts: Vec<u64> = vec![0];
+    for digit in digits.chars() {
+        let mut carry = u64::from(digit.to_digit(16)?);
+        for part in parts.iter_mut() {
+            let value = *part * 16 + carry;
+            *part = value % 1_000_000_000;
+            carry = value / 1_000_000_000;
+        }
+        if carry > 0 {
+            parts.push(carry);
+        }
+    }
+    let mut decimal = format!("{}{}", sign, parts[parts.len() - 1]);
+    for part in parts.iter().rev().skip(1) {
+        decimal.push_str(&format!("{:09}", part));
+    }
+    Some(decimal)
+}
+
+#[derive(Clone, Copy, PartialEq, std::fmt::Debug)]
+pub enum NumberMode {
+    // Tag::Number with the text of the number
+    Text,
+    // Tag::TypedNumber
+    Typed(OutOfRange),
+}
+
+// what to do when an object has the same key more than once. rfc 8259 leaves that up
+// to the implementation, so different parsers tend to disagree
+#[derive(Clone, Copy, PartialEq, std::fmt::Debug)]
+pub enum DuplicateKeys {
+    // pass every key along as is
+    Allow,
+    Error,
+    // the winning policies leave out the other occurrences of the key entirely,
+    // including their values
+    FirstWins,
+    // note that this holds back the tags of every object until it's closed, since a
+    // later key can still replace an earlier one
+    LastWins,
+}
+
+#[derive(Clone, Copy, PartialEq, std::fmt::Debug)]
+pub enum DocumentMode {
+    // exactly one root value
+    Single,
+    // any number of root values, separated by whitespace (or nothing at all)
+    Multiple,
+    // one root value per line, as in JSON Lines/NDJSON. blank lines are skipped
+    Lines,
+    // one root value per record, as in RFC 7464 JSON text sequences. every record
+    // starts with an RS character, and several of them in a row count as one
+    Records,
+}
+
+#[derive(Clone, std::fmt::Debug)]
+pub struct ParserOptions {
+    // hand out strings and object keys exactly as they're written in the source (minus
+    // the quotes) instead of decoding their escape sequences. useful for tools that
+    // need to round-trip a document byte for byte
+    pub raw_strings: bool,
+    // report anything but whitespace after the root value as an error instead of
+    // quietly ignoring it
+    pub strict: bool,
+    // in the multi-document modes an error only ends the document it's in. parsing
+    // picks up again at the next line, or at the next record in Records mode
+    pub documents: DocumentMode,
+    // how deeply arrays and objects may be nested. the parser keeps track of every one
+    // of them, so without a limit a hostile [[[[[[... could eat up all the memory
+    pub max_depth: usize,
+    // limits for the lexer. only used by the entry points that run the lexer
+    // themselves; a TokenSource was already lexed with whatever it was lexed with.
+    // the dialect is the exception, the parser needs to know about it either way
+    pub lexer: lexer::LexerOptions,
+    pub numbers: NumberMode,
+    pub duplicate_keys: DuplicateKeys,
+    // send comments along as Tag::Comment instead of dropping them. the lexer only
+    // produces them with LexerOptions::comments or in Dialect::Json5
+    pub emit_comments: bool,
+    // allow a comma after the last element of an array or object, as JSONC does.
+    // Dialect::Json5 always does
+    pub trailing_commas: bool,
+    // let numbers with leading zeros like 01 through as they are. neither json nor
+    // json5 allows them
+    pub leading_zeros: bool,
+    // keep going after a syntax error so that every error in the source gets reported
+    // in one pass. the parser patches things up as well as it can (by inserting a
+    // missing comma or colon, or by skipping ahead to the next ',' or closing bracket)
+    // and sends a Tag::Recovered wherever a value got lost. errors that follow from
+    // one that was just reported are left out
+    pub recover: bool,
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        ParserOptions {
+            raw_strings: false,
+            strict: true,
+            documents: DocumentMode::Single,
+            max_depth: 128,
+            lexer: lexer::LexerOptions::default(),
+            numbers: NumberMode::Text,
+            duplicate_keys: DuplicateKeys::Allow,
+            emit_comments: false,
+            trailing_commas: false,
+            leading_zeros: false,
+            recover: false,
+        }
+    }
+}
+
+// everything the parser needs to get at. the tokens come either from a
+// channel or straight from a lexer running on the same thread, and the tags either go
+// out over a channel or into the pull parser's queue. every tag is sent along with the
+// span it covers in the source. the sink returns Err once nobody is listening anymore,
+// which aborts the parse the same way a syntax error does
+struct Context<'a> {
+    tokens: &'a mut dyn Iterator<Item = TokenPair>,
+    sink: &'a mut dyn FnMut(Tag, &TagInfo) -> Result<(), ()>,
+    options: &'a ParserOptions,
+    state: &'a mut DocumentState,
+}
+
+// every key along with the tags of its value, starting with the ObjectKey itself
+type ObjectMembers = Vec<(String, Vec<PositionedTag>)>;
+
+// drops every member whose key shows up again later on
+fn surviving_members(members: ObjectMembers) -> impl Iterator<Item = PositionedTag> {
+    let mut last_index = HashMap::new();
+    for (index, (key, _)) in members.iter().enumerate() {
+        last_index.insert(key.clone(), index);
+    }
+    members
+        .into_iter()
+        .enumerate()
+        .filter(move |(index, (key, _))| last_index[key] == *index)
+        .flat_map(|(_, (_, tags))| tags)
+}
+
+// what has to survive from one document to the next
+#[derive(Default)]
+struct DocumentState {
+    // a token that was already pulled from the source but belongs to the next document
+    pending: Option<TokenPair>,
+    // in Lines mode, the line the current document is on. tokens past it belong to
+    // the next document
+    line: Option<usize>,
+    // in Records mode, set while inside a record. the next record separator belongs
+    // to the next document
+    in_record: bool,
+    // how many arrays and objects the parser is currently inside of
+    depth: usize,
+    // set while parsing the value of a key that lost out under DuplicateKeys::FirstWins.
+    // nothing but errors gets through
+    muted: usize,
+    // with DuplicateKeys::LastWins, the members of every object that's still open. the
+    // innermost one is last
+    objects: Vec<ObjectMembers>,
+    // the arrays and objects the parser is inside of. the innermost one is last
+    frames: Vec<Frame>,
+    // how many documents have been parsed so far
+    count: usize,
+    // where the last tag came from, so EndDocument can point right after it
+    last_info: Option<TagInfo>,
+    error_line: Option<usize>,
+    // with ParserOptions::recover, the last token pulled from the source. whenever an
+    // error comes up, that's the one it was found at
+    last_token: Option<TokenPair>,
+    // with ParserOptions::recover, set after an error until the next value comes along.
+    // any errors in between are most likely caused by the first one
+    quiet: bool,
+    // set once there's nothing left to parse or nobody is listening anymore
+    finished: bool,
+}
+
+impl Context<'_> {
+    fn send(&mut self, tag: Tag, info: &TagInfo) -> Result<(), ()> {
+        if !matches!(
+            tag,
+            Tag::Error(_, _)
+                | Tag::Comment(_)
+                | Tag::Recovered
+                | Tag::EndObject
+                | Tag::EndArray
+                | Tag::EndDocument(_)
+        ) {
+            // a value got through since the last error
+            self.state.quiet = false;
+        }
+        if let Tag::Error(_, _) = &tag {
+            self.state.error_line = Some(info.line_number);
+            self.state.quiet = true;
+            if self.options.recover {
+                // the objects that are held back aren't over yet. the error goes out
+                // ahead of them, since it'd get lost if its member loses out
+                return self.send_now(tag, info);
+            }
+            // the document ends here, so whatever objects were held back go out as
+            // far as they got
+            for members in std::mem::take(&mut self.state.objects) {
+                for (held_tag, held_info) in surviving_members(members) {
+                    self.send(held_tag, &held_info)?;
+                }
+            }
+        } else if self.state.muted > 0 {
+            return Ok(());
+        } else if let Some(members) = self.state.objects.last_mut() {
+            if let Tag::ObjectKey(key) = &tag {
+                members.push((key.clone(), Vec::new()));
+            }
+            // anything that comes before an object's first key (like a comment) goes
+            // wherever the object's BeginObject went
+            let held_tags = self
+                .state
+                .objects
+                .iter_mut()
+                .rev()
+                .find_map(|members| members.last_mut());
+            if let Some((_, tags)) = held_tags {
+                tags.push((tag, info.clone()));
+                return Ok(());
+            }
+        }
+        self.send_now(tag, info)
+    }
+
+    // past the holdback for DuplicateKeys::LastWins, straight to the sink
+    fn send_now(&mut self, tag: Tag, info: &TagInfo) -> Result<(), ()> {
+        self.state.last_info = Some(info.clone());
+        let result = (self.sink)(tag, info);
+        if result.is_err() {
+            self.state.finished = true;
+        }
+        result
+    }
+
+    fn json5(&self) -> bool {
+        self.options.lexer.dialect == lexer::Dialect::Json5
+    }
+
+    fn trailing_commas(&self) -> bool {
+        self.options.trailing_commas || self.json5()
+    }
+
+    fn recovering(&self) -> bool {
+        self.options.recover && !self.state.finished
+    }
+
+    // reports a syntax error and hands back an Err so callers can just return it
+    fn error<T>(&mut self, kind: ErrorKind, info: &TagInfo) -> Result<T, ()> {
+        if self.options.recover && self.state.quiet {
             return Err(());
         }
+        self.send(Tag::Error(ErrorInfo::from(kind), info.clone()), info)?;
+        Err(())
+    }
 
-        if let Ok(mut next_token) = require_next_token(rest, sink, &start_token.1) {
-            let mut require_comma_or_curly = false;
-            let mut require_kv_pair = false;
-            loop {
-                match &next_token {
-                    // possible kv pair
-                    (lexer::MaybeToken::StringLiteral(key_name), key_name_info) => {
-                        let res = require_object_kv_pair((&key_name, &key_name_info), rest, sink);
-                        if !res.0 {
-                            return Err(());
-                        }
-                        require_comma_or_curly = true;
-                        require_kv_pair = false;
-                        if res.1.is_some() {
-                            next_token = res.1.unwrap();
-                            // we have a lookahead left over, so bypass the next token code
-                            // after match
-                            continue;
-                        }
-                    }
-                    (lexer::MaybeToken::Comma, info) => {
-                        if require_comma_or_curly {
-                            require_kv_pair = true;
-                            require_comma_or_curly = false;
-                        } else {
-                            sink.send(Tag::Error(
-                                ErrorInfo {
-                                    message: String::from(
-                                        "Syntax error. Encountered unexpected ',' in object",
-                                    ),
-                                    fragment: None,
-                                },
-                                info.clone(),
-                            ))
-                            .unwrap();
-                            return Err(());
-                        }
+    // like error(...), except that when recovering the caller gets to carry on
+    fn report(&mut self, kind: ErrorKind, info: &TagInfo) -> Result<(), ()> {
+        let result = self.error(kind, info);
+        if self.recovering() {
+            Ok(())
+        } else {
+            result
+        }
+    }
+}
+
+// right after the last tag that was sent
+fn end_of_last_tag(state: &DocumentState) -> TagInfo {
+    match &state.last_info {
+        Some(info) => TagInfo {
+            line_number: info.line_number,
+            char_position: info.char_position,
+            start: info.start + info.length,
+            length: 0,
+        },
+        None => TagInfo {
+            line_number: 1,
+            char_position: 1,
+            start: 0,
+            length: 0,
+        },
+    }
+}
+
+// an empty span right where the token starts
+fn start_of(info: &TagInfo) -> TagInfo {
+    TagInfo {
+        line_number: info.line_number,
+        char_position: info.char_position.saturating_sub(info.length).max(1),
+        start: info.start,
+        length: 0,
+    }
+}
+
+// when recovering, skips whatever is left of a broken value or key-value pair: up to
+// the next ',' or closing bracket that isn't nested any deeper, starting at the token
+// the error was found at. that token is handed back like a lookahead. None means the
+// source (or the line or record) ended first
+fn skip_rest(ctx: &mut Context) -> Result<Option<TokenPair>, ()> {
+    if !ctx.recovering() {
+        return Err(());
+    }
+    let mut next = ctx.state.last_token.take();
+    let mut depth = 0;
+    loop {
+        let pair = match next.take() {
+            Some(pair) => pair,
+            None => match try_next_token(ctx)? {
+                Some(pair) => pair,
+                None => return Ok(None),
+            },
+        };
+        match pair.0 {
+            lexer::MaybeToken::LeftBracket | lexer::MaybeToken::LeftCurly => depth += 1,
+            lexer::MaybeToken::RightBracket | lexer::MaybeToken::RightCurly if depth > 0 => {
+                depth -= 1
+            }
+            lexer::MaybeToken::Comma
+            | lexer::MaybeToken::RightBracket
+            | lexer::MaybeToken::RightCurly => return Ok(Some(pair)),
+            _ => {}
+        }
+    }
+}
+
+// when recovering, puts a Tag::Recovered where a value couldn't be parsed and skips
+// whatever is left of it. see skip_rest(...)
+fn recover_value(ctx: &mut Context) -> Result<Option<TokenPair>, ()> {
+    if !ctx.recovering() {
+        return Err(());
+    }
+    let info = match &ctx.state.last_token {
+        Some((_, info)) => start_of(info),
+        None => end_of_last_tag(ctx.state),
+    };
+    ctx.send(Tag::Recovered, &info)?;
+    skip_rest(ctx)
+}
+
+// None if the token doesn't start and end with the same quote. our lexer never sends
+// one of those, but a TokenSource can be fed from anywhere
+fn strip_string_quotes(s: &str) -> Option<&str> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next_back()) {
+        (Some(open @ ('"' | '\'')), Some(close)) if open == close => Some(chars.as_str()),
+        _ => None,
+    }
+}
+
+fn parse_hex_unit(hex: &str) -> Option<u32> {
+    if !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        u32::from_str_radix(hex, 16).ok()
+    } else {
+        None
+    }
+}
+
+// turns the escape sequences of a string (without its quotes) into the characters they
+// stand for. on failure it returns what went wrong along with the byte offset and length
+// (in chars) of the offending escape
+fn unescape_string(raw: &str, json5: bool) -> Result<String, (ErrorKind, usize, usize)> {
+    let mut decoded = String::with_capacity(raw.len());
+    let mut pos = 0;
+    while let Some(found) = raw[pos..].find('\\') {
+        let backslash = pos + found;
+        decoded.push_str(&raw[pos..backslash]);
+        let escaped = match raw[backslash + 1..].chars().next() {
+            Some(c) => c,
+            None => return Err((ErrorKind::IncompleteEscape, backslash, 1)),
+        };
+        pos = backslash + 1 + escaped.len_utf8();
+        let c = match escaped {
+            '"' => '"',
+            '\\' => '\\',
+            '/' => '/',
+            'b' => '\u{8}',
+            'f' => '\u{c}',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'v' if json5 => '\u{b}',
+            // a digit after it would make it look like an octal escape, which json5
+            // doesn't have
+            '0' if json5 => match raw[pos..].chars().next() {
+                Some(digit) if digit.is_ascii_digit() => {
+                    return Err((ErrorKind::InvalidEscape(digit), backslash, 3))
+                }
+                _ => '\0',
+            },
+            'x' if json5 => {
+                let value = match raw.get(pos..pos + 2).and_then(parse_hex_unit) {
+                    Some(value) => value,
+                    None => return Err((ErrorKind::IncompleteHexEscape, backslash, 2)),
+                };
+                pos += 2;
+                // two digits are never more than 0xFF
+                char::from(value as u8)
+            }
+            // line continuations
+            '\n' | '\u{2028}' | '\u{2029}' if json5 => continue,
+            '\r' if json5 => {
+                if raw[pos..].starts_with('\n') {
+                    pos += 1;
+                }
+                continue;
+            }
+            'u' => {
+                let unit = match raw.get(pos..pos + 4).and_then(parse_hex_unit) {
+                    Some(unit) => unit,
+                    None => {
+                        return Err((
+                            ErrorKind::IncompleteUnicodeEscape,
+                            backslash,
+                            2,
+                        ))
                     }
-                    (lexer::MaybeToken::RightCurly, info) => {
-                        if require_kv_pair {
-                            sink.send(Tag::Error(
-                                ErrorInfo {
-                                    message: String::from("Expected another key-value pair after comma, but found a '}'"),
-                                    fragment: None,
-                                },
-                                info.clone(),
-                            ))
-                            .unwrap();
-                            return Err(());
+                };
+                pos += 4;
+                match unit {
+                    0xD800..=0xDBFF => {
+                        // the first half of a surrogate pair. the second half has to
+                        // follow right away
+                        let low = match raw.get(pos..pos + 2) {
+                            Some("\\u") => raw.get(pos + 2..pos + 6).and_then(parse_hex_unit),
+                            _ => None,
+                        };
+                        match low {
+                            Some(low @ 0xDC00..=0xDFFF) => {
+                                pos += 6;
+                                let combined = 0x10000 + ((unit - 0xD800) << 10) + (low - 0xDC00);
+                                std::char::from_u32(combined).unwrap_or('\u{FFFD}')
+                            }
+                            _ => {
+                                return Err((
+                                    ErrorKind::UnpairedSurrogate(raw[backslash + 2..backslash + 6].to_string()),
+                                    backslash,
+                                    6,
+                                ))
+                            }
                         }
-                        sink.send(Tag::EndObject).unwrap();
-                        return Ok(true);
                     }
-                    (_, info) => {
-                        if require_kv_pair {
-                            sink.send(Tag::Error(
-                                ErrorInfo {
-                                    message: String::from("Expected another key-value pair"),
-                                    fragment: None,
-                                },
-                                info.clone(),
-                            ))
-                            .unwrap();
-                            return Err(());
-                        }
-                        if require_comma_or_curly {
-                            sink.send(Tag::Error(
-                                ErrorInfo {
-                                    message: String::from("Expected comma or closing curly brace"),
-                                    fragment: None,
-                                },
-                                info.clone(),
-                            ))
-                            .unwrap();
-                            return Err(());
-                        }
-                        // the general case. most likely the object looks like { false, ...}
-                        // because someone forgot to quote the key name
-                        sink.send(Tag::Error(
-                            ErrorInfo {
-                                message: String::from(
-                                    "Expected key-value pair or closing curly brace",
-                                ),
-                                fragment: None,
-                            },
-                            info.clone(),
+                    0xDC00..=0xDFFF => {
+                        return Err((
+                            ErrorKind::UnpairedSurrogate(raw[backslash + 2..backslash + 6].to_string()),
+                            backslash,
+                            6,
                         ))
-                        .unwrap();
-                        return Err(());
                     }
+                    // everything outside the surrogate range is a valid char
+                    _ => std::char::from_u32(unit).unwrap_or('\u{FFFD}'),
+                }
+            }
+            // any other character but a digit stands for itself in json5, like \' does
+            other if json5 && !other.is_ascii_digit() => other,
+            other => {
+                return Err((
+                    ErrorKind::InvalidEscape(other),
+                    backslash,
+                    2,
+                ))
+            }
+        };
+        decoded.push(c);
+    }
+    decoded.push_str(&raw[pos..]);
+    Ok(decoded)
+}
+
+// where each line in a string token starts, as a byte offset. a \r\n is one line break,
+// like it is for the lexer
+fn line_breaks(text: &str) -> impl Iterator<Item = usize> + '_ {
+    text.char_indices().filter_map(move |(i, c)| match c {
+        '\r' if text[i + 1..].starts_with('\n') => Some(i + 2),
+        '\r' => Some(i + 1),
+        '\n' if !text[..i].ends_with('\r') => Some(i + 1),
+        _ => None,
+    })
+}
+
+// strips the quotes off a string token and, unless raw strings were asked for, decodes
+// its escape sequences
+fn string_contents(token_text: &str, info: &TagInfo, ctx: &mut Context) -> Result<String, ()> {
+    let raw = match strip_string_quotes(token_text) {
+        Some(raw) => raw,
+        None => {
+            let kind = ErrorKind::Internal(format!("malformed string token {}", token_text));
+            return ctx.error(kind, info);
+        }
+    };
+    if ctx.options.raw_strings {
+        return Ok(raw.to_string());
+    }
+    match unescape_string(raw, ctx.json5()) {
+        Ok(decoded) => Ok(decoded),
+        Err((kind, offset, length)) => {
+            // point right at the escape. the +1 skips the opening quote
+            let chars_before = raw[..offset].chars().count() + 1;
+            let escape_end = 1 + offset + raw[offset..].chars().take(length).map(char::len_utf8).sum::<usize>();
+            // json5's line continuations let a string span lines, and the token's
+            // position is where it ends. so the line is counted back from there, and the
+            // column from the last line break in front of the escape
+            let breaks_after = line_breaks(&token_text[escape_end..]).count();
+            let char_position = if breaks_after == 0 {
+                (info.char_position + chars_before + length).saturating_sub(info.length)
+            } else if let Some(line_start) = line_breaks(&token_text[..escape_end]).last() {
+                token_text[line_start..escape_end].chars().count() + 1
+            } else {
+                // on the first line of the string, the column is only known if the last
+                // tag ended on that line too
+                let first_line = info.line_number - breaks_after;
+                let quote_column = match &ctx.state.last_info {
+                    Some(last) if last.line_number == first_line => {
+                        last.char_position + info.start.saturating_sub(last.start + last.length)
+                    }
+                    _ => 1,
+                };
+                quote_column + chars_before + length
+            };
+            let escape_info = TagInfo {
+                line_number: info.line_number - breaks_after,
+                char_position,
+                start: info.start + chars_before,
+                length,
+            };
+            ctx.error(kind, &escape_info)
+        }
+    }
+}
+
+// an array or object that's still open. the parser keeps these on a stack of its own
+// instead of recursing into each of them, so a parse can stop after any tag and pick
+// up again later. that's what lets PullParser hand out tags as they come
+enum Frame {
+    Object(Box<ObjectFrame>),
+    Array(ArrayFrame),
+}
+
+// what try_value found
+enum Value {
+    // the whole value was parsed. a number hands back the token it had to look ahead at
+    Done(Option<TokenPair>),
+    // an array or object started. the rest of it is parsed in the frame
+    Opened(Frame),
+}
+
+// how far one step inside an array or object got
+enum Flow {
+    Continue,
+    // an array or object started inside of it, and has to be parsed first
+    Opened(Frame),
+    Closed,
+}
+
+impl Frame {
+    fn step(&mut self, ctx: &mut Context) -> Result<Flow, ()> {
+        match self {
+            Frame::Object(object) => object.step(ctx),
+            Frame::Array(array) => array.step(ctx),
+        }
+    }
+
+    // an array or object inside of this one is over. Err if it failed and this one
+    // can't recover from that either
+    fn value_done(&mut self, result: Result<Option<TokenPair>, ()>, ctx: &mut Context) -> Result<(), ()> {
+        match self {
+            Frame::Object(object) => {
+                let result = match result {
+                    Ok(lookahead) => Ok(lookahead),
+                    Err(()) => recover_value(ctx),
+                };
+                object.member_done(result, ctx)
+            }
+            Frame::Array(array) => {
+                let lookahead = match result {
+                    Ok(lookahead) => lookahead,
+                    Err(()) => recover_value(ctx)?,
+                };
+                array.element_done(lookahead);
+                Ok(())
+            }
+        }
+    }
+}
+
+struct ObjectFrame {
+    start_info: TagInfo,
+    held_back: bool,
+    // a token that was already pulled from the source, like the lookahead of a number.
+    // it's the next one this object gets to see
+    next_token: Option<TokenPair>,
+    require_comma_or_curly: bool,
+    require_kv_pair: bool,
+    // where each key of the object first appeared. it's only filled in for the
+    // policies that need it
+    seen_keys: HashMap<String, TagInfo>,
+    // set while parsing the value of a key that lost out under DuplicateKeys::FirstWins
+    muted: bool,
+}
+
+impl ObjectFrame {
+    // deals with the next token: a key along with its value, a comma, the closing curly
+    // or something that doesn't belong there
+    fn step(&mut self, ctx: &mut Context) -> Result<Flow, ()> {
+        let next_token = match self.next_token.take() {
+            Some(pair) => pair,
+            None => match require_next_token(ctx, &self.start_info) {
+                Ok(pair) => pair,
+                Err(()) if ctx.recovering() => return self.close(&end_of_last_tag(ctx.state), ctx),
+                Err(()) => return Err(()),
+            },
+        };
+        if ctx.recovering() && next_token.0 == lexer::MaybeToken::RightBracket {
+            let message = if self.require_comma_or_curly {
+                ErrorKind::MissingObjectComma
+            } else {
+                ErrorKind::ExpectedKeyValuePair
+            };
+            ctx.report(message, &next_token.1)?;
+            let info = start_of(&next_token.1);
+            // most likely it closes an array the object is in, so it's left for that
+            ctx.state.pending = Some(next_token);
+            return self.close(&info, ctx);
+        }
+        let key_name = match &next_token {
+            (lexer::MaybeToken::StringLiteral(key_name), key_name_info) => {
+                match string_contents(key_name, key_name_info, ctx) {
+                    Ok(key_name) => Some(key_name),
+                    // the pair gets skipped below
+                    Err(()) if ctx.recovering() => None,
+                    Err(()) => return Err(()),
                 }
-                // load the next token (if there is one)
-                let temp = require_next_token(rest, sink, &start_token.1);
-                if temp.is_ok() {
-                    next_token = temp.unwrap();
+            }
+            // json5 takes identifiers as keys, even reserved ones
+            (lexer::MaybeToken::Identifier(key_name), _) if ctx.json5() => {
+                Some(key_name.clone())
+            }
+            (lexer::MaybeToken::TrueLiteral, _) if ctx.json5() => Some(String::from("true")),
+            (lexer::MaybeToken::FalseLiteral, _) if ctx.json5() => Some(String::from("false")),
+            (lexer::MaybeToken::NullLiteral, _) if ctx.json5() => Some(String::from("null")),
+            _ => None,
+        };
+        match (&next_token, key_name) {
+            // possible kv pair
+            ((_, key_name_info), Some(key_name)) => {
+                if self.require_comma_or_curly {
+                    // when recovering, carry on as if the comma was there
+                    ctx.report(ErrorKind::MissingObjectComma, key_name_info)?;
+                }
+                match self.require_kv_pair((key_name, key_name_info), ctx) {
+                    Ok(Value::Done(lookahead)) => self.member_done(Ok(lookahead), ctx)?,
+                    // the rest of the pair comes in value_done()
+                    Ok(Value::Opened(frame)) => return Ok(Flow::Opened(frame)),
+                    Err(()) => self.member_done(Err(()), ctx)?,
+                }
+            }
+            ((lexer::MaybeToken::Comma, info), _) => {
+                if self.require_comma_or_curly {
+                    self.require_comma_or_curly = false;
                 } else {
-                    return Err(());
+                    ctx.report(ErrorKind::UnexpectedObjectComma, info)?;
                 }
+                self.require_kv_pair = true;
+            }
+            ((lexer::MaybeToken::RightCurly, info), _) => {
+                if self.require_kv_pair && !ctx.trailing_commas() {
+                    ctx.report(
+                        ErrorKind::TrailingComma('}'),
+                        info,
+                    )?;
+                }
+                return self.close(info, ctx);
+            }
+            ((_, info), _) => {
+                let message = if self.require_kv_pair {
+                    ErrorKind::MissingKeyValuePair
+                } else if self.require_comma_or_curly {
+                    ErrorKind::MissingObjectComma
+                } else {
+                    // the general case. most likely the object looks like
+                    // { false, ...} because someone forgot to quote the key name
+                    ErrorKind::ExpectedKeyValuePair
+                };
+                ctx.report(message, info)?;
+                // when recovering, the broken pair gets skipped
+                self.require_comma_or_curly = true;
+                self.require_kv_pair = false;
+                self.next_token = skip_rest(ctx)?;
             }
-        } else {
-            return Err(());
         }
+        Ok(Flow::Continue)
     }
-    Ok(false)
-}
 
-fn try_array(start_token: &TokenPair, rest: &mut TokenIter, sink: &TagSource) -> Result<bool, ()> {
-    if let lexer::MaybeToken::LeftBracket = &start_token.0 {
-        if sink.send(Tag::BeginArray).is_err() {
-            return Err(());
+    fn require_kv_pair(&mut self, key: (String, &TagInfo), ctx: &mut Context) -> Result<Value, ()> {
+        let stripped_name = key.0;
+        let policy = ctx.options.duplicate_keys;
+        if policy == DuplicateKeys::Error || policy == DuplicateKeys::FirstWins {
+            if let Some(first_info) = self.seen_keys.get(&stripped_name) {
+                if policy == DuplicateKeys::Error {
+                    let kind = ErrorKind::DuplicateKey(
+                        stripped_name,
+                        first_info.line_number,
+                        first_info.start,
+                    );
+                    return ctx.error(kind, key.1);
+                }
+                self.muted = true;
+                ctx.state.muted += 1;
+            } else {
+                self.seen_keys.insert(stripped_name.clone(), key.1.clone());
+            }
         }
+        require_object_value(stripped_name, key.1, ctx)
+    }
 
-        if let Ok(mut next_token) = require_next_token(rest, sink, &start_token.1) {
-            let mut require_comma_or_bracket = false;
-            let mut require_value_tok = false;
-            loop {
-                match &next_token {
-                    (lexer::MaybeToken::Comma, info) => {
-                        if require_comma_or_bracket {
-                            require_value_tok = true;
-                            require_comma_or_bracket = false;
-                        } else {
-                            sink.send(Tag::Error(
-                                ErrorInfo {
-                                    message: String::from(
-                                        "Syntax error. Encountered unexpected ',' in array",
-                                    ),
-                                    fragment: None,
-                                },
-                                info.clone(),
-                            ))
-                            .unwrap();
-                            return Err(());
-                        }
-                    }
-                    (lexer::MaybeToken::RightBracket, info) => {
-                        if require_value_tok {
-                            sink.send(Tag::Error(
-                                ErrorInfo {
-                                    message: String::from("Expected another value after comma, but found a ']'"),
-                                    fragment: None,
-                                },
-                                info.clone(),
-                            ))
-                            .unwrap();
-                            return Err(());
-                        }
-                        sink.send(Tag::EndArray).unwrap();
-                        return Ok(true);
-                    }
-                    any_pair => {
-                        if require_comma_or_bracket {
-                            sink.send(Tag::Error(
-                                ErrorInfo {
-                                    message: String::from("Expected comma or closing bracket"),
-                                    fragment: None,
-                                },
-                                any_pair.1.clone(),
-                            ))
-                            .unwrap();
-                            return Err(());
-                        }
-                        {
-                            let result = try_value(any_pair, rest, sink);
-                            if result.is_err() {
-                                sink.send(Tag::Error(
-                                    ErrorInfo {
-                                        message: String::from("Expected value or closing bracket"),
-                                        fragment: None,
-                                    },
-                                    any_pair.1.clone(),
-                                ))
-                                .unwrap();
-                                return Err(());
-                            } 
-                            let result = result.unwrap();
-                            if !result.0 {
-                                sink.send(Tag::Error(
-                                    ErrorInfo {
-                                        message: String::from("Expected value or closing bracket"),
-                                        fragment: None,
-                                    },
-                                    any_pair.1.clone(),
-                                ))
-                                .unwrap();
-                                return Err(());
-                            }
-                            if let Some(lookahead) = result.1 {
-                                // ok value succeeded, but we have a lookahead token
-                                next_token = lookahead;
-                                require_comma_or_bracket = true;
-                                require_value_tok = false;
-                                continue;
-                            } else {
-                                require_comma_or_bracket = true;
-                                require_value_tok = false;
-                            }
+    // the value of a key is over. if the pair failed (a duplicate key, say), whatever is
+    // left of it gets skipped
+    fn member_done(&mut self, result: Result<Option<TokenPair>, ()>, ctx: &mut Context) -> Result<(), ()> {
+        if self.muted {
+            self.muted = false;
+            ctx.state.muted -= 1;
+        }
+        let lookahead = match result {
+            Ok(lookahead) => lookahead,
+            Err(()) => skip_rest(ctx)?,
+        };
+        self.require_comma_or_curly = true;
+        self.require_kv_pair = false;
+        self.next_token = lookahead;
+        Ok(())
+    }
+
+    fn close(&mut self, info: &TagInfo, ctx: &mut Context) -> Result<Flow, ()> {
+        if self.held_back {
+            // every key is known now, so the object can go out. if it's nested in
+            // another held back object, that's where it goes
+            if let Some(members) = ctx.state.objects.pop() {
+                let tags = surviving_members(members);
+                match ctx.state.objects.last_mut().and_then(|m| m.last_mut()) {
+                    Some((_, parent_tags)) => parent_tags.extend(tags),
+                    None => {
+                        for (tag, tag_info) in tags {
+                            ctx.send(tag, &tag_info)?;
                         }
                     }
                 }
-                // load the next token (if there is one)
-                let temp = require_next_token(rest, sink, &start_token.1);
-                if temp.is_ok() {
-                    next_token = temp.unwrap();
+            }
+        }
+        ctx.send(Tag::EndObject, info)?;
+        ctx.state.depth -= 1;
+        Ok(Flow::Closed)
+    }
+}
+
+fn require_object_value(
+    stripped_name: String,
+    key_info: &TagInfo,
+    ctx: &mut Context,
+) -> Result<Value, ()> {
+    ctx.send(Tag::ObjectKey(stripped_name.clone()), key_info)?;
+    let (next_token, info) = match require_next_token(ctx, key_info) {
+        Ok(pair) => pair,
+        Err(()) => return recover_value(ctx).map(Value::Done),
+    };
+    let hopefully_value_token = if let lexer::MaybeToken::Colon = next_token {
+        match require_next_token(ctx, &info) {
+            Ok(pair) => pair,
+            Err(()) => return recover_value(ctx).map(Value::Done),
+        }
+    } else {
+        ctx.report(
+            ErrorKind::MissingColon(stripped_name.clone()),
+            &info,
+        )?;
+        // when recovering, carry on as if the colon was there
+        (next_token, info.clone())
+    };
+    match try_value(&hopefully_value_token, ctx) {
+        Ok(Some(value)) => Ok(value),
+        Ok(None) => {
+            ctx.report(
+                ErrorKind::MissingValue(stripped_name),
+                &info,
+            )?;
+            recover_value(ctx).map(Value::Done)
+        }
+        Err(()) => recover_value(ctx).map(Value::Done),
+    }
+}
+
+// called before descending into an array or object. there's no need to undo this on
+// errors since every document starts over at depth 0
+fn enter_container(ctx: &mut Context, info: &TagInfo) -> Result<(), ()> {
+    if ctx.state.depth >= ctx.options.max_depth {
+        return ctx.error(ErrorKind::MaxDepthExceeded, info);
+    }
+    ctx.state.depth += 1;
+    Ok(())
+}
+
+fn try_object(start_token: &TokenPair, ctx: &mut Context) -> Result<Option<Frame>, ()> {
+    if let lexer::MaybeToken::LeftCurly = &start_token.0 {
+        enter_container(ctx, &start_token.1)?;
+        ctx.send(Tag::BeginObject, &start_token.1)?;
+        let held_back = ctx.options.duplicate_keys == DuplicateKeys::LastWins;
+        if held_back {
+            ctx.state.objects.push(Vec::new());
+        }
+        return Ok(Some(Frame::Object(Box::new(ObjectFrame {
+            start_info: start_token.1.clone(),
+            held_back,
+            next_token: None,
+            require_comma_or_curly: false,
+            require_kv_pair: false,
+            seen_keys: HashMap::new(),
+            muted: false,
+        }))));
+    }
+    Ok(None)
+}
+
+struct ArrayFrame {
+    start_info: TagInfo,
+    // a token that was already pulled from the source, see ObjectFrame
+    next_token: Option<TokenPair>,
+    require_comma_or_bracket: bool,
+    require_value_tok: bool,
+}
+
+impl ArrayFrame {
+    // deals with the next token: a value, a comma, the closing bracket or something
+    // that doesn't belong there
+    fn step(&mut self, ctx: &mut Context) -> Result<Flow, ()> {
+        let next_token = match self.next_token.take() {
+            Some(pair) => pair,
+            None => match require_next_token(ctx, &self.start_info) {
+                Ok(pair) => pair,
+                Err(()) if ctx.recovering() => return self.close(&end_of_last_tag(ctx.state), ctx),
+                Err(()) => return Err(()),
+            },
+        };
+        if ctx.recovering() && next_token.0 == lexer::MaybeToken::RightCurly {
+            let message = if self.require_comma_or_bracket {
+                ErrorKind::MissingArrayComma
+            } else {
+                ErrorKind::ExpectedArrayValue
+            };
+            ctx.report(message, &next_token.1)?;
+            let info = start_of(&next_token.1);
+            // most likely it closes an object the array is in, so it's left for that
+            ctx.state.pending = Some(next_token);
+            return self.close(&info, ctx);
+        }
+        match &next_token {
+            (lexer::MaybeToken::Comma, info) => {
+                if self.require_comma_or_bracket {
+                    self.require_comma_or_bracket = false;
                 } else {
-                    return Err(());
+                    ctx.report(ErrorKind::UnexpectedArrayComma, info)?;
+                    // when recovering, there's a value missing in front of the comma
+                    ctx.send(Tag::Recovered, &start_of(info))?;
                 }
+                self.require_value_tok = true;
+            }
+            (lexer::MaybeToken::RightBracket, info) => {
+                if self.require_value_tok && !ctx.trailing_commas() {
+                    ctx.report(
+                        ErrorKind::TrailingComma(']'),
+                        info,
+                    )?;
+                }
+                return self.close(info, ctx);
+            }
+            any_pair => {
+                if self.require_comma_or_bracket {
+                    // when recovering, carry on as if the comma was there
+                    ctx.report(ErrorKind::MissingArrayComma, &any_pair.1)?;
+                }
+                let lookahead = match try_value(any_pair, ctx) {
+                    Ok(Some(Value::Done(lookahead))) => lookahead,
+                    // the rest of the element comes in value_done()
+                    Ok(Some(Value::Opened(frame))) => return Ok(Flow::Opened(frame)),
+                    Ok(None) => {
+                        ctx.report(ErrorKind::ExpectedArrayValue, &any_pair.1)?;
+                        recover_value(ctx)?
+                    }
+                    Err(()) => recover_value(ctx)?,
+                };
+                self.element_done(lookahead);
             }
-        } else {
-            return Err(());
         }
+        Ok(Flow::Continue)
+    }
+
+    fn element_done(&mut self, lookahead: Option<TokenPair>) {
+        self.require_comma_or_bracket = true;
+        self.require_value_tok = false;
+        self.next_token = lookahead;
+    }
+
+    fn close(&mut self, info: &TagInfo, ctx: &mut Context) -> Result<Flow, ()> {
+        ctx.send(Tag::EndArray, info)?;
+        ctx.state.depth -= 1;
+        Ok(Flow::Closed)
     }
-    Ok(false)
 }
 
-fn try_string(start_token: &TokenPair, sink: &TagSource) -> Result<bool, ()> {
+fn try_array(start_token: &TokenPair, ctx: &mut Context) -> Result<Option<Frame>, ()> {
+    if let lexer::MaybeToken::LeftBracket = &start_token.0 {
+        enter_container(ctx, &start_token.1)?;
+        ctx.send(Tag::BeginArray, &start_token.1)?;
+        return Ok(Some(Frame::Array(ArrayFrame {
+            start_info: start_token.1.clone(),
+            next_token: None,
+            require_comma_or_bracket: false,
+            require_value_tok: false,
+        })));
+    }
+    Ok(None)
+}
+
+fn try_string(start_token: &TokenPair, ctx: &mut Context) -> Result<bool, ()> {
     if let lexer::MaybeToken::StringLiteral(s) = &start_token.0 {
         // unlike in the lexer where we need to preserve char positions for all tokens
         // we strip the enclosing quotes off strings here because they'd otherwise
         // be tedious for users of the parser
-        if sink
-            .send(Tag::StringLiteral(strip_string_quotes(s).to_string()))
-            .is_err()
-        {
-            return Err(());
-        } else {
-            return Ok(true);
-        }
+        let contents = string_contents(s, &start_token.1, ctx)?;
+        ctx.send(Tag::StringLiteral(contents), &start_token.1)?;
+        return Ok(true);
     }
     Ok(false)
 }
 
-fn require_integer(next_token: &lexer::MaybeToken) -> Result<String, ()> {
-    if let lexer::MaybeToken::Integer(n) = next_token {
-        Ok(n.clone())
-    } else {
-        Err(())
-    }
-}
-
 // possibly advances the iter by 1 token pair. if the next token is an Error, it sends
 // it to the sink and returns an Err result for propagating an abort.
 // note that if the next token doesn't exist because the stream ended, this is
 // not treated as an error because in some cases it's ok and just means the parse
 // has finished
-fn try_next_token(source: &mut TokenIter, sink: &TagSource) -> Result<Option<TokenPair>, ()> {
-    match source.next() {
-        Some((token, info)) => {
-            if let lexer::MaybeToken::Error(err_info) = token {
-                sink.send(Tag::Error(err_info, info)).unwrap();
+fn try_next_token(ctx: &mut Context) -> Result<Option<TokenPair>, ()> {
+    loop {
+        let next = match ctx.state.pending.take() {
+            Some(pending) => Some(pending),
+            None => ctx.tokens.next(),
+        };
+        return match next {
+            // in Lines mode the end of the line is the end of the document, and in
+            // Records mode it's the next record separator
+            Some(pair) if ends_document(ctx.state, &pair) => {
+                ctx.state.pending = Some(pair);
+                ctx.state.last_token = None;
+                Ok(None)
+            }
+            Some((lexer::MaybeToken::Error(err_info), info)) => {
+                ctx.send(Tag::Error(err_info, info.clone()), &info)?;
+                if ctx.recovering() {
+                    // the lexer patched things up already
+                    continue;
+                }
                 Err(())
-            } else {
-                Ok(Some((token, info)))
             }
-        }
-        None => Ok(None),
+            // comments can be anywhere, so they're dealt with right here
+            Some((lexer::MaybeToken::Comment(text), info)) => {
+                if ctx.options.emit_comments {
+                    ctx.send(Tag::Comment(text), &info)?;
+                }
+                continue;
+            }
+            other => {
+                if ctx.options.recover {
+                    ctx.state.last_token = other.clone();
+                }
+                Ok(other)
+            }
+        };
+    }
+}
+
+// try_number has to look at the token after a number to know where it ends. a comment
+// there ends it too, but its tag has to wait until the number's been sent, so it's left
+// for try_next_token. when recovering, so is an error the lexer found right after it
+fn try_next_number_token(ctx: &mut Context) -> Result<Option<TokenPair>, ()> {
+    let next = match ctx.state.pending.take() {
+        Some(pending) => pending,
+        None => match ctx.tokens.next() {
+            Some(pair) => pair,
+            None => return Ok(None),
+        },
+    };
+    let is_comment = match next.0 {
+        lexer::MaybeToken::Comment(_) => true,
+        lexer::MaybeToken::Error(_) => ctx.options.recover,
+        _ => false,
+    };
+    ctx.state.pending = Some(next);
+    if is_comment {
+        Ok(None)
+    } else {
+        try_next_token(ctx)
+    }
+}
+
+fn ends_document(state: &DocumentState, token_pair: &TokenPair) -> bool {
+    match token_pair {
+        (lexer::MaybeToken::RecordSeparator, _) => state.in_record,
+        (_, info) => state.line.is_some_and(|line| info.line_number > line),
     }
 }
 
 // the last token info is only needed to output a helpful error message in case the stream ends
 // unexpectedly
-fn require_next_token(
-    source: &mut TokenIter,
-    sink: &TagSource,
-    last_token_info: &lexer::TokenInfo,
-) -> Result<TokenPair, ()> {
-    let result = try_next_token(source, sink);
-    if result.is_err() {
-        Err(())
-    } else {
-        let pair = result?;
-        if pair.is_none() {
-            let dupe = lexer::TokenInfo {
+fn require_next_token(ctx: &mut Context, last_token_info: &TagInfo) -> Result<TokenPair, ()> {
+    match try_next_token(ctx)? {
+        Some(pair) => Ok(pair),
+        None => {
+            let mut dupe = TagInfo {
                 char_position: last_token_info.char_position + 1,
                 start: last_token_info.start + last_token_info.length,
                 length: 0,
                 line_number: last_token_info.line_number,
             };
-            sink.send(Tag::Error(
-                ErrorInfo {
-                    message: String::from("Encountered end of stream, but more tokens expected"),
-                    fragment: None,
-                },
-                dupe,
-            ))
-            .unwrap();
-            Err(())
-        } else {
-            Ok(pair.unwrap())
+            // a container hands in its opening bracket, but whatever came last inside
+            // of it is where things ran out
+            let end = end_of_last_tag(ctx.state);
+            if end.start > dupe.start {
+                dupe = TagInfo {
+                    char_position: end.char_position + 1,
+                    ..end
+                };
+            }
+            if ctx.state.pending.is_some() {
+                if ctx.options.documents == DocumentMode::Records {
+                    ctx.error(ErrorKind::UnexpectedEndOfRecord, &dupe)
+                } else {
+                    ctx.error(ErrorKind::UnexpectedEndOfLine, &dupe)
+                }
+            } else {
+                ctx.error(ErrorKind::UnexpectedEof, &dupe)
+            }
         }
     }
 }
 
-fn try_number(start_token: &TokenPair, rest: &mut TokenIter, sink: &TagSource) -> Result<(bool,Option<TokenPair>), ()> {
-    let mut recomposed_float = String::from("");
-    let mut new_tok_info = start_token.1.clone();
-    new_tok_info.length = 0; // reset, we're going to manually count the length
-
-    let error_sender = |msg: &str, tag_info: &TagInfo| {
-        sink.send(Tag::Error(
-            ErrorInfo {
-                message: String::from(msg),
-                fragment: None,
-            },
-            tag_info.clone(),
-        ))
-        .unwrap();
-    };
+// a number is spread over several tokens, so its span grows with every one we consume
+fn extend_span(span: &mut TagInfo, info: &TagInfo) {
+    span.length += info.length;
+    span.char_position = info.char_position;
+}
 
-    let send_num_and_return = |tok,leftover_tok| -> Result<(bool,Option<TokenPair>), ()> {
-        sink.send(tok).unwrap();
-        Ok((true,leftover_tok))
-    };
+// the text of a number in plain json notation, for Tag::Number, and the way it was
+// written, for Tag::TypedNumber. they only differ in json5
+#[derive(Default)]
+struct NumberText {
+    json: String,
+    written: String,
+}
 
-    // in case we need to give back the lookahead, which we cannot really return to a
-    // mpsc::Receiver
-    let mut leftover_token : Option<TokenPair> = None;
+impl NumberText {
+    fn push_str(&mut self, text: &str) {
+        self.json.push_str(text);
+        self.written.push_str(text);
+    }
+}
 
-    if let (lexer::MaybeToken::MinusSign, info) = start_token {
-        let next_token = require_next_token(rest, sink, info);
-        if next_token.is_err() {
-            return Err(());
+// everything after the '.' of a number. hands back the token that comes after the
+// fraction, if there is one
+fn require_fraction(
+    dot_info: &TagInfo,
+    leading: bool,
+    text: &mut NumberText,
+    span: &mut TagInfo,
+    ctx: &mut Context,
+) -> Result<Option<TokenPair>, ()> {
+    text.push_str(".");
+    extend_span(span, dot_info);
+    let next = if ctx.json5() {
+        try_next_number_token(ctx)?
+    } else {
+        Some(require_next_token(ctx, dot_info)?)
+    };
+    match next {
+        // in json5 the digits aren't necessarily part of the fraction, since the lexer
+        // allows whitespace after a dot there
+        Some((lexer::MaybeToken::Integer(intval), int_info))
+            if int_info.start == dot_info.start + dot_info.length =>
+        {
+            text.push_str(&intval);
+            extend_span(span, &int_info);
+            try_next_number_token(ctx)
         }
-        recomposed_float.push('-');
-        new_tok_info.length += info.length;
-        new_tok_info.char_position = info.char_position;
-        let (tok, info) = next_token?;
-        if let Ok(intval) = require_integer(&tok) {
-            recomposed_float.push_str(&intval);
-            new_tok_info.length += info.length;
-            new_tok_info.char_position = info.char_position;
-        } else {
-            error_sender("Syntax error. Expected digits after '-'", &info);
-            return Err(());
+        // json5 allows a trailing decimal point as in 5. as long as there's something
+        // in front of it
+        other if ctx.json5() && !leading => {
+            text.json.push('0');
+            Ok(other)
         }
-    } else if let (lexer::MaybeToken::Integer(n), info) = start_token {
-        recomposed_float.push_str(n);
-        new_tok_info.length += info.length;
-        new_tok_info.char_position = info.char_position;
-    } else {
-        return Ok((false,None));
+        _ => ctx.error(ErrorKind::MissingFraction, dot_info),
     }
-    {
-        // check for optional fraction, optional exponent
-        let mut next_token = try_next_token(rest, sink);
-        if next_token.is_err() {
-            return Err(());
+}
+
+fn try_number(
+    start_token: &TokenPair,
+    ctx: &mut Context,
+) -> Result<(bool, Option<TokenPair>), ()> {
+    let json5 = ctx.json5();
+    let mut text = NumberText::default();
+    let mut new_tok_info = start_token.1.clone();
+    new_tok_info.length = 0; // reset, we're going to manually count the length
+
+    let after_sign;
+    let mut sign = None;
+    let first = match start_token {
+        (lexer::MaybeToken::MinusSign, info) => {
+            sign = Some('-');
+            text.push_str("-");
+            extend_span(&mut new_tok_info, info);
+            after_sign = require_next_token(ctx, info)?;
+            &after_sign
         }
-        let opt_pair = next_token?;
-        if !opt_pair.is_none() {
-            let mut pair = opt_pair.unwrap();
-            if let (lexer::MaybeToken::Dot, info) = pair {
-                recomposed_float.push('.');
-                new_tok_info.length += info.length;
-                new_tok_info.char_position = info.char_position;
-                let hopefully_int = require_next_token(rest, sink, &info);
-                if hopefully_int.is_err() {
-                    return Err(());
-                }
-                let int_pair = hopefully_int?;
-                if let Ok(intval) = require_integer(&int_pair.0) {
-                    recomposed_float.push_str(&intval);
-                    let int_info = int_pair.1;
-                    new_tok_info.length += int_info.length;
-                    new_tok_info.char_position = int_info.char_position;
-                } else {
-                    error_sender("Syntax error. Expected fractional digits after '.'", &info);
-                    return Err(());
+        // json5 allows a leading '+', which doesn't need to show up in the json text
+        (lexer::MaybeToken::PlusSign, info) if json5 => {
+            sign = Some('+');
+            text.written.push('+');
+            extend_span(&mut new_tok_info, info);
+            after_sign = require_next_token(ctx, info)?;
+            &after_sign
+        }
+        _ => start_token,
+    };
+
+    // check for optional fraction, optional exponent. whatever else we pull out of the
+    // stream doesn't belong to us and has to be handed back as a lookahead, since we
+    // cannot really return it to the token source
+    let mut had_fraction = false;
+    let mut pair = match first {
+        (lexer::MaybeToken::Integer(intval), info) => {
+            if !ctx.options.leading_zeros && intval.len() > 1 && intval.starts_with('0') {
+                // when recovering, the number is still good for something
+                ctx.report(ErrorKind::LeadingZero, info)?;
+            }
+            text.push_str(intval);
+            extend_span(&mut new_tok_info, info);
+            match try_next_number_token(ctx)? {
+                Some(pair) => pair,
+                None => {
+                    // it's just an integer and we're done
+                    send_number(text, &new_tok_info, ctx)?;
+                    return Ok((true, None));
                 }
-                // great, we found the fractional part. now set everything back up
-                // for the exponent code
-                next_token = try_next_token(rest, sink);
-                if next_token.is_err() {
-                    return Err(());
+            }
+        }
+        (lexer::MaybeToken::HexInteger(digits), info) if json5 => {
+            // a Tag::Number has it in decimal like every other number
+            extend_span(&mut new_tok_info, info);
+            text.written.push_str("0x");
+            text.written.push_str(digits);
+            text.json = hex_to_decimal(&text.written).unwrap_or_default();
+            send_number(text, &new_tok_info, ctx)?;
+            return Ok((true, None));
+        }
+        (lexer::MaybeToken::Identifier(name), info)
+            if json5 && (name == "Infinity" || name == "NaN") =>
+        {
+            extend_span(&mut new_tok_info, info);
+            // json has no way to write these, so they stay as they are, sign and all
+            text.push_str(name);
+            send_number(text, &new_tok_info, ctx)?;
+            return Ok((true, None));
+        }
+        // a leading decimal point, as in .5
+        (lexer::MaybeToken::Dot, info) if json5 => {
+            had_fraction = true;
+            text.json.push('0');
+            match require_fraction(info, true, &mut text, &mut new_tok_info, ctx)? {
+                Some(pair) => pair,
+                None => {
+                    send_number(text, &new_tok_info, ctx)?;
+                    return Ok((true, None));
                 }
-                let opt_pair = next_token?;
-                if !opt_pair.is_none() {
-                    pair = opt_pair.unwrap();
-                } else {
-                    // we're done
-                    return send_num_and_return(Tag::Number(recomposed_float), None);
+            }
+        }
+        (_, info) => {
+            return match sign {
+                Some(sign) => ctx.error(ErrorKind::MissingDigits(sign), info),
+                None => Ok((false, None)),
+            };
+        }
+    };
+    if let (lexer::MaybeToken::Dot, info) = &pair {
+        // the lexer makes sure there's no whitespace in front of the dot, except in
+        // json5 where a dot can start a number of its own
+        let info = info.clone();
+        if !had_fraction && (!json5 || info.start == new_tok_info.start + new_tok_info.length) {
+            // great, we found the fractional part. now set everything back up
+            // for the exponent code
+            pair = match require_fraction(&info, false, &mut text, &mut new_tok_info, ctx)? {
+                Some(pair) => pair,
+                None => {
+                    send_number(text, &new_tok_info, ctx)?;
+                    return Ok((true, None));
                 }
+            };
+        }
+    }
+    let mut leftover_token = None;
+    if let (lexer::MaybeToken::Exponent, info) = &pair {
+        text.push_str("E");
+        extend_span(&mut new_tok_info, info);
+        // check for (+|-)<int>
+        let (mut tok, mut info) = match try_next_token(ctx)? {
+            Some(pair) => pair,
+            None => {
+                return ctx.error(
+                    ErrorKind::MissingExponentDigits,
+                    info,
+                )
             }
-            if let (lexer::MaybeToken::Exponent, info) = pair {
-                recomposed_float.push('E');
-                new_tok_info.length += info.length;
-                new_tok_info.char_position = info.char_position;
-                // check for (+|-)<int>
-                next_token = try_next_token(rest, sink);
-                if next_token.is_err() {
-                    return Err(());
+        };
+        let sign = match tok {
+            lexer::MaybeToken::PlusSign => Some("+"),
+            lexer::MaybeToken::MinusSign => Some("-"),
+            _ => None,
+        };
+        if let Some(sign) = sign {
+            text.push_str(sign);
+            extend_span(&mut new_tok_info, &info);
+            match try_next_token(ctx)? {
+                Some(pair) => {
+                    tok = pair.0;
+                    info = pair.1;
                 }
-                let opt_pair = next_token?;
-                if !opt_pair.is_none() {
-                    // check for + or - followed by an int or just an int
-                    let (mut tok, mut info) = opt_pair.unwrap();
-                    let mut was_plus_or_minus = false;
-                    if tok == lexer::MaybeToken::PlusSign {
-                        recomposed_float.push('+');
-                        was_plus_or_minus = true;
-                    } else if tok == lexer::MaybeToken::MinusSign {
-                        recomposed_float.push('-');
-                        was_plus_or_minus = true;
-                    }
-                    if was_plus_or_minus {
-                        new_tok_info.length += info.length;
-                        new_tok_info.char_position = info.char_position;
-                        next_token = try_next_token(rest, sink);
-                        if next_token.is_err() {
-                            return Err(());
-                        }
-                        let opt_pair = next_token?;
-                        if !opt_pair.is_none() {
-                            // ok rust doesn't allow this according to reddit...
-                            // (tok,info) = opt_pair.unwrap();
-                            let temp = opt_pair.unwrap();
-                            tok = temp.0;
-                            info = temp.1;
-                        } else {
-                            error_sender(
-                                "Syntax error. Expected digits after exponent character",
-                                &info,
-                            );
-                            return Err(());
-                        }
-                    }
-                    // the next token MUST be a digit
-                    if let Ok(intval) = require_integer(&tok) {
-                        recomposed_float.push_str(&intval);
-                        new_tok_info.length += info.length;
-                        new_tok_info.char_position = info.char_position;
-                    } else {
-                        error_sender("Syntax error. Expected digits after '-'", &info);
-                        return Err(());
-                    }
-                } else {
-                    error_sender(
-                        "Syntax error. Expected digits after exponent character",
+                None => {
+                    return ctx.error(
+                        ErrorKind::MissingExponentDigits,
                         &info,
-                    );
-                    return Err(());
+                    )
                 }
-            } else {
-                // oops, we took a token that didn't belong to us
-                leftover_token = Some(pair);
             }
-        } // else it's just an integer and we're done
+        }
+        // the next token MUST be a digit
+        if let lexer::MaybeToken::Integer(intval) = &tok {
+            text.push_str(intval);
+            extend_span(&mut new_tok_info, &info);
+        } else {
+            return ctx.error(ErrorKind::MissingExponentDigits, &info);
+        }
+    } else {
+        // oops, we took a token that didn't belong to us
+        leftover_token = Some(pair);
     }
 
-    send_num_and_return(Tag::Number(recomposed_float), leftover_token)
+    send_number(text, &new_tok_info, ctx)?;
+    Ok((true, leftover_token))
 }
 
-fn try_literal(start_token: &TokenPair, sink: &TagSource) -> Result<bool, ()> {
+fn send_number(text: NumberText, info: &TagInfo, ctx: &mut Context) -> Result<(), ()> {
+    match ctx.options.numbers {
+        NumberMode::Text => ctx.send(Tag::Number(text.json), info),
+        NumberMode::Typed(out_of_range) => match Number::from_lexeme(&text.written, out_of_range) {
+            Ok(number) => ctx.send(Tag::TypedNumber(number, text.written), info),
+            Err(kind) => ctx.error(kind, info),
+        },
+    }
+}
+
+fn try_literal(start_token: &TokenPair, ctx: &mut Context) -> Result<bool, ()> {
     let matching_literal = match start_token.0 {
         lexer::MaybeToken::FalseLiteral => Tag::FalseLiteral,
         lexer::MaybeToken::TrueLiteral => Tag::TrueLiteral,
         lexer::MaybeToken::NullLiteral => Tag::NullLiteral,
         _ => return Ok(false),
     };
-    // i vaguely recall there's some way to convert one result type to another, but
-    // i cannot find it now, unfortunately
-    if sink.send(matching_literal).is_err() {
-        Err(())
-    } else {
-        Ok(true)
+    ctx.send(matching_literal, &start_token.1)?;
+    Ok(true)
+}
+
+// Ok(None) means the token doesn't start a value at all. Err means an error was
+// already reported (or nobody is listening anymore) and the parse has to stop
+fn try_value(start_token: &TokenPair, ctx: &mut Context) -> Result<Option<Value>, ()> {
+    if let Some(frame) = try_object(start_token, ctx)? {
+        return Ok(Some(Value::Opened(frame)));
+    }
+    if let Some(frame) = try_array(start_token, ctx)? {
+        return Ok(Some(Value::Opened(frame)));
+    }
+    if try_string(start_token, ctx)? || try_literal(start_token, ctx)? {
+        return Ok(Some(Value::Done(None)));
+    }
+    match try_number(start_token, ctx)? {
+        (true, lookahead) => Ok(Some(Value::Done(lookahead))),
+        (false, _) => Ok(None),
     }
 }
 
-fn try_value(start_token: &TokenPair, rest: &mut TokenIter, sink: &TagSource) -> Result<(bool,Option<TokenPair>), ()> {
-    // not sure how to elegantly do this. Result has a billion functions
-    // but none of them really match what I want:
-    // if Ok(true) or Err short-circuit, else call the next try fn
-    let worked = try_object(start_token, rest, sink);
-    if worked.is_err() {
-        return Err(());
-    } else if worked.unwrap() {
-        return Ok((true,None));
+// starts on the root value of the next document. Ok(None) once there's nothing left
+fn begin_document(ctx: &mut Context) -> Result<Option<Value>, ()> {
+    ctx.state.line = None;
+    ctx.state.in_record = false;
+    ctx.state.depth = 0;
+    ctx.state.muted = 0;
+    ctx.state.objects.clear();
+    ctx.state.frames.clear();
+    ctx.state.quiet = false;
+    let mut token_pair = match try_next_token(ctx)? {
+        Some(token_pair) => token_pair,
+        None => {
+            ctx.state.finished = true;
+            return Ok(None);
+        }
+    };
+    if ctx.options.documents == DocumentMode::Lines {
+        ctx.state.line = Some(token_pair.1.line_number);
     }
-    let worked = try_array(start_token, rest, sink);
-    if worked.is_err() {
-        return Err(());
-    } else if worked.unwrap() {
-        return Ok((true,None));
+    if ctx.options.documents == DocumentMode::Records {
+        if token_pair.0 != lexer::MaybeToken::RecordSeparator {
+            return ctx.error(ErrorKind::MissingRecordSeparator, &token_pair.1);
+        }
+        // empty records don't count as documents
+        while token_pair.0 == lexer::MaybeToken::RecordSeparator {
+            token_pair = match try_next_token(ctx)? {
+                Some(token_pair) => token_pair,
+                None => {
+                    ctx.state.finished = true;
+                    return Ok(None);
+                }
+            };
+        }
+        ctx.state.in_record = true;
     }
-    let worked = try_string(start_token, sink);
-    if worked.is_err() {
-        return Err(());
-    } else if worked.unwrap() {
-        return Ok((true,None));
+    match try_value(&token_pair, ctx)? {
+        Some(value) => Ok(Some(value)),
+        None => ctx.error(ErrorKind::ExpectedValue, &token_pair.1),
     }
-    let worked = try_literal(start_token, sink);
-    if worked.is_err() {
-        return Err(());
-    } else if worked.unwrap() {
-        return Ok((true,None));
+}
+
+// the root value is over. lookahead is whatever try_number had to look ahead at
+fn finish_root(ctx: &mut Context, lookahead: Option<TokenPair>) -> Result<(), ()> {
+    let check_trailing = match ctx.options.documents {
+        DocumentMode::Single => ctx.options.strict,
+        // try_next_token stops at the end of the line or record, so this only looks
+        // at what's left of the current one
+        DocumentMode::Lines | DocumentMode::Records => true,
+        DocumentMode::Multiple => {
+            // whatever try_number had to look ahead at starts the next document. if it
+            // stopped at a comment, that's already waiting in pending
+            if lookahead.is_some() {
+                ctx.state.pending = lookahead;
+            }
+            return Ok(());
+        }
+    };
+    if check_trailing {
+        // the lexer already skips whitespace, so any token at all is one too many.
+        // that includes whatever try_number had to look ahead at
+        let trailing = match lookahead {
+            Some(lookahead) => Some(lookahead),
+            None => try_next_token(ctx)?,
+        };
+        if let Some((_, info)) = trailing {
+            return ctx.error(ErrorKind::TrailingToken, &info);
+        }
     }
-    let worked = try_number(start_token, rest, sink);
-    if worked.is_err() {
-        return Err(());
-    } else if let (true,lookahead) = worked.unwrap() {
-        return Ok((true,lookahead));
+    Ok(())
+}
+
+// throws away everything up to the end of the line an error was on, or up to the next
+// record separator in Records mode. the lexer does the same for its own errors, so
+// both end up in the same place
+fn is_fatal(token_pair: &TokenPair) -> bool {
+    match token_pair {
+        (lexer::MaybeToken::Error(err_info), _) => err_info.kind.is_fatal(),
+        _ => false,
     }
-    Err(())
 }
 
-pub fn parse(token_source: TokenSource, tag_sink: TagSource) -> () {
-    thread::spawn(move || {
-        let mut token_iter = token_source.iter();
-        match token_iter.next() {
-            Some(token_pair) => {
-                if let (lexer::MaybeToken::Error(err_info), info) = token_pair {
-                    tag_sink.send(Tag::Error(err_info, info)).unwrap();
+fn skip_broken_document(ctx: &mut Context, error_line: usize) {
+    ctx.state.line = None;
+    ctx.state.in_record = false;
+    let records = ctx.options.documents == DocumentMode::Records;
+    loop {
+        let next = match ctx.state.pending.take() {
+            Some(pending) => Some(pending),
+            None => ctx.tokens.next(),
+        };
+        match next {
+            Some((lexer::MaybeToken::RecordSeparator, info)) if records => {
+                ctx.state.pending = Some((lexer::MaybeToken::RecordSeparator, info));
+                return;
+            }
+            // an error the lexer stops for good at, like a failed read, mustn't get
+            // lost with the rest of the broken document
+            Some(pair) if is_fatal(&pair) => {
+                ctx.state.pending = Some(pair);
+                return;
+            }
+            Some(_) if records => continue,
+            Some(pair) if pair.1.line_number <= error_line => continue,
+            other => {
+                ctx.state.pending = other;
+                return;
+            }
+        }
+    }
+}
+
+fn end_document(ctx: &mut Context, result: Result<(), ()>) {
+    if ctx.options.documents == DocumentMode::Single {
+        ctx.state.finished = true;
+    }
+    if ctx.state.finished {
+        return;
+    }
+    if result.is_err() {
+        if let Some(error_line) = ctx.state.error_line {
+            skip_broken_document(ctx, error_line);
+        }
+    }
+    let end_info = end_of_last_tag(ctx.state);
+    let index = ctx.state.count;
+    ctx.state.count += 1;
+    // a failed send already marks the parse as finished
+    let _ = ctx.send(Tag::EndDocument(index), &end_info);
+}
+
+// hands the outcome of an array or object to the one it's in, or to the document if it
+// was the root. an Err travels outwards for as long as nothing recovers from it
+fn value_done(ctx: &mut Context, mut result: Result<Option<TokenPair>, ()>) {
+    while let Some(mut frame) = ctx.state.frames.pop() {
+        match frame.value_done(result, ctx) {
+            Ok(()) => {
+                ctx.state.frames.push(frame);
+                return;
+            }
+            Err(()) => result = Err(()),
+        }
+    }
+    let result = result.and_then(|lookahead| finish_root(ctx, lookahead));
+    end_document(ctx, result);
+}
+
+// does the next bit of the parse: starts a document, or deals with the next token in
+// the innermost array or object. every step only sends a few tags (save for a held back
+// object that just closed), so a PullParser never has to queue up much
+fn parse_step(ctx: &mut Context) {
+    let mut frame = match ctx.state.frames.pop() {
+        Some(frame) => frame,
+        None => {
+            ctx.state.error_line = None;
+            let result = match begin_document(ctx) {
+                Ok(Some(Value::Opened(frame))) => {
+                    ctx.state.frames.push(frame);
                     return;
                 }
-                let result = try_value(&token_pair, &mut token_iter, &tag_sink);
-                if result.is_err() {
-                    // we _should have_ already handled it
+                Ok(Some(Value::Done(lookahead))) => finish_root(ctx, lookahead),
+                Ok(None) => Ok(()),
+                Err(()) => Err(()),
+            };
+            return end_document(ctx, result);
+        }
+    };
+    match frame.step(ctx) {
+        Ok(Flow::Continue) => ctx.state.frames.push(frame),
+        Ok(Flow::Opened(inner)) => {
+            ctx.state.frames.push(frame);
+            ctx.state.frames.push(inner);
+        }
+        Ok(Flow::Closed) => value_done(ctx, Ok(None)),
+        Err(()) => value_done(ctx, Err(())),
+    }
+}
+
+// runs the parser on its own thread, handing every tag to the sink
+fn parse_in_background<I, F>(tokens: I, options: ParserOptions, mut sink: F)
+where
+    I: Iterator<Item = TokenPair> + Send + 'static,
+    F: FnMut(Tag, &TagInfo) -> Result<(), ()> + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut tokens = tokens;
+        let mut state = DocumentState::default();
+        let mut ctx = Context {
+            tokens: &mut tokens,
+            sink: &mut sink,
+            options: &options,
+            state: &mut state,
+        };
+        while !ctx.state.finished {
+            parse_step(&mut ctx);
+        }
+    });
+}
+
+pub fn parse(token_source: TokenSource, tag_sink: TagSource) {
+    parse_with_options(token_source, tag_sink, ParserOptions::default());
+}
+
+pub fn parse_with_options(token_source: TokenSource, tag_sink: TagSource, options: ParserOptions) {
+    parse_in_background(token_source.into_iter(), options, move |tag, _| {
+        tag_sink.send(tag).map_err(|_| ())
+    });
+}
+
+// same as parse(...), but every tag comes with its position in the source
+pub fn parse_positioned(token_source: TokenSource, tag_sink: PositionedTagSource) {
+    parse_in_background(token_source.into_iter(), ParserOptions::default(), move |tag, info| {
+        tag_sink.send((tag, info.clone())).map_err(|_| ())
+    });
+}
+
+// the batched counterpart of parse_with_options(...), to go with lexer::lex_batches(...).
+// the tags go out in batches of up to batch_size, and whatever is ready goes out before
+// waiting on the next batch of tokens. pipeline::BatchReceiver::new(...) turns the
+// receiving end into a TagSink
+pub fn parse_batches(
+    token_batches: TokenBatchSource,
+    tag_batches: mpsc::Sender<Vec<Tag>>,
+    options: ParserOptions,
+    batch_size: usize,
+) {
+    let batcher = pipeline::Batcher::new(
+        pipeline::Outgoing::Batches(pipeline::Sender::Unbounded(tag_batches)),
+        batch_size,
+        pipeline::CancelHandle::default(),
+    );
+    let batcher = std::sync::Arc::new(std::sync::Mutex::new(batcher));
+    let tokens = TokenBatches {
+        batches: token_batches,
+        current: Vec::new().into_iter(),
+        batcher: batcher.clone(),
+    };
+    // the last batch goes out when the batcher is dropped at the end of the parse
+    parse_in_background(tokens, options, move |tag, _| {
+        batcher.lock().map_err(|_| ())?.push(tag)
+    });
+}
+
+struct TokenBatches {
+    batches: TokenBatchSource,
+    current: std::vec::IntoIter<TokenPair>,
+    batcher: std::sync::Arc<std::sync::Mutex<pipeline::Batcher<Tag>>>,
+}
+
+impl Iterator for TokenBatches {
+    type Item = TokenPair;
+
+    fn next(&mut self) -> Option<TokenPair> {
+        loop {
+            if let Some(pair) = self.current.next() {
+                return Some(pair);
+            }
+            // the next batch might take a while
+            if let Ok(mut batcher) = self.batcher.lock() {
+                if batcher.flush().is_err() {
+                    return None;
                 }
-                return;
             }
-            None => {}
+            self.current = self.batches.recv().ok()?.into_iter();
         }
-    });
+    }
+}
+
+fn lexer_for(options: &ParserOptions) -> lexer::Lexer {
+    let mut lexer = lexer::Lexer::with_options(options.lexer.clone());
+    lexer.set_recover(options.recover);
+    // one broken document shouldn't take the others down with it
+    match options.documents {
+        DocumentMode::Single => {}
+        DocumentMode::Multiple | DocumentMode::Lines => lexer.set_resume_at_next_line(true),
+        DocumentMode::Records => lexer.set_record_separators(true),
+    }
+    lexer
+}
+
+// lexes and parses the whole source on the caller's thread, handing every tag to the
+// sink as soon as it's ready. the background parses in lib.rs are built on this
+pub(crate) fn parse_into_sink<S: lexer::ChunkSource>(
+    source: S,
+    options: ParserOptions,
+    sink: &mut dyn FnMut(Tag, &TagInfo) -> Result<(), ()>,
+) {
+    let mut tokens = lexer::Tokens::with_lexer(lexer_for(&options), source);
+    let mut state = DocumentState::default();
+    let mut ctx = Context {
+        tokens: &mut tokens,
+        sink,
+        options: &options,
+        state: &mut state,
+    };
+    while !ctx.state.finished {
+        parse_step(&mut ctx);
+    }
+}
+
+// runs the lexer and the parser on the caller's thread, no channels involved. next()
+// only parses as far as it takes to get the next tag, so the source is read as the tags
+// are asked for. the one exception is an object held back under DuplicateKeys::LastWins,
+// whose tags all come at once when it closes
+pub struct PullParser<S> {
+    tokens: lexer::Tokens<S>,
+    tags: VecDeque<PositionedTag>,
+    state: DocumentState,
+    options: ParserOptions,
+}
+
+impl<S: lexer::ChunkSource> PullParser<S> {
+    pub fn new(source: S) -> PullParser<S> {
+        Self::with_options(source, ParserOptions::default())
+    }
+
+    pub fn with_options(source: S, options: ParserOptions) -> PullParser<S> {
+        PullParser {
+            tokens: lexer::Tokens::with_lexer(lexer_for(&options), source),
+            tags: VecDeque::new(),
+            state: DocumentState::default(),
+            options,
+        }
+    }
+
+    // like next(), but keeps the position of the tag around
+    pub fn next_positioned(&mut self) -> Option<PositionedTag> {
+        while self.tags.is_empty() && !self.state.finished {
+            self.fill();
+        }
+        self.tags.pop_front()
+    }
+
+    pub fn into_positioned(self) -> Positioned<S> {
+        Positioned { parser: self }
+    }
+
+    // the lines the lexer has seen last, if LexerOptions::recent_lines asked for them.
+    // once an error shows up, the line it's on is among them, unless the chunk it came
+    // in went on for more than recent_lines lines after it
+    pub fn recent_lines(&self) -> Option<&lexer::RecentLines> {
+        self.tokens.lexer().recent_lines()
+    }
+
+    fn fill(&mut self) {
+        let tags = &mut self.tags;
+        let mut sink = |tag, info: &TagInfo| {
+            tags.push_back((tag, info.clone()));
+            Ok(())
+        };
+        parse_step(&mut Context {
+            tokens: &mut self.tokens,
+            sink: &mut sink,
+            options: &self.options,
+            state: &mut self.state,
+        });
+    }
+}
+
+impl<S: lexer::ChunkSource> Iterator for PullParser<S> {
+    type Item = Tag;
+
+    fn next(&mut self) -> Option<Tag> {
+        self.next_positioned().map(|(tag, _)| tag)
+    }
+}
+
+pub struct Positioned<S> {
+    parser: PullParser<S>,
+}
+
+impl<S: lexer::ChunkSource> Iterator for Positioned<S> {
+    type Item = PositionedTag;
+
+    fn next(&mut self) -> Option<PositionedTag> {
+        self.parser.next_positioned()
+    }
 }
@@ -0,0 +1,165 @@
+// keeps track of where in the document each tag is, so consumers don't need their own
+// stack of keys and indices. paths come out as RFC 6901 JSON Pointers like /config/2
+use crate::{parser::Tag, pipeline};
+use std::{fmt, sync::Arc};
+
+#[derive(Clone, PartialEq, std::fmt::Debug)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+impl fmt::Display for PathSegment {
+    // one reference token of a pointer, without the '/' in front. '~' and '/' are the
+    // only characters a pointer escapes
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PathSegment::Key(key) => write!(f, "{}", key.replace('~', "~0").replace('/', "~1")),
+            PathSegment::Index(index) => write!(f, "{}", index),
+        }
+    }
+}
+
+// the pointer for a list of segments. the root is the empty string
+pub fn pointer(segments: &[PathSegment]) -> String {
+    segments.iter().map(|segment| format!("/{}", segment)).collect()
+}
+
+enum Container {
+    // the index the next element gets
+    Array(usize),
+    Object,
+}
+
+// follows a tag stream and knows the path of the last tag it was given:
+// - a value, or the Begin... of an array or object, is at the path of the value
+// - an ObjectKey is at the path of the value that follows it
+// - an End... is at the path of the array or object it closes
+// - comments and errors are wherever the parser was when they came up
+// - an EndDocument starts over at the root
+#[derive(Default)]
+pub struct PathTracker {
+    segments: Vec<PathSegment>,
+    containers: Vec<Container>,
+    // set once the last tag finished a value inside an array or object. its segment
+    // goes away with the next tag
+    value_done: bool,
+    // the pointer for the segments as they are, if it's been asked for since they last
+    // changed
+    shared: Option<Arc<str>>,
+}
+
+impl PathTracker {
+    pub fn new() -> PathTracker {
+        Self::default()
+    }
+
+    pub fn update(&mut self, tag: &Tag) {
+        if std::mem::replace(&mut self.value_done, false) {
+            self.segments.pop();
+            self.shared = None;
+        }
+        match tag {
+            Tag::ObjectKey(key) => {
+                // a key whose value got lost still has its segment around
+                if self.in_member() {
+                    self.segments.pop();
+                }
+                self.segments.push(PathSegment::Key(key.clone()));
+                self.shared = None;
+            }
+            Tag::BeginArray => {
+                self.begin_value();
+                self.containers.push(Container::Array(0));
+            }
+            Tag::BeginObject => {
+                self.begin_value();
+                self.containers.push(Container::Object);
+            }
+            Tag::EndArray | Tag::EndObject => {
+                self.containers.pop();
+                self.value_done = self.in_member();
+            }
+            Tag::StringLiteral(_)
+            | Tag::Number(_)
+            | Tag::TypedNumber(_, _)
+            | Tag::TrueLiteral
+            | Tag::FalseLiteral
+            | Tag::NullLiteral
+            | Tag::Recovered => {
+                self.begin_value();
+                self.value_done = self.in_member();
+            }
+            Tag::EndDocument(_) => {
+                self.segments.clear();
+                self.containers.clear();
+                self.shared = None;
+            }
+            Tag::Comment(_) | Tag::Error(_, _) => {}
+        }
+    }
+
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.segments
+    }
+
+    pub fn depth(&self) -> usize {
+        self.containers.len()
+    }
+
+    pub fn pointer(&self) -> String {
+        pointer(&self.segments)
+    }
+
+    // the same as pointer(), but only built again once the path changes. tags at the
+    // same path share it
+    pub fn shared_pointer(&mut self) -> Arc<str> {
+        let segments = &self.segments;
+        self.shared
+            .get_or_insert_with(|| Arc::from(pointer(segments)))
+            .clone()
+    }
+
+    // whether the last segment belongs to an element or member of the innermost
+    // array or object, rather than to the array or object itself
+    fn in_member(&self) -> bool {
+        !self.containers.is_empty() && self.segments.len() == self.containers.len()
+    }
+
+    // array elements get their index here. object members already got their key
+    fn begin_value(&mut self) {
+        if let Some(Container::Array(next)) = self.containers.last_mut() {
+            self.segments.push(PathSegment::Index(*next));
+            self.shared = None;
+            *next += 1;
+        }
+    }
+}
+
+// hands out every tag along with its path
+pub struct WithPaths<I> {
+    tags: I,
+    tracker: PathTracker,
+}
+
+impl<I: Iterator<Item = Tag>> Iterator for WithPaths<I> {
+    type Item = PathTag;
+
+    fn next(&mut self) -> Option<PathTag> {
+        let tag = self.tags.next()?;
+        self.tracker.update(&tag);
+        Some((tag, self.tracker.shared_pointer()))
+    }
+}
+
+// the pointer is shared by all the tags at the same path
+pub type PathTag = (Tag, Arc<str>);
+pub type PathTagSink = pipeline::BatchReceiver<PathTag>;
+
+// works on a TagSink, a PullParser or any other iterator of tags
+pub fn with_paths<I: IntoIterator<Item = Tag>>(tags: I) -> WithPaths<I::IntoIter> {
+    WithPaths {
+        tags: tags.into_iter(),
+        tracker: PathTracker::new(),
+    }
+}
@@ -0,0 +1,456 @@
+use crate::{
+    error::{Error, ErrorKind},
+    lexer, parser,
+    parser::{Tag, TagInfo},
+};
+use std::{
+    any::Any,
+    cell::{Cell, RefCell},
+    fmt,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex, PoisonError, Weak,
+    },
+    thread,
+};
+
+// tells a background parse to stop. it can be cloned and handed to other threads, and
+// stays valid after the parse is over, when cancelling it does nothing
+#[derive(Clone, Default)]
+pub struct CancelHandle {
+    cancelled: Arc<AtomicBool>,
+    // the sink the parse sends to, for as long as anybody has it. a parser that's
+    // waiting for room in it only gets going again once something is taken out
+    sink: Option<Weak<dyn Drain>>,
+    // wake up a source that's waiting for its next chunk, see ChunkSource::wake_on_cancel
+    wakers: Arc<Mutex<Vec<Waker>>>,
+}
+
+type Waker = Box<dyn Fn() + Send>;
+
+impl CancelHandle {
+    // whatever tags are still waiting in the sink are thrown away, unless the consumer
+    // is busy taking them out anyway
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+        if let Some(sink) = self.sink.as_ref().and_then(Weak::upgrade) {
+            sink.drain();
+        }
+        let wakers = self.wakers.lock().unwrap_or_else(PoisonError::into_inner);
+        for wake in wakers.iter() {
+            wake();
+        }
+    }
+
+    // runs wake when the parse is cancelled, or right away if it already is
+    pub(crate) fn on_cancel(&self, wake: impl Fn() + Send + 'static) {
+        let mut wakers = self.wakers.lock().unwrap_or_else(PoisonError::into_inner);
+        if self.is_cancelled() {
+            wake();
+        }
+        wakers.push(Box::new(wake));
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+impl fmt::Debug for CancelHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CancelHandle")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+trait Drain: Send + Sync {
+    fn drain(&self);
+}
+
+impl<T: Send> Drain for Mutex<Incoming<T>> {
+    fn drain(&self) {
+        // if it's locked, the consumer is in the middle of a recv(...) and makes room
+        // on its own
+        if let Ok(incoming) = self.try_lock() {
+            match &*incoming {
+                Incoming::Batches(batches) => while batches.try_recv().is_ok() {},
+                Incoming::Items(items) => while items.try_recv().is_ok() {},
+            }
+        }
+    }
+}
+
+// comes with every background parse that can be cancelled. dropping it leaves the parse
+// running until it's done or nobody is listening anymore, same as a JoinHandle
+pub struct ParseHandle {
+    cancel: CancelHandle,
+    thread: thread::JoinHandle<Result<(), Error>>,
+}
+
+impl ParseHandle {
+    // stops both the lexer and the parser. the lexer stops before its next chunk and
+    // the parser before its next tag, so a few more tags may still come through. the
+    // ones that were waiting in the sink are thrown away
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    // waits for the background thread to exit. syntax errors are in the tag stream and
+    // don't count as failures here, but a source that couldn't be read does, along with
+    // Error::Cancelled if the parse was cut short and Error::Internal for anything that
+    // should never happen. a consumer that stopped listening isn't a failure either
+    pub fn join(self) -> Result<(), Error> {
+        self.thread.join().unwrap_or_else(|payload| Err(internal(payload)))
+    }
+}
+
+fn internal(payload: Box<dyn Any + Send>) -> Error {
+    let message = match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => String::from("the parser thread panicked"),
+        },
+    };
+    Error::Internal(message)
+}
+
+// how many tags can be waiting in the sink by default before the parser has to wait
+// for the consumer to catch up
+pub const DEFAULT_TAG_CAPACITY: usize = 1024;
+// tags travel to the sink in batches of up to this many, which saves a lot of channel
+// traffic when the tags are small
+pub const DEFAULT_BATCH_SIZE: usize = 256;
+
+#[derive(Clone, std::fmt::Debug)]
+pub struct PipelineOptions {
+    pub parser: parser::ParserOptions,
+    // how many tags can be waiting in the sink before the parser stops and waits for
+    // the consumer. that's what keeps memory bounded when the consumer is slower than
+    // the parser. None lets the sink grow without limit
+    pub tag_capacity: Option<usize>,
+    // a batch goes out once it's full, or earlier if the parser has to wait for more of
+    // the source first. 1 sends every tag on its own
+    pub batch_size: usize,
+}
+
+impl Default for PipelineOptions {
+    fn default() -> Self {
+        PipelineOptions {
+            parser: parser::ParserOptions::default(),
+            tag_capacity: Some(DEFAULT_TAG_CAPACITY),
+            batch_size: DEFAULT_BATCH_SIZE,
+        }
+    }
+}
+
+impl From<parser::ParserOptions> for PipelineOptions {
+    fn from(parser: parser::ParserOptions) -> Self {
+        PipelineOptions {
+            parser,
+            ..Default::default()
+        }
+    }
+}
+
+pub(crate) enum Sender<T> {
+    Unbounded(mpsc::Sender<T>),
+    Bounded(mpsc::SyncSender<T>),
+}
+
+impl<T> Sender<T> {
+    // a full sink is waited on until there's room. cancelling empties it, so a parse
+    // that got cancelled while the consumer wasn't reading anymore can still exit. Err
+    // once nobody is listening anymore
+    fn send(&self, item: T) -> Result<(), ()> {
+        match self {
+            Sender::Unbounded(tx) => tx.send(item).map_err(|_| ()),
+            Sender::Bounded(tx) => tx.send(item).map_err(|_| ()),
+        }
+    }
+}
+
+// where a Batcher sends to. with a batch size of 1 the items go out as they are, which
+// saves a Vec for every one of them
+pub(crate) enum Outgoing<T> {
+    Batches(Sender<Vec<T>>),
+    Items(Sender<T>),
+}
+
+// collects items until there's a batch worth sending
+pub(crate) struct Batcher<T> {
+    tx: Outgoing<T>,
+    batch: Vec<T>,
+    size: usize,
+    cancel: CancelHandle,
+}
+
+impl<T> Batcher<T> {
+    pub(crate) fn new(tx: Outgoing<T>, size: usize, cancel: CancelHandle) -> Batcher<T> {
+        let size = size.max(1);
+        let batch = match tx {
+            Outgoing::Batches(_) => Vec::with_capacity(size),
+            Outgoing::Items(_) => Vec::new(),
+        };
+        Batcher {
+            tx,
+            batch,
+            size,
+            cancel,
+        }
+    }
+
+    pub(crate) fn push(&mut self, item: T) -> Result<(), ()> {
+        if let Outgoing::Items(tx) = &self.tx {
+            return tx.send(item);
+        }
+        self.batch.push(item);
+        if self.batch.len() >= self.size {
+            self.flush()
+        } else {
+            Ok(())
+        }
+    }
+
+    pub(crate) fn flush(&mut self) -> Result<(), ()> {
+        match &self.tx {
+            Outgoing::Batches(tx) if !self.batch.is_empty() => {
+                let batch = std::mem::replace(&mut self.batch, Vec::with_capacity(self.size));
+                tx.send(batch)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// whatever is left goes out at the end, unless the parse was cancelled
+impl<T> Drop for Batcher<T> {
+    fn drop(&mut self) {
+        if !self.cancel.is_cancelled() {
+            let _ = self.flush();
+        }
+    }
+}
+
+// sits between the real source and the lexer. whatever tags are ready go out before
+// the source gets asked for more, since that might take a while, and once the parse is
+// cancelled the lexer doesn't get anything else. as far as it knows, the source just
+// ended. a channel that has nothing to say gets woken up for that
+struct Feeder<S, T> {
+    source: S,
+    batcher: Rc<RefCell<Batcher<T>>>,
+    cancel: CancelHandle,
+    // set if the source didn't get to say it had ended
+    cut_short: Rc<Cell<bool>>,
+}
+
+impl<S: lexer::ChunkSource, T> lexer::ChunkSource for Feeder<S, T> {
+    fn feed(&mut self, lexer: &mut lexer::Lexer) -> bool {
+        if self.cancel.is_cancelled() {
+            self.cut_short.set(true);
+            return false;
+        }
+        if self.batcher.borrow_mut().flush().is_err() {
+            return false;
+        }
+        let more = self.source.feed(lexer);
+        if !more && self.cancel.is_cancelled() {
+            self.cut_short.set(true);
+        }
+        more
+    }
+}
+
+fn channel<T>(capacity: Option<usize>) -> (Sender<T>, mpsc::Receiver<T>) {
+    match capacity {
+        Some(capacity) => {
+            let (tx, rx) = mpsc::sync_channel(capacity);
+            (Sender::Bounded(tx), rx)
+        }
+        None => {
+            let (tx, rx) = mpsc::channel();
+            (Sender::Unbounded(tx), rx)
+        }
+    }
+}
+
+// lexes and parses on a new thread. every tag goes through wrap(...) and on to the
+// returned sink as soon as its batch is done
+pub(crate) fn spawn<S, T, F>(
+    source: S,
+    options: PipelineOptions,
+    mut wrap: F,
+) -> (BatchReceiver<T>, ParseHandle)
+where
+    S: lexer::ChunkSource + Send + 'static,
+    T: Send + 'static,
+    F: FnMut(Tag, &TagInfo) -> T + Send + 'static,
+{
+    // the capacity counts tags, the channel counts batches
+    let batch_size = match options.tag_capacity {
+        Some(capacity) => options.batch_size.min(capacity).max(1),
+        None => options.batch_size.max(1),
+    };
+    let capacity = options.tag_capacity.map(|capacity| (capacity / batch_size).max(1));
+    let (tx, rx) = if batch_size == 1 {
+        let (tx, rx) = channel(capacity);
+        (Outgoing::Items(tx), Incoming::Items(rx))
+    } else {
+        let (tx, rx) = channel(capacity);
+        (Outgoing::Batches(tx), Incoming::Batches(rx))
+    };
+    let rx = Arc::new(Mutex::new(rx));
+    let sink = Arc::downgrade(&rx);
+    let cancel = CancelHandle {
+        sink: Some(sink as Weak<dyn Drain>),
+        ..Default::default()
+    };
+    let flag = cancel.clone();
+    let thread = thread::spawn(move || {
+        let mut source = source;
+        source.wake_on_cancel(&flag);
+        let batcher = Rc::new(RefCell::new(Batcher::new(tx, batch_size, flag.clone())));
+        let cut_short = Rc::new(Cell::new(false));
+        let source = Feeder {
+            source,
+            batcher: batcher.clone(),
+            cancel: flag.clone(),
+            cut_short: cut_short.clone(),
+        };
+        let mut failure = None;
+        let mut sink = |tag: Tag, info: &TagInfo| {
+            if flag.is_cancelled() {
+                cut_short.set(true);
+                return Err(());
+            }
+            if let Tag::Error(error, _) = &tag {
+                if failure.is_none()
+                    && matches!(error.kind, ErrorKind::ReadFailed(_, _) | ErrorKind::Internal(_))
+                {
+                    failure = Error::from_tag(&tag);
+                }
+            }
+            batcher.borrow_mut().push(wrap(tag, info))
+        };
+        parser::parse_into_sink(source, options.parser, &mut sink);
+        // a parse that got to the end before it was cancelled wasn't cut short, and a
+        // failure that had already happened is what stopped it
+        match failure {
+            Some(failure) => Err(failure),
+            None if cut_short.get() => Err(Error::Cancelled),
+            None => Ok(()),
+        }
+    });
+    let tags = BatchReceiver {
+        batches: rx,
+        current: RefCell::new(Vec::new().into_iter()),
+    };
+    (tags, ParseHandle { cancel, thread })
+}
+
+// the receiving end of a channel that carries batches, handing them out one item at a
+// time. it works like an mpsc::Receiver as far as recv(), try_recv() and iterating go.
+// parser::TagSink is one of these
+pub struct BatchReceiver<T> {
+    // shared with the CancelHandle of the parse, which empties it when cancelling
+    batches: Arc<Mutex<Incoming<T>>>,
+    current: RefCell<std::vec::IntoIter<T>>,
+}
+
+// the other end of an Outgoing
+pub(crate) enum Incoming<T> {
+    Batches(mpsc::Receiver<Vec<T>>),
+    Items(mpsc::Receiver<T>),
+}
+
+impl<T> BatchReceiver<T> {
+    pub fn new(batches: mpsc::Receiver<Vec<T>>) -> BatchReceiver<T> {
+        BatchReceiver {
+            batches: Arc::new(Mutex::new(Incoming::Batches(batches))),
+            current: RefCell::new(Vec::new().into_iter()),
+        }
+    }
+
+    // blocks until there's an item or the sending side is gone
+    pub fn recv(&self) -> Result<T, mpsc::RecvError> {
+        let mut current = self.current.borrow_mut();
+        loop {
+            if let Some(item) = current.next() {
+                return Ok(item);
+            }
+            match &*self.batches.lock().unwrap_or_else(PoisonError::into_inner) {
+                Incoming::Batches(batches) => *current = batches.recv()?.into_iter(),
+                Incoming::Items(items) => return items.recv(),
+            }
+        }
+    }
+
+    pub fn try_recv(&self) -> Result<T, mpsc::TryRecvError> {
+        let mut current = self.current.borrow_mut();
+        loop {
+            if let Some(item) = current.next() {
+                return Ok(item);
+            }
+            match &*self.batches.lock().unwrap_or_else(PoisonError::into_inner) {
+                Incoming::Batches(batches) => *current = batches.try_recv()?.into_iter(),
+                Incoming::Items(items) => return items.try_recv(),
+            }
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { receiver: self }
+    }
+}
+
+pub struct Iter<'a, T> {
+    receiver: &'a BatchReceiver<T>,
+}
+
+impl<T> Iterator for Iter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv().ok()
+    }
+}
+
+pub struct IntoIter<T> {
+    receiver: BatchReceiver<T>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl<T> IntoIterator for BatchReceiver<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { receiver: self }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a BatchReceiver<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
@@ -0,0 +1,382 @@
+// picks values out of a tag stream with a JSONPath expression, so one field can be
+// pulled out of a huge document without building anything for the rest of it. only the
+// subset that can be decided as the tags go by is supported:
+// - $ for the root
+// - .name or ['name'] for a member
+// - .* or [*] for every member or element
+// - [2] for an element
+// - [start:end:step] for a slice, any part of which can be left out
+// - ..name, ..* or ..[2] for the same at any depth below
+// negative indices and filters would need to see the rest of the array first
+use crate::{
+    error::Error,
+    parser::Tag,
+    path::{self, PathSegment},
+};
+use std::collections::VecDeque;
+
+#[derive(Clone, PartialEq, std::fmt::Debug)]
+enum Matcher {
+    Key(String),
+    Wildcard,
+    Index(usize),
+    Slice {
+        start: usize,
+        end: Option<usize>,
+        step: usize,
+    },
+}
+
+impl Matcher {
+    fn matches(&self, segment: &PathSegment) -> bool {
+        match (self, segment) {
+            (Matcher::Wildcard, _) => true,
+            (Matcher::Key(name), PathSegment::Key(key)) => name == key,
+            (Matcher::Index(index), PathSegment::Index(at)) => index == at,
+            (Matcher::Slice { start, end, step }, PathSegment::Index(at)) => {
+                at >= start && !matches!(end, Some(end) if at >= end) && (at - start) % step == 0
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, std::fmt::Debug)]
+struct Step {
+    matcher: Matcher,
+    // set for the steps after a '..', which match at any depth
+    descendant: bool,
+}
+
+// a parsed select expression
+#[derive(Clone, PartialEq, std::fmt::Debug)]
+pub struct Selector {
+    steps: Vec<Step>,
+}
+
+impl Selector {
+    pub fn parse(expression: &str) -> Result<Selector, Error> {
+        let mut rest = expression
+            .strip_prefix('$')
+            .ok_or_else(|| invalid("a selector starts with '$'"))?;
+        let mut steps = Vec::new();
+        while !rest.is_empty() {
+            let descendant = rest.starts_with("..");
+            if descendant {
+                rest = &rest[2..];
+            } else if let Some(after) = rest.strip_prefix('.') {
+                rest = after;
+                if rest.starts_with('[') {
+                    return Err(invalid("expected a name after '.'"));
+                }
+            } else if !rest.starts_with('[') {
+                return Err(invalid(&format!("unexpected '{}'", rest)));
+            }
+            let matcher = if let Some(after) = rest.strip_prefix('[') {
+                let end = after.find(']').ok_or_else(|| invalid("a '[' without a ']'"))?;
+                // a ']' in a quoted name isn't the end of it
+                let end = match after.chars().next() {
+                    Some(quote @ ('\'' | '"')) => {
+                        let closing = closing_quote(after, quote)?;
+                        if !after[closing + 1..].starts_with(']') {
+                            return Err(invalid("expected a ']' after the name"));
+                        }
+                        closing + 1
+                    }
+                    _ => end,
+                };
+                rest = &after[end + 1..];
+                parse_bracket(&after[..end])?
+            } else {
+                let end = rest.find(['.', '[']).unwrap_or(rest.len());
+                let name = &rest[..end];
+                rest = &rest[end..];
+                match name {
+                    "" => return Err(invalid("expected a name")),
+                    "*" => Matcher::Wildcard,
+                    name => Matcher::Key(name.to_string()),
+                }
+            };
+            steps.push(Step {
+                matcher,
+                descendant,
+            });
+        }
+        Ok(Selector { steps })
+    }
+
+    // which steps can still match below a value that's reached with the given steps
+    // active, once it turns out to be at segment
+    fn advance(&self, states: &[usize], segment: &PathSegment) -> Vec<usize> {
+        let mut next = Vec::new();
+        for &state in states {
+            let step = match self.steps.get(state) {
+                Some(step) => step,
+                None => continue,
+            };
+            if step.descendant && !next.contains(&state) {
+                next.push(state);
+            }
+            if step.matcher.matches(segment) && !next.contains(&(state + 1)) {
+                next.push(state + 1);
+            }
+        }
+        next
+    }
+
+    fn matched(&self, states: &[usize]) -> bool {
+        states.contains(&self.steps.len())
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::Selector(message.to_string())
+}
+
+// where the quoted name at the start of text ends
+fn closing_quote(text: &str, quote: char) -> Result<usize, Error> {
+    let mut escaped = false;
+    for (i, c) in text.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            c if c == quote => return Ok(i),
+            _ => {}
+        }
+    }
+    Err(invalid("a name without its closing quote"))
+}
+
+// what's between [ and ]
+fn parse_bracket(inside: &str) -> Result<Matcher, Error> {
+    let inside = inside.trim();
+    if inside == "*" {
+        return Ok(Matcher::Wildcard);
+    }
+    if let Some(quote @ ('\'' | '"')) = inside.chars().next() {
+        let name = inside[1..]
+            .strip_suffix(quote)
+            .ok_or_else(|| invalid("a name without its closing quote"))?;
+        let mut key = String::with_capacity(name.len());
+        let mut chars = name.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some(c @ ('\\' | '\'' | '"')) => key.push(c),
+                    _ => return Err(invalid("only \\\\, \\' and \\\" can be escaped in a name")),
+                },
+                c if c == quote => return Err(invalid("an unescaped quote in a name")),
+                c => key.push(c),
+            }
+        }
+        return Ok(Matcher::Key(key));
+    }
+    if inside.starts_with('?') || inside.starts_with('(') {
+        return Err(invalid("filters and expressions aren't supported"));
+    }
+    if inside.contains(',') {
+        return Err(invalid("unions aren't supported"));
+    }
+    let parts: Vec<&str> = inside.split(':').map(str::trim).collect();
+    let index = |part: &str| -> Result<Option<usize>, Error> {
+        if part.is_empty() {
+            return Ok(None);
+        }
+        if part.starts_with('-') {
+            return Err(invalid(
+                "negative indices need the length of the array, which a stream doesn't know \
+                 until it's over",
+            ));
+        }
+        part.parse()
+            .map(Some)
+            .map_err(|_| invalid(&format!("'{}' isn't an index", part)))
+    };
+    match parts[..] {
+        [only] => match index(only)? {
+            Some(at) => Ok(Matcher::Index(at)),
+            None => Err(invalid("empty brackets")),
+        },
+        [start, end] | [start, end, ""] => Ok(Matcher::Slice {
+            start: index(start)?.unwrap_or(0),
+            end: index(end)?,
+            step: 1,
+        }),
+        [start, end, step] => match index(step)? {
+            Some(0) => Err(invalid("a slice's step can't be 0")),
+            step => Ok(Matcher::Slice {
+                start: index(start)?.unwrap_or(0),
+                end: index(end)?,
+                step: step.unwrap_or(1),
+            }),
+        },
+        _ => Err(invalid("a slice has at most three parts")),
+    }
+}
+
+#[derive(PartialEq, std::fmt::Debug)]
+pub enum Selected {
+    // comes before the tags of every match: the JSON Pointer of the value, like
+    // /items/3/price
+    Path(String),
+    Tag(Tag),
+}
+
+enum Kind {
+    // the index the next element gets
+    Array(usize),
+    Object,
+}
+
+// an array or object that might still have a match somewhere below it
+struct Frame {
+    kind: Kind,
+    // how it's reached from the one around it. None for the root
+    segment: Option<PathSegment>,
+    // the steps that are still in play for what's inside of it
+    states: Vec<usize>,
+}
+
+pub struct Select<I> {
+    tags: I,
+    selector: Selector,
+    frames: Vec<Frame>,
+    // the key of the member whose value comes next
+    key: Option<String>,
+    // how deep into a match, or into something that can't have one, we are. the tags
+    // of the former all go out, the latter are only counted
+    matched: usize,
+    skipped: usize,
+    queued: VecDeque<Selected>,
+}
+
+impl<I: Iterator<Item = Tag>> Select<I> {
+    // what's below a value the selector has nothing to do with anymore is only counted
+    // until it's over. nothing gets matched, compared or kept there
+    fn skip(&mut self, tag: &Tag) {
+        match tag {
+            Tag::BeginArray | Tag::BeginObject => self.skipped += 1,
+            Tag::EndArray | Tag::EndObject => self.skipped -= 1,
+            _ => {}
+        }
+    }
+
+    fn forward(&mut self, tag: Tag) -> Selected {
+        match tag {
+            Tag::BeginArray | Tag::BeginObject => self.matched += 1,
+            Tag::EndArray | Tag::EndObject => self.matched -= 1,
+            _ => {}
+        }
+        Selected::Tag(tag)
+    }
+
+    // a new document starts over at the root, even if an error cut the last one short
+    fn reset(&mut self) {
+        self.frames.clear();
+        self.key = None;
+        self.matched = 0;
+        self.skipped = 0;
+    }
+
+    fn value(&mut self, tag: Tag) -> Option<Selected> {
+        let (segment, states) = match self.frames.last_mut() {
+            None => (None, vec![0]),
+            Some(frame) => {
+                let segment = match &mut frame.kind {
+                    Kind::Array(next) => {
+                        *next += 1;
+                        PathSegment::Index(*next - 1)
+                    }
+                    Kind::Object => PathSegment::Key(self.key.take().unwrap_or_default()),
+                };
+                let states = self.selector.advance(&frame.states, &segment);
+                (Some(segment), states)
+            }
+        };
+        let container = match tag {
+            Tag::BeginArray => Some(Kind::Array(0)),
+            Tag::BeginObject => Some(Kind::Object),
+            _ => None,
+        };
+        if self.selector.matched(&states) {
+            let mut segments: Vec<PathSegment> =
+                self.frames.iter().filter_map(|frame| frame.segment.clone()).collect();
+            segments.extend(segment);
+            let forwarded = self.forward(tag);
+            self.queued.push_back(forwarded);
+            return Some(Selected::Path(path::pointer(&segments)));
+        }
+        match container {
+            Some(_) if states.is_empty() => self.skipped = 1,
+            Some(kind) => self.frames.push(Frame {
+                kind,
+                segment,
+                states,
+            }),
+            None => {}
+        }
+        None
+    }
+}
+
+impl<I: Iterator<Item = Tag>> Iterator for Select<I> {
+    type Item = Selected;
+
+    fn next(&mut self) -> Option<Selected> {
+        if let Some(selected) = self.queued.pop_front() {
+            return Some(selected);
+        }
+        loop {
+            let tag = self.tags.next()?;
+            let selected = match tag {
+                Tag::EndDocument(_) => {
+                    self.reset();
+                    None
+                }
+                Tag::Error(_, _) => Some(Selected::Tag(tag)),
+                tag if self.matched > 0 => Some(self.forward(tag)),
+                tag if self.skipped > 0 => {
+                    self.skip(&tag);
+                    None
+                }
+                tag => match tag {
+                    Tag::ObjectKey(key) => {
+                        self.key = Some(key);
+                        None
+                    }
+                    Tag::EndArray | Tag::EndObject => {
+                        self.frames.pop();
+                        None
+                    }
+                    Tag::Comment(_) => None,
+                    tag => self.value(tag),
+                },
+            };
+            if selected.is_some() {
+                return selected;
+            }
+        }
+    }
+}
+
+// hands out the values the selector matches, each one a Selected::Path followed by all
+// of its tags. errors in the stream go out too, wherever they are. a match inside of
+// another match is already part of it and doesn't come out again
+pub fn select<I: IntoIterator<Item = Tag>>(
+    tags: I,
+    expression: &str,
+) -> Result<Select<I::IntoIter>, Error> {
+    Ok(with_selector(tags, Selector::parse(expression)?))
+}
+
+pub fn with_selector<I: IntoIterator<Item = Tag>>(tags: I, selector: Selector) -> Select<I::IntoIter> {
+    Select {
+        tags: tags.into_iter(),
+        selector,
+        frames: Vec::new(),
+        key: None,
+        matched: 0,
+        skipped: 0,
+        queued: VecDeque::new(),
+    }
+}
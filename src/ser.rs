@@ -0,0 +1,409 @@
+// a serde Serializer that turns any Serialize type into tags, which then go through a
+// writer::TagWriter or into a Vec. only built with the serde feature
+use crate::{
+    error::Error,
+    parser::Tag,
+    writer::{TagWriter, WriterOptions},
+};
+use serde::ser::{self, Serialize};
+use std::{fmt, io::Write};
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        Error::Data(message.to_string(), None)
+    }
+}
+
+// wherever the tags end up
+trait Output {
+    fn push(&mut self, tag: Tag) -> Result<(), Error>;
+}
+
+impl<W: Write> Output for TagWriter<W> {
+    fn push(&mut self, tag: Tag) -> Result<(), Error> {
+        self.write_tag(&tag)
+    }
+}
+
+impl Output for Vec<Tag> {
+    fn push(&mut self, tag: Tag) -> Result<(), Error> {
+        self.push(tag);
+        Ok(())
+    }
+}
+
+pub fn to_writer<W: Write, T: Serialize + ?Sized>(
+    out: W,
+    value: &T,
+    options: WriterOptions,
+) -> Result<W, Error> {
+    let mut writer = TagWriter::with_options(out, options);
+    value.serialize(&mut Serializer { out: &mut writer })?;
+    writer.finish()
+}
+
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String, Error> {
+    into_string(to_writer(Vec::new(), value, WriterOptions::compact())?)
+}
+
+pub fn to_string_pretty<T: Serialize + ?Sized>(value: &T) -> Result<String, Error> {
+    into_string(to_writer(Vec::new(), value, WriterOptions::pretty())?)
+}
+
+// the same tags the parser would hand out for the json of the value
+pub fn to_tags<T: Serialize + ?Sized>(value: &T) -> Result<Vec<Tag>, Error> {
+    let mut tags = Vec::new();
+    value.serialize(&mut Serializer { out: &mut tags })?;
+    Ok(tags)
+}
+
+fn into_string(bytes: Vec<u8>) -> Result<String, Error> {
+    String::from_utf8(bytes).map_err(|e| Error::Internal(e.to_string()))
+}
+
+struct Serializer<'a, O> {
+    out: &'a mut O,
+}
+
+impl<O: Output> Serializer<'_, O> {
+    fn number(&mut self, number: impl ToString) -> Result<(), Error> {
+        self.out.push(Tag::Number(number.to_string()))
+    }
+
+    // json has no NaN or infinity. like most json libraries, they come out as null
+    // formatted as the type it is, so 0.1f32 doesn't come out as 0.10000000149011612
+    fn float<F: fmt::Debug>(&mut self, value: F, finite: bool) -> Result<(), Error> {
+        if finite {
+            self.out.push(Tag::Number(format!("{:?}", value)))
+        } else {
+            self.out.push(Tag::NullLiteral)
+        }
+    }
+
+    // enum variants with data are objects with the variant as the only key
+    fn begin_variant(&mut self, variant: &str) -> Result<(), Error> {
+        self.out.push(Tag::BeginObject)?;
+        self.out.push(Tag::ObjectKey(variant.to_string()))
+    }
+}
+
+impl<'a, 'b, O: Output> ser::Serializer for &'a mut Serializer<'b, O> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Compound<'a, 'b, O>;
+    type SerializeTuple = Compound<'a, 'b, O>;
+    type SerializeTupleStruct = Compound<'a, 'b, O>;
+    type SerializeTupleVariant = Compound<'a, 'b, O>;
+    type SerializeMap = Compound<'a, 'b, O>;
+    type SerializeStruct = Compound<'a, 'b, O>;
+    type SerializeStructVariant = Compound<'a, 'b, O>;
+
+    fn serialize_bool(self, value: bool) -> Result<(), Error> {
+        self.out.push(if value { Tag::TrueLiteral } else { Tag::FalseLiteral })
+    }
+
+    fn serialize_i8(self, value: i8) -> Result<(), Error> {
+        self.number(value)
+    }
+
+    fn serialize_i16(self, value: i16) -> Result<(), Error> {
+        self.number(value)
+    }
+
+    fn serialize_i32(self, value: i32) -> Result<(), Error> {
+        self.number(value)
+    }
+
+    fn serialize_i64(self, value: i64) -> Result<(), Error> {
+        self.number(value)
+    }
+
+    fn serialize_i128(self, value: i128) -> Result<(), Error> {
+        self.number(value)
+    }
+
+    fn serialize_u8(self, value: u8) -> Result<(), Error> {
+        self.number(value)
+    }
+
+    fn serialize_u16(self, value: u16) -> Result<(), Error> {
+        self.number(value)
+    }
+
+    fn serialize_u32(self, value: u32) -> Result<(), Error> {
+        self.number(value)
+    }
+
+    fn serialize_u64(self, value: u64) -> Result<(), Error> {
+        self.number(value)
+    }
+
+    fn serialize_u128(self, value: u128) -> Result<(), Error> {
+        self.number(value)
+    }
+
+    fn serialize_f32(self, value: f32) -> Result<(), Error> {
+        self.float(value, value.is_finite())
+    }
+
+    fn serialize_f64(self, value: f64) -> Result<(), Error> {
+        self.float(value, value.is_finite())
+    }
+
+    fn serialize_char(self, value: char) -> Result<(), Error> {
+        self.out.push(Tag::StringLiteral(value.to_string()))
+    }
+
+    fn serialize_str(self, value: &str) -> Result<(), Error> {
+        self.out.push(Tag::StringLiteral(value.to_string()))
+    }
+
+    fn serialize_bytes(self, value: &[u8]) -> Result<(), Error> {
+        self.out.push(Tag::BeginArray)?;
+        for byte in value {
+            self.number(byte)?;
+        }
+        self.out.push(Tag::EndArray)
+    }
+
+    fn serialize_none(self) -> Result<(), Error> {
+        self.out.push(Tag::NullLiteral)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), Error> {
+        self.out.push(Tag::NullLiteral)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), Error> {
+        self.out.push(Tag::NullLiteral)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<(), Error> {
+        self.out.push(Tag::StringLiteral(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.begin_variant(variant)?;
+        value.serialize(&mut *self)?;
+        self.out.push(Tag::EndObject)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Compound<'a, 'b, O>, Error> {
+        self.out.push(Tag::BeginArray)?;
+        Ok(Compound::new(self, vec![Tag::EndArray]))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Compound<'a, 'b, O>, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Compound<'a, 'b, O>, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Compound<'a, 'b, O>, Error> {
+        self.begin_variant(variant)?;
+        self.out.push(Tag::BeginArray)?;
+        Ok(Compound::new(self, vec![Tag::EndArray, Tag::EndObject]))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Compound<'a, 'b, O>, Error> {
+        self.out.push(Tag::BeginObject)?;
+        Ok(Compound::new(self, vec![Tag::EndObject]))
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Compound<'a, 'b, O>, Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Compound<'a, 'b, O>, Error> {
+        self.begin_variant(variant)?;
+        self.out.push(Tag::BeginObject)?;
+        Ok(Compound::new(self, vec![Tag::EndObject, Tag::EndObject]))
+    }
+}
+
+// an array or object that's being serialized, along with the tags that close it
+struct Compound<'a, 'b, O> {
+    ser: &'a mut Serializer<'b, O>,
+    closers: Vec<Tag>,
+}
+
+impl<'a, 'b, O: Output> Compound<'a, 'b, O> {
+    fn new(ser: &'a mut Serializer<'b, O>, closers: Vec<Tag>) -> Self {
+        Compound { ser, closers }
+    }
+
+    fn element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        value.serialize(&mut *self.ser)
+    }
+
+    // keys have to be strings in json. numbers, chars and the like are turned into one
+    fn key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        let key = match to_tags(key)?.as_slice() {
+            [Tag::StringLiteral(key)] | [Tag::Number(key)] => key.clone(),
+            [Tag::TrueLiteral] => String::from("true"),
+            [Tag::FalseLiteral] => String::from("false"),
+            _ => return Err(ser::Error::custom("object keys must be strings or numbers")),
+        };
+        self.ser.out.push(Tag::ObjectKey(key))
+    }
+
+    fn field<T: Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<(), Error> {
+        self.ser.out.push(Tag::ObjectKey(key.to_string()))?;
+        self.element(value)
+    }
+
+    fn close(self) -> Result<(), Error> {
+        for closer in self.closers {
+            self.ser.out.push(closer)?;
+        }
+        Ok(())
+    }
+}
+
+impl<O: Output> ser::SerializeSeq for Compound<'_, '_, O> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.close()
+    }
+}
+
+impl<O: Output> ser::SerializeTuple for Compound<'_, '_, O> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.close()
+    }
+}
+
+impl<O: Output> ser::SerializeTupleStruct for Compound<'_, '_, O> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.close()
+    }
+}
+
+impl<O: Output> ser::SerializeTupleVariant for Compound<'_, '_, O> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.close()
+    }
+}
+
+impl<O: Output> ser::SerializeMap for Compound<'_, '_, O> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.key(key)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.close()
+    }
+}
+
+impl<O: Output> ser::SerializeStruct for Compound<'_, '_, O> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.close()
+    }
+}
+
+impl<O: Output> ser::SerializeStructVariant for Compound<'_, '_, O> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<(), Error> {
+        self.close()
+    }
+}
@@ -0,0 +1,314 @@
+// the other direction: turns the tags the parser hands out back into JSON. the tags are
+// checked as they come in, so whatever gets written is well-formed
+use crate::{
+    error::Error,
+    parser::{self, Number, OutOfRange, Tag},
+};
+use std::io::Write;
+
+#[derive(Clone, std::fmt::Debug, Default)]
+pub struct WriterOptions {
+    // what to indent each level with. None writes everything on one line without any
+    // whitespace at all
+    pub indent: Option<String>,
+}
+
+impl WriterOptions {
+    pub fn compact() -> WriterOptions {
+        WriterOptions { indent: None }
+    }
+
+    pub fn pretty() -> WriterOptions {
+        WriterOptions {
+            indent: Some(String::from("  ")),
+        }
+    }
+}
+
+enum Frame {
+    Array { empty: bool },
+    // after_key is set between a key and its value
+    Object { empty: bool, after_key: bool },
+}
+
+pub struct TagWriter<W> {
+    out: W,
+    options: WriterOptions,
+    stack: Vec<Frame>,
+    // set once the root value is complete. only an EndDocument may follow
+    done: bool,
+}
+
+impl<W: Write> TagWriter<W> {
+    pub fn new(out: W) -> TagWriter<W> {
+        Self::with_options(out, WriterOptions::compact())
+    }
+
+    pub fn pretty(out: W) -> TagWriter<W> {
+        Self::with_options(out, WriterOptions::pretty())
+    }
+
+    pub fn with_options(out: W, options: WriterOptions) -> TagWriter<W> {
+        TagWriter {
+            out,
+            options,
+            stack: Vec::new(),
+            done: false,
+        }
+    }
+
+    // comments are dropped, json doesn't have them. an EndDocument ends the line, so a
+    // multi-document stream comes out as JSON Lines. an Error or a Recovered tag means
+    // there's nothing sensible to write, so they're an error here too
+    pub fn write_tag(&mut self, tag: &Tag) -> Result<(), Error> {
+        match tag {
+            Tag::BeginObject => {
+                self.begin_value()?;
+                self.out.write_all(b"{")?;
+                self.stack.push(Frame::Object {
+                    empty: true,
+                    after_key: false,
+                });
+            }
+            Tag::BeginArray => {
+                self.begin_value()?;
+                self.out.write_all(b"[")?;
+                self.stack.push(Frame::Array { empty: true });
+            }
+            Tag::EndObject => match self.stack.pop() {
+                Some(Frame::Object {
+                    empty,
+                    after_key: false,
+                }) => self.end_container(empty, b"}")?,
+                _ => return Err(invalid("EndObject without an object to close")),
+            },
+            Tag::EndArray => match self.stack.pop() {
+                Some(Frame::Array { empty }) => self.end_container(empty, b"]")?,
+                _ => return Err(invalid("EndArray without an array to close")),
+            },
+            Tag::ObjectKey(key) => {
+                let first = match self.stack.last_mut() {
+                    Some(Frame::Object { empty, after_key }) if !*after_key => {
+                        *after_key = true;
+                        std::mem::replace(empty, false)
+                    }
+                    Some(Frame::Object { .. }) => return Err(invalid("a key without a value")),
+                    _ => return Err(invalid("ObjectKey outside of an object")),
+                };
+                self.separate(first)?;
+                write_string(&mut self.out, key)?;
+                let colon: &[u8] = if self.options.indent.is_some() { b": " } else { b":" };
+                self.out.write_all(colon)?;
+            }
+            Tag::StringLiteral(value) => {
+                self.begin_value()?;
+                write_string(&mut self.out, value)?;
+                self.end_value();
+            }
+            Tag::Number(lexeme) | Tag::TypedNumber(_, lexeme) => {
+                let number = json_number(lexeme)?;
+                self.begin_value()?;
+                self.out.write_all(number.as_bytes())?;
+                self.end_value();
+            }
+            Tag::TrueLiteral => self.write_literal(b"true")?,
+            Tag::FalseLiteral => self.write_literal(b"false")?,
+            Tag::NullLiteral => self.write_literal(b"null")?,
+            Tag::Comment(_) => {}
+            Tag::EndDocument(_) => {
+                if !self.stack.is_empty() {
+                    return Err(invalid("EndDocument inside an array or object"));
+                }
+                if self.done {
+                    self.out.write_all(b"\n")?;
+                }
+                self.done = false;
+            }
+            Tag::Error(_, _) | Tag::Recovered => {
+                return Err(Error::from_tag(tag).unwrap_or_else(|| invalid("Recovered tag")));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write_tags<'a>(&mut self, tags: impl IntoIterator<Item = &'a Tag>) -> Result<(), Error> {
+        tags.into_iter().try_for_each(|tag| self.write_tag(tag))
+    }
+
+    // checks that the document is complete and hands the output back
+    pub fn finish(mut self) -> Result<W, Error> {
+        if !self.stack.is_empty() {
+            return Err(invalid("the document ends inside an array or object"));
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn write_literal(&mut self, literal: &[u8]) -> Result<(), Error> {
+        self.begin_value()?;
+        self.out.write_all(literal)?;
+        self.end_value();
+        Ok(())
+    }
+
+    // the comma and the line break that go before every value, wherever it is
+    fn begin_value(&mut self) -> Result<(), Error> {
+        match self.stack.last_mut() {
+            None if self.done => Err(invalid("a second root value without an EndDocument")),
+            None => Ok(()),
+            Some(Frame::Object { after_key, .. }) => {
+                if *after_key {
+                    *after_key = false;
+                    Ok(())
+                } else {
+                    Err(invalid("a value in an object without a key"))
+                }
+            }
+            Some(Frame::Array { empty }) => {
+                let first = std::mem::replace(empty, false);
+                self.separate(first)
+            }
+        }
+    }
+
+    fn end_value(&mut self) {
+        if self.stack.is_empty() {
+            self.done = true;
+        }
+    }
+
+    fn separate(&mut self, first: bool) -> Result<(), Error> {
+        if !first {
+            self.out.write_all(b",")?;
+        }
+        self.newline(self.stack.len())
+    }
+
+    fn end_container(&mut self, empty: bool, closer: &[u8]) -> Result<(), Error> {
+        // empty ones stay on one line
+        if !empty {
+            self.newline(self.stack.len())?;
+        }
+        self.out.write_all(closer)?;
+        self.end_value();
+        Ok(())
+    }
+
+    fn newline(&mut self, depth: usize) -> Result<(), Error> {
+        if let Some(indent) = &self.options.indent {
+            self.out.write_all(b"\n")?;
+            for _ in 0..depth {
+                self.out.write_all(indent.as_bytes())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::Write(message.to_string())
+}
+
+fn write_string<W: Write>(out: &mut W, value: &str) -> Result<(), Error> {
+    out.write_all(b"\"")?;
+    let mut start = 0;
+    for (i, c) in value.char_indices() {
+        let escaped = match c {
+            '"' => "\\\"",
+            '\\' => "\\\\",
+            '\n' => "\\n",
+            '\r' => "\\r",
+            '\t' => "\\t",
+            '\u{8}' => "\\b",
+            '\u{c}' => "\\f",
+            c if c < ' ' => "",
+            _ => continue,
+        };
+        out.write_all(&value.as_bytes()[start..i])?;
+        if escaped.is_empty() {
+            write!(out, "\\u{:04x}", c as u32)?;
+        } else {
+            out.write_all(escaped.as_bytes())?;
+        }
+        start = i + c.len_utf8();
+    }
+    out.write_all(&value.as_bytes()[start..])?;
+    out.write_all(b"\"")?;
+    Ok(())
+}
+
+// numbers as written in json are written as they are, so no digits get lost. the ones
+// only json5 allows (hex, a leading '+' or '.', Infinity...) are converted if they can be
+fn json_number(lexeme: &str) -> Result<String, Error> {
+    if is_json_number(lexeme) {
+        return Ok(lexeme.to_string());
+    }
+    // hex doesn't need to fit anywhere on the way
+    if let Some(decimal) = parser::hex_to_decimal(lexeme) {
+        return Ok(decimal);
+    }
+    match Number::from_lexeme(lexeme, OutOfRange::KeepAsString) {
+        Ok(Number::Int(value)) => Ok(value.to_string()),
+        Ok(Number::UInt(value)) => Ok(value.to_string()),
+        Ok(Number::Float(value)) if value.is_finite() => Ok(format!("{:?}", value)),
+        _ => Err(Error::Write(format!("{} can't be written as a json number", lexeme))),
+    }
+}
+
+fn is_json_number(lexeme: &str) -> bool {
+    let bytes = lexeme.as_bytes();
+    let mut i = 0;
+    let digits = |i: &mut usize| {
+        let start = *i;
+        while *i < bytes.len() && bytes[*i].is_ascii_digit() {
+            *i += 1;
+        }
+        *i > start
+    };
+    if bytes.first() == Some(&b'-') {
+        i += 1;
+    }
+    match bytes.get(i) {
+        Some(b'0') => i += 1,
+        Some(b'1'..=b'9') => {
+            digits(&mut i);
+        }
+        _ => return false,
+    }
+    if bytes.get(i) == Some(&b'.') {
+        i += 1;
+        if !digits(&mut i) {
+            return false;
+        }
+    }
+    if let Some(b'e' | b'E') = bytes.get(i) {
+        i += 1;
+        if let Some(b'+' | b'-') = bytes.get(i) {
+            i += 1;
+        }
+        if !digits(&mut i) {
+            return false;
+        }
+    }
+    i == bytes.len()
+}
+
+// writes the tags as compact json, or pretty-printed if indent is set
+pub fn to_writer<'a, W: Write>(
+    tags: impl IntoIterator<Item = &'a Tag>,
+    out: W,
+    options: WriterOptions,
+) -> Result<W, Error> {
+    let mut writer = TagWriter::with_options(out, options);
+    writer.write_tags(tags)?;
+    writer.finish()
+}
+
+pub fn to_string<'a>(
+    tags: impl IntoIterator<Item = &'a Tag>,
+    options: WriterOptions,
+) -> Result<String, Error> {
+    let bytes = to_writer(tags, Vec::new(), options)?;
+    // everything that goes in is a str, or ascii
+    String::from_utf8(bytes).map_err(|e| Error::Internal(e.to_string()))
+}
@@ -1,5 +1,18 @@
 pub mod test_lexer;
 pub mod test_parser;
+pub mod test_documents;
+pub mod test_json5;
+pub mod test_jsonc;
+pub mod test_recovery;
+pub mod test_errors;
+pub mod test_pipeline;
+pub mod test_writer;
+pub mod test_paths;
+pub mod test_select;
+#[cfg(feature = "serde")]
+pub mod test_serde;
+#[cfg(feature = "dom")]
+pub mod test_dom;
 
 use hamberder::*;
 
@@ -42,4 +55,590 @@ fn test_obj() {
     assert_eq!(tags[9], parser::Tag::ObjectKey(String::from("disabled")));
     assert_eq!(tags[10], parser::Tag::TrueLiteral);
     assert_eq!(tags[11], parser::Tag::EndObject);
-}
\ No newline at end of file
+}
+#[test]
+fn test_parse_str() {
+    let tags: parser::TagVec = hamberder::parse_str("{\"a\": [1, -2.5E3, null]}").collect();
+    assert_eq!(
+        tags,
+        vec![
+            parser::Tag::BeginObject,
+            parser::Tag::ObjectKey(String::from("a")),
+            parser::Tag::BeginArray,
+            parser::Tag::Number(String::from("1")),
+            parser::Tag::Number(String::from("-2.5E3")),
+            parser::Tag::NullLiteral,
+            parser::Tag::EndArray,
+            parser::Tag::EndObject,
+        ]
+    );
+}
+
+#[test]
+fn test_parse_str_error() {
+    let tags: parser::TagVec = hamberder::parse_str("[true false]").collect();
+    assert_eq!(tags.len(), 3);
+    assert_eq!(tags[0], parser::Tag::BeginArray);
+    assert_eq!(tags[1], parser::Tag::TrueLiteral);
+    if let parser::Tag::Error(_, _) = tags[2] {
+    } else {
+        panic!();
+    }
+}
+
+// hands out its data a few bytes at a time to make sure the pull parser keeps
+// asking for more
+struct TrickleReader<'a> {
+    data: &'a [u8],
+}
+
+impl std::io::Read for TrickleReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = std::cmp::min(std::cmp::min(3, buf.len()), self.data.len());
+        buf[..len].copy_from_slice(&self.data[..len]);
+        self.data = &self.data[len..];
+        Ok(len)
+    }
+}
+
+// an array that never ends
+struct EndlessReader {
+    started: bool,
+}
+
+impl std::io::Read for EndlessReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let data: &[u8] = if self.started { b"1, " } else { b"[" };
+        self.started = true;
+        buf[..data.len()].copy_from_slice(data);
+        Ok(data.len())
+    }
+}
+
+#[test]
+fn test_pull_parser_endless_source() {
+    let source = lexer::ReadSource::new(EndlessReader { started: false });
+    let tags: parser::TagVec = parser::PullParser::new(source).take(3).collect();
+    assert_eq!(
+        tags,
+        vec![
+            parser::Tag::BeginArray,
+            parser::Tag::Number(String::from("1")),
+            parser::Tag::Number(String::from("1")),
+        ]
+    );
+}
+
+// a &str that's fed to the lexer, and can tell how much of it hasn't been yet
+struct Watched<'a> {
+    rest: std::rc::Rc<std::cell::Cell<&'a str>>,
+}
+
+impl lexer::ChunkSource for Watched<'_> {
+    fn feed(&mut self, lexer: &mut lexer::Lexer) -> bool {
+        let mut rest = self.rest.get();
+        let more = rest.feed(lexer);
+        self.rest.set(rest);
+        more
+    }
+}
+
+#[test]
+fn test_pull_parser_str_in_chunks() {
+    let source = format!("[{}1]", "\"grüße\", ".repeat(100_000));
+    let rest = std::rc::Rc::new(std::cell::Cell::new(source.as_str()));
+    let mut tags = parser::PullParser::new(Watched { rest: rest.clone() });
+    assert_eq!(tags.next(), Some(parser::Tag::BeginArray));
+    // the first tag is there long before the end of the source
+    assert!(rest.get().len() > source.len() / 2);
+    assert_eq!(tags.count(), 100_002);
+
+    let tags: parser::TagVec = parser::PullParser::new(source.as_bytes()).collect();
+    assert_eq!(tags.len(), 100_003);
+    assert_eq!(tags[1], parser::Tag::StringLiteral(String::from("grüße")));
+}
+
+#[test]
+fn test_parse_reader() {
+    let reader = TrickleReader {
+        data: "[\"grüße\", 42]".as_bytes(),
+    };
+    let tags: parser::TagVec = hamberder::parse_reader(reader).collect();
+    assert_eq!(
+        tags,
+        vec![
+            parser::Tag::BeginArray,
+            parser::Tag::StringLiteral(String::from("grüße")),
+            parser::Tag::Number(String::from("42")),
+            parser::Tag::EndArray,
+        ]
+    );
+}
+
+#[test]
+fn test_parse_file_split_utf8() {
+    // pad things out so the first 'ö' straddles the 8 KiB read boundary
+    let mut contents = String::from("[\"");
+    contents.push_str(&"x".repeat(8 * 1024 - 3));
+    contents.push_str("öö\"]");
+    let path = std::env::temp_dir().join(format!("hamberder_split_{}.json", std::process::id()));
+    std::fs::write(&path, &contents).unwrap();
+    let tag_rx = hamberder::parse_file(path.to_str().unwrap()).unwrap();
+    let tags: parser::TagVec = tag_rx.iter().collect();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(tags.len(), 3);
+    assert_eq!(
+        tags[1],
+        parser::Tag::StringLiteral(contents[2..contents.len() - 2].to_string())
+    );
+}
+
+#[test]
+fn test_parse_positioned() {
+    let tags: Vec<parser::PositionedTag> = hamberder::parse_str("{\n  \"port\": 70000\n}")
+        .into_positioned()
+        .collect();
+    assert_eq!(
+        tags,
+        vec![
+            (
+                parser::Tag::BeginObject,
+                lexer::TokenInfo {
+                    line_number: 1,
+                    char_position: 2,
+                    start: 0,
+                    length: 1
+                }
+            ),
+            (
+                parser::Tag::ObjectKey(String::from("port")),
+                lexer::TokenInfo {
+                    line_number: 2,
+                    char_position: 9,
+                    start: 4,
+                    length: 6
+                }
+            ),
+            (
+                parser::Tag::Number(String::from("70000")),
+                lexer::TokenInfo {
+                    line_number: 2,
+                    char_position: 16,
+                    start: 12,
+                    length: 5
+                }
+            ),
+            (
+                parser::Tag::EndObject,
+                lexer::TokenInfo {
+                    line_number: 3,
+                    char_position: 2,
+                    start: 18,
+                    length: 1
+                }
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_parse_positioned_channel() {
+    let (str_tx, str_rx) = std::sync::mpsc::channel();
+    let tag_rx = hamberder::parse_positioned(str_rx);
+    str_tx.send(String::from("[null,")).unwrap();
+    str_tx.send(String::from(" true]")).unwrap();
+    drop(str_tx);
+    let tags: Vec<parser::PositionedTag> = tag_rx.iter().collect();
+    assert_eq!(tags.len(), 4);
+    assert_eq!(tags[2].0, parser::Tag::TrueLiteral);
+    assert_eq!(tags[2].1.start, 7);
+    assert_eq!(tags[3].0, parser::Tag::EndArray);
+    assert_eq!(tags[3].1.start, 11);
+}
+
+#[test]
+fn test_parse_positioned_with_options() {
+    let options = parser::ParserOptions {
+        documents: parser::DocumentMode::Lines,
+        ..Default::default()
+    };
+    let tag_rx = hamberder::parse_positioned_with_options("1\n\"a\"", options);
+    let tags: Vec<parser::PositionedTag> = tag_rx.iter().collect();
+    assert_eq!(tags.len(), 4);
+    assert_eq!(tags[2].0, parser::Tag::StringLiteral(String::from("a")));
+    assert_eq!(tags[2].1.line_number, 2);
+    assert_eq!(tags[3].0, parser::Tag::EndDocument(1));
+}
+
+#[test]
+fn test_parse_escapes() {
+    let tags: parser::TagVec =
+        hamberder::parse_str(r#"{"say \"hi\"": "a\\b\/c\n\té😀"}"#).collect();
+    assert_eq!(tags.len(), 4);
+    assert_eq!(tags[1], parser::Tag::ObjectKey(String::from("say \"hi\"")));
+    assert_eq!(
+        tags[2],
+        parser::Tag::StringLiteral(String::from("a\\b/c\n\té😀"))
+    );
+}
+
+#[test]
+fn test_parse_raw_strings() {
+    let options = parser::ParserOptions {
+        raw_strings: true,
+        ..Default::default()
+    };
+    let tags: parser::TagVec =
+        parser::PullParser::with_options(r#"["a\nb😀"]"#, options).collect();
+    assert_eq!(
+        tags[1],
+        parser::Tag::StringLiteral(String::from(r#"a\nb😀"#))
+    );
+}
+
+#[test]
+fn test_parse_lone_surrogate() {
+    let tags: Vec<parser::PositionedTag> = hamberder::parse_str(r#"["ab\uDE00"]"#)
+        .into_positioned()
+        .collect();
+    assert_eq!(tags.len(), 2);
+    if let parser::Tag::Error(err_info, _) = &tags[1].0 {
+        assert_eq!(err_info.message, "Unpaired UTF-16 low surrogate '\\uDE00'");
+    } else {
+        panic!();
+    }
+    assert_eq!(
+        tags[1].1,
+        lexer::TokenInfo {
+            line_number: 1,
+            char_position: 11,
+            start: 4,
+            length: 6
+        }
+    );
+}
+
+#[test]
+fn test_parse_unpaired_high_surrogate() {
+    let tags: parser::TagVec = hamberder::parse_str(r#""\uD83Dx""#).collect();
+    assert_eq!(tags.len(), 1);
+    if let parser::Tag::Error(err_info, _) = &tags[0] {
+        assert_eq!(err_info.message, "Unpaired UTF-16 high surrogate '\\uD83D'");
+    } else {
+        panic!();
+    }
+}
+
+#[test]
+fn test_parse_trailing_value() {
+    let tags: Vec<parser::PositionedTag> = hamberder::parse_str("[1] [2]").into_positioned().collect();
+    assert_eq!(tags.len(), 4);
+    if let parser::Tag::Error(err_info, _) = &tags[3].0 {
+        assert_eq!(err_info.message, "Unexpected token after document end");
+    } else {
+        panic!();
+    }
+    assert_eq!(tags[3].1.start, 4);
+}
+
+#[test]
+fn test_parse_trailing_number_lookahead() {
+    // the second number is the token try_number has to look ahead at
+    let tags: parser::TagVec = hamberder::parse_str("12 34").collect();
+    assert_eq!(tags.len(), 2);
+    assert_eq!(tags[0], parser::Tag::Number(String::from("12")));
+    if let parser::Tag::Error(_, _) = tags[1] {
+    } else {
+        panic!();
+    }
+}
+
+#[test]
+fn test_parse_trailing_whitespace_ok() {
+    let tags: parser::TagVec = hamberder::parse_str(" {\"a\":1} \r\n\t ").collect();
+    assert_eq!(tags.len(), 4);
+    assert_eq!(tags[3], parser::Tag::EndObject);
+}
+
+#[test]
+fn test_parse_trailing_not_strict() {
+    let options = parser::ParserOptions {
+        strict: false,
+        ..Default::default()
+    };
+    let tags: parser::TagVec = parser::PullParser::with_options("[1] [2]", options).collect();
+    assert_eq!(
+        tags,
+        vec![
+            parser::Tag::BeginArray,
+            parser::Tag::Number(String::from("1")),
+            parser::Tag::EndArray,
+        ]
+    );
+}
+
+#[test]
+fn test_parse_unterminated_string() {
+    let tags: parser::TagVec = hamberder::parse_str("{\"a\":1} \"abc").collect();
+    assert_eq!(tags.len(), 5);
+    if let parser::Tag::Error(err_info, _) = &tags[4] {
+        assert_eq!(err_info.message, "Encountered end of stream inside a string");
+    } else {
+        panic!();
+    }
+}
+
+#[test]
+fn test_parse_max_depth() {
+    // deep enough to blow the stack if nothing stopped it
+    let source = "[".repeat(100_000);
+    let tags: Vec<parser::PositionedTag> =
+        hamberder::parse_str(&source).into_positioned().collect();
+    assert_eq!(tags.len(), 129);
+    assert!(tags[..128].iter().all(|(tag, _)| *tag == parser::Tag::BeginArray));
+    if let (parser::Tag::Error(err_info, _), info) = &tags[128] {
+        assert_eq!(err_info.message, "Maximum nesting depth exceeded");
+        assert_eq!(info.start, 128);
+    } else {
+        panic!();
+    }
+
+    let (str_tx, str_rx) = std::sync::mpsc::channel();
+    let tag_rx = hamberder::parse(str_rx);
+    str_tx.send("{\"a\":".repeat(100_000)).unwrap();
+    drop(str_tx);
+    let tags: parser::TagVec = tag_rx.iter().collect();
+    assert!(matches!(tags.last(), Some(parser::Tag::Error(_, _))));
+}
+
+#[test]
+fn test_parse_custom_max_depth() {
+    let options = parser::ParserOptions {
+        max_depth: 2,
+        ..Default::default()
+    };
+    let tags: parser::TagVec =
+        parser::PullParser::with_options("[[1], {\"a\": 2}]", options.clone()).collect();
+    assert_eq!(tags.len(), 9);
+    let tags: parser::TagVec =
+        parser::PullParser::with_options("[{\"a\": [1]}]", options).collect();
+    assert_eq!(tags.len(), 4);
+    assert!(matches!(&tags[3], parser::Tag::Error(_, info) if info.start == 7));
+}
+
+#[test]
+fn test_parse_lexer_limits() {
+    // a limit stops everything, even in a mode that would otherwise resume
+    let options = parser::ParserOptions {
+        documents: parser::DocumentMode::Lines,
+        lexer: lexer::LexerOptions {
+            max_string_len: Some(4),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let tags: parser::TagVec =
+        parser::PullParser::with_options("\"abcd\"\n\"abcde\"\n\"abc\"\n", options).collect();
+    assert_eq!(tags.len(), 4);
+    assert_eq!(tags[0], parser::Tag::StringLiteral(String::from("abcd")));
+    if let parser::Tag::Error(err_info, info) = &tags[2] {
+        assert_eq!(err_info.message, "The string exceeds the maximum length of 4 bytes");
+        assert_eq!(info.line_number, 2);
+    } else {
+        panic!();
+    }
+    assert_eq!(tags[3], parser::Tag::EndDocument(1));
+}
+
+fn typed_options(out_of_range: parser::OutOfRange) -> parser::ParserOptions {
+    parser::ParserOptions {
+        numbers: parser::NumberMode::Typed(out_of_range),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_parse_typed_numbers() {
+    let source = "[1, -2, 18446744073709551615, 1.5e3, -0.25]";
+    let tags: parser::TagVec =
+        parser::PullParser::with_options(source, typed_options(parser::OutOfRange::Error))
+            .collect();
+    assert_eq!(
+        tags,
+        vec![
+            parser::Tag::BeginArray,
+            parser::Tag::TypedNumber(parser::Number::Int(1), String::from("1")),
+            parser::Tag::TypedNumber(parser::Number::Int(-2), String::from("-2")),
+            parser::Tag::TypedNumber(
+                parser::Number::UInt(u64::MAX),
+                String::from("18446744073709551615")
+            ),
+            parser::Tag::TypedNumber(parser::Number::Float(1500.0), String::from("1.5E3")),
+            parser::Tag::TypedNumber(parser::Number::Float(-0.25), String::from("-0.25")),
+            parser::Tag::EndArray,
+        ]
+    );
+}
+
+#[test]
+fn test_parse_typed_numbers_out_of_range() {
+    let source = "[18446744073709551616, -9223372036854775809, -1E400]";
+    let tags: Vec<parser::PositionedTag> =
+        parser::PullParser::with_options(source, typed_options(parser::OutOfRange::Error))
+            .into_positioned()
+            .collect();
+    assert_eq!(tags.len(), 2);
+    if let (parser::Tag::Error(err_info, _), info) = &tags[1] {
+        assert_eq!(
+            err_info.message,
+            "The number 18446744073709551616 is out of range"
+        );
+        assert_eq!(info.start, 1);
+        assert_eq!(info.length, 20);
+    } else {
+        panic!();
+    }
+
+    let numbers = |out_of_range| -> Vec<parser::Number> {
+        parser::PullParser::with_options(source, typed_options(out_of_range))
+            .filter_map(|tag| match tag {
+                parser::Tag::TypedNumber(number, _) => Some(number),
+                _ => None,
+            })
+            .collect()
+    };
+    assert_eq!(
+        numbers(parser::OutOfRange::Saturate),
+        vec![
+            parser::Number::UInt(u64::MAX),
+            parser::Number::Int(i64::MIN),
+            parser::Number::Float(f64::MIN),
+        ]
+    );
+    assert_eq!(
+        numbers(parser::OutOfRange::KeepAsString),
+        vec![
+            parser::Number::BigNumber(String::from("18446744073709551616")),
+            parser::Number::BigNumber(String::from("-9223372036854775809")),
+            parser::Number::BigNumber(String::from("-1E400")),
+        ]
+    );
+}
+
+#[test]
+fn test_number_conversions() {
+    let number = |lexeme| parser::Number::from_lexeme(lexeme, parser::OutOfRange::Error).unwrap();
+    assert_eq!(number("-3").as_i64(), Some(-3));
+    assert_eq!(number("-3").as_u64(), None);
+    assert_eq!(number("-3").as_f64(), Some(-3.0));
+    assert_eq!(number("9223372036854775808").as_i64(), None);
+    assert_eq!(number("9223372036854775808").as_u64(), Some(1 << 63));
+    assert_eq!(number("9007199254740993").as_f64(), None);
+    assert_eq!(number("9223372036854775807").as_f64(), None);
+    assert_eq!(number("0.5").as_i64(), None);
+    assert_eq!(number("0.5").as_f64(), Some(0.5));
+}
+
+fn parse_with_duplicate_keys(source: &str, policy: parser::DuplicateKeys) -> parser::TagVec {
+    let options = parser::ParserOptions {
+        duplicate_keys: policy,
+        ..Default::default()
+    };
+    parser::PullParser::with_options(source, options).collect()
+}
+
+#[test]
+fn test_parse_duplicate_keys_allow() {
+    let tags = parse_with_duplicate_keys("{\"a\":1,\"a\":2}", parser::DuplicateKeys::Allow);
+    assert_eq!(tags.len(), 6);
+}
+
+#[test]
+fn test_parse_duplicate_keys_error() {
+    let tags = parse_with_duplicate_keys(
+        "{\"a\":1,\"b\":{\"a\":2},\n\"\\u0061\":3}",
+        parser::DuplicateKeys::Error,
+    );
+    assert_eq!(tags.len(), 9);
+    if let parser::Tag::Error(err_info, info) = &tags[8] {
+        assert_eq!(
+            err_info.message,
+            "Duplicate key 'a', first seen on line 1 at offset 1"
+        );
+        assert_eq!(info.line_number, 2);
+        assert_eq!(info.start, 20);
+    } else {
+        panic!();
+    }
+}
+
+#[test]
+fn test_parse_duplicate_keys_first_wins() {
+    let tags = parse_with_duplicate_keys(
+        "{\"a\":1,\"b\":[true],\"a\":{\"c\":[2]},\"b\":null}",
+        parser::DuplicateKeys::FirstWins,
+    );
+    assert_eq!(
+        tags,
+        vec![
+            parser::Tag::BeginObject,
+            parser::Tag::ObjectKey(String::from("a")),
+            parser::Tag::Number(String::from("1")),
+            parser::Tag::ObjectKey(String::from("b")),
+            parser::Tag::BeginArray,
+            parser::Tag::TrueLiteral,
+            parser::Tag::EndArray,
+            parser::Tag::EndObject,
+        ]
+    );
+}
+
+#[test]
+fn test_parse_duplicate_keys_last_wins() {
+    let tags = parse_with_duplicate_keys(
+        "[{\"a\":{\"x\":1,\"x\":2},\"b\":3,\"a\":[4]}]",
+        parser::DuplicateKeys::LastWins,
+    );
+    assert_eq!(
+        tags,
+        vec![
+            parser::Tag::BeginArray,
+            parser::Tag::BeginObject,
+            parser::Tag::ObjectKey(String::from("b")),
+            parser::Tag::Number(String::from("3")),
+            parser::Tag::ObjectKey(String::from("a")),
+            parser::Tag::BeginArray,
+            parser::Tag::Number(String::from("4")),
+            parser::Tag::EndArray,
+            parser::Tag::EndObject,
+            parser::Tag::EndArray,
+        ]
+    );
+
+    // the nested object is held back until its parent closes, but positions survive
+    let options = parser::ParserOptions {
+        duplicate_keys: parser::DuplicateKeys::LastWins,
+        ..Default::default()
+    };
+    let tags: Vec<parser::PositionedTag> =
+        parser::PullParser::with_options("{\"a\":{\"x\":1,\"x\":2}}", options)
+            .into_positioned()
+            .collect();
+    assert_eq!(tags.len(), 7);
+    assert_eq!(tags[3].0, parser::Tag::ObjectKey(String::from("x")));
+    assert_eq!(tags[3].1.start, 12);
+}
+
+#[test]
+fn test_parse_duplicate_keys_last_wins_error() {
+    // what was parsed before the error still comes out, minus the replaced keys
+    let tags = parse_with_duplicate_keys(
+        "{\"a\":1,\"b\":{\"c\":2,\"c\":3,",
+        parser::DuplicateKeys::LastWins,
+    );
+    assert_eq!(tags.len(), 8);
+    assert_eq!(tags[4], parser::Tag::BeginObject);
+    assert_eq!(tags[5], parser::Tag::ObjectKey(String::from("c")));
+    assert_eq!(tags[6], parser::Tag::Number(String::from("3")));
+    assert!(matches!(tags[7], parser::Tag::Error(_, _)));
+}
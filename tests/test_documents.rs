@@ -0,0 +1,297 @@
+use hamberder::{error::ErrorKind, lexer, parser, Error};
+
+fn lines_options() -> parser::ParserOptions {
+    parser::ParserOptions {
+        documents: parser::DocumentMode::Lines,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_ndjson() {
+    let (str_tx, str_rx) = std::sync::mpsc::channel();
+    let tag_rx = hamberder::parse_ndjson(str_rx);
+    str_tx.send(String::from("{\"a\":1}\n\n[tr")).unwrap();
+    str_tx.send(String::from("ue]\r\n\"x\"\n")).unwrap();
+    drop(str_tx);
+    let tags: parser::TagVec = tag_rx.iter().collect();
+    assert_eq!(
+        tags,
+        vec![
+            parser::Tag::BeginObject,
+            parser::Tag::ObjectKey(String::from("a")),
+            parser::Tag::Number(String::from("1")),
+            parser::Tag::EndObject,
+            parser::Tag::EndDocument(0),
+            parser::Tag::BeginArray,
+            parser::Tag::TrueLiteral,
+            parser::Tag::EndArray,
+            parser::Tag::EndDocument(1),
+            parser::Tag::StringLiteral(String::from("x")),
+            parser::Tag::EndDocument(2),
+        ]
+    );
+}
+
+#[test]
+fn test_ndjson_truncated_line() {
+    let tags: Vec<parser::PositionedTag> =
+        parser::PullParser::with_options("{\"a\":\n{\"b\":2}", lines_options())
+            .into_positioned()
+            .collect();
+    assert_eq!(tags.len(), 9);
+    if let parser::Tag::Error(err_info, _) = &tags[2].0 {
+        assert_eq!(
+            err_info.message,
+            "Encountered end of line, but more tokens expected"
+        );
+    } else {
+        panic!();
+    }
+    assert_eq!(tags[2].1.line_number, 1);
+    assert_eq!(tags[3].0, parser::Tag::EndDocument(0));
+    assert_eq!(tags[4].0, parser::Tag::BeginObject);
+    assert_eq!(tags[4].1.line_number, 2);
+    assert_eq!(tags[8].0, parser::Tag::EndDocument(1));
+}
+
+#[test]
+fn test_ndjson_resumes_after_lexer_error() {
+    let tags: Vec<parser::PositionedTag> =
+        parser::PullParser::with_options("[1, @, 3]\n[\"a\nb\"]\nnull", lines_options())
+            .into_positioned()
+            .collect();
+    let tag_kinds: Vec<&parser::Tag> = tags.iter().map(|(tag, _)| tag).collect();
+    assert_eq!(tag_kinds.len(), 11);
+    assert_eq!(tag_kinds[0], &parser::Tag::BeginArray);
+    assert_eq!(tag_kinds[1], &parser::Tag::Number(String::from("1")));
+    if let parser::Tag::Error(_, info) = tag_kinds[2] {
+        assert_eq!(info.line_number, 1);
+    } else {
+        panic!();
+    }
+    assert_eq!(tag_kinds[3], &parser::Tag::EndDocument(0));
+    assert_eq!(tag_kinds[4], &parser::Tag::BeginArray);
+    if let parser::Tag::Error(_, info) = tag_kinds[5] {
+        assert_eq!(info.line_number, 2);
+    } else {
+        panic!();
+    }
+    assert_eq!(tag_kinds[6], &parser::Tag::EndDocument(1));
+    // "b\"]" is the third line, which is broken too
+    if let parser::Tag::Error(_, info) = tag_kinds[7] {
+        assert_eq!(info.line_number, 3);
+    } else {
+        panic!();
+    }
+    assert_eq!(tag_kinds[8], &parser::Tag::EndDocument(2));
+    assert_eq!(tag_kinds[9], &parser::Tag::NullLiteral);
+    assert_eq!(tag_kinds[10], &parser::Tag::EndDocument(3));
+}
+
+// hands out its data, then fails
+struct FailingReader {
+    data: &'static [u8],
+}
+
+impl std::io::Read for FailingReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.data.is_empty() {
+            return Err(std::io::Error::new(std::io::ErrorKind::ConnectionReset, "gone"));
+        }
+        let len = self.data.len().min(buf.len());
+        buf[..len].copy_from_slice(&self.data[..len]);
+        self.data = &self.data[len..];
+        Ok(len)
+    }
+}
+
+#[test]
+fn test_ndjson_read_failure_after_broken_line() {
+    // the read fails while the rest of a broken line is being skipped. that's the end
+    // of the stream, and it has to say so
+    let broken_lines: [&'static [u8]; 3] = [b"{\"a\": 1}\n{\"b\" 2, ", b"1\n[@, ", b"1\n\"a"];
+    for data in broken_lines {
+        for recover in [false, true] {
+            let options = parser::ParserOptions {
+                recover,
+                ..lines_options()
+            };
+            let source = lexer::ReadSource::new(FailingReader { data });
+            let (tag_rx, handle) = hamberder::parse_cancellable(source, options);
+            let errors: Vec<ErrorKind> = tag_rx
+                .iter()
+                .filter_map(|tag| match tag {
+                    parser::Tag::Error(err_info, _) => Some(err_info.kind),
+                    _ => None,
+                })
+                .collect();
+            let reset = std::io::ErrorKind::ConnectionReset;
+            assert!(
+                matches!(errors.last(), Some(ErrorKind::ReadFailed(kind, _)) if *kind == reset),
+                "{:?}",
+                errors
+            );
+            // and the kind of the io::Error survives
+            match handle.join() {
+                Err(Error::Io(e)) => assert_eq!(e.kind(), reset),
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_ndjson_two_values_on_one_line() {
+    let tags: parser::TagVec =
+        parser::PullParser::with_options("1 2\n3", lines_options()).collect();
+    assert_eq!(tags.len(), 5);
+    assert_eq!(tags[0], parser::Tag::Number(String::from("1")));
+    if let parser::Tag::Error(_, _) = tags[1] {
+    } else {
+        panic!();
+    }
+    assert_eq!(tags[2], parser::Tag::EndDocument(0));
+    assert_eq!(tags[3], parser::Tag::Number(String::from("3")));
+    assert_eq!(tags[4], parser::Tag::EndDocument(1));
+}
+
+#[test]
+fn test_multiple_documents() {
+    let options = parser::ParserOptions {
+        documents: parser::DocumentMode::Multiple,
+        ..Default::default()
+    };
+    let tags: parser::TagVec =
+        parser::PullParser::with_options("[1]{} 12 \"a\"", options).collect();
+    assert_eq!(
+        tags,
+        vec![
+            parser::Tag::BeginArray,
+            parser::Tag::Number(String::from("1")),
+            parser::Tag::EndArray,
+            parser::Tag::EndDocument(0),
+            parser::Tag::BeginObject,
+            parser::Tag::EndObject,
+            parser::Tag::EndDocument(1),
+            parser::Tag::Number(String::from("12")),
+            parser::Tag::EndDocument(2),
+            parser::Tag::StringLiteral(String::from("a")),
+            parser::Tag::EndDocument(3),
+        ]
+    );
+}
+
+#[test]
+fn test_multiple_documents_threaded() {
+    let (token_tx, token_rx) = std::sync::mpsc::channel();
+    let (tag_tx, tag_rx) = std::sync::mpsc::channel();
+    let options = parser::ParserOptions {
+        documents: parser::DocumentMode::Multiple,
+        ..Default::default()
+    };
+    parser::parse_with_options(token_rx, tag_tx, options);
+    for (i, token) in vec![lexer::MaybeToken::TrueLiteral, lexer::MaybeToken::NullLiteral]
+        .into_iter()
+        .enumerate()
+    {
+        token_tx
+            .send((
+                token,
+                lexer::TokenInfo {
+                    line_number: 1,
+                    char_position: 5 * i + 5,
+                    start: 5 * i,
+                    length: 4,
+                },
+            ))
+            .unwrap();
+    }
+    drop(token_tx);
+    let tags: Vec<parser::Tag> = tag_rx.iter().collect();
+    assert_eq!(
+        tags,
+        vec![
+            parser::Tag::TrueLiteral,
+            parser::Tag::EndDocument(0),
+            parser::Tag::NullLiteral,
+            parser::Tag::EndDocument(1),
+        ]
+    );
+}
+
+fn records_options() -> parser::ParserOptions {
+    parser::ParserOptions {
+        documents: parser::DocumentMode::Records,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_json_seq() {
+    let (str_tx, str_rx) = std::sync::mpsc::channel();
+    let tag_rx = hamberder::parse_json_seq(str_rx);
+    str_tx.send(String::from("\u{1e}{\"a\":\n1}\n\u{1e}\u{1e}12")).unwrap();
+    str_tx.send(String::from("3\n\u{1e}\"x\"\n")).unwrap();
+    drop(str_tx);
+    let tags: parser::TagVec = tag_rx.iter().collect();
+    assert_eq!(
+        tags,
+        vec![
+            parser::Tag::BeginObject,
+            parser::Tag::ObjectKey(String::from("a")),
+            parser::Tag::Number(String::from("1")),
+            parser::Tag::EndObject,
+            parser::Tag::EndDocument(0),
+            parser::Tag::Number(String::from("123")),
+            parser::Tag::EndDocument(1),
+            parser::Tag::StringLiteral(String::from("x")),
+            parser::Tag::EndDocument(2),
+        ]
+    );
+}
+
+#[test]
+fn test_json_seq_truncated_records() {
+    let source = "\u{1e}{\"a\":[1,\n2\u{1e}\"cut off\u{1e}tr\u{1e}[@ \n 1]\n\u{1e}null\n";
+    let tags: Vec<parser::PositionedTag> =
+        parser::PullParser::with_options(source, records_options())
+            .into_positioned()
+            .collect();
+    let messages: Vec<&str> = tags
+        .iter()
+        .filter_map(|(tag, _)| match tag {
+            parser::Tag::Error(err_info, _) => Some(err_info.message.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        messages,
+        vec![
+            "Encountered end of record, but more tokens expected",
+            "Encountered a record separator inside a string",
+            "Unrecognized token",
+            "Encountered an unexpected character '@'",
+        ]
+    );
+    let (last_tag, last_info) = &tags[tags.len() - 2];
+    assert_eq!(last_tag, &parser::Tag::NullLiteral);
+    assert_eq!(last_info.line_number, 4);
+    assert_eq!(tags[tags.len() - 1].0, parser::Tag::EndDocument(4));
+}
+
+#[test]
+fn test_json_seq_missing_separator() {
+    let tags: parser::TagVec =
+        parser::PullParser::with_options("1\n2\n\u{1e}3\n", records_options()).collect();
+    assert_eq!(tags.len(), 4);
+    if let parser::Tag::Error(err_info, info) = &tags[0] {
+        assert_eq!(err_info.message, "Expected a record separator");
+        assert_eq!(info.line_number, 1);
+    } else {
+        panic!();
+    }
+    assert_eq!(tags[1], parser::Tag::EndDocument(0));
+    assert_eq!(tags[2], parser::Tag::Number(String::from("3")));
+    assert_eq!(tags[3], parser::Tag::EndDocument(1));
+}
@@ -0,0 +1,88 @@
+#![cfg(feature = "dom")]
+
+use hamberder::{
+    dom::Value,
+    parser::{Tag, TagVec},
+    writer::{self, WriterOptions},
+    Error,
+};
+
+const SOURCE: &str = r#"{
+    "version":  1.0,
+    "config": ["en", "de", {"jp": null}],
+    "disabled": true,
+    "port": 18446744073709551615
+}"#;
+
+#[test]
+fn test_dom_from_tags() {
+    let (str_tx, str_rx) = std::sync::mpsc::channel();
+    let tag_sink = hamberder::parse(str_rx);
+    str_tx.send(String::from(SOURCE)).unwrap();
+    drop(str_tx);
+    let value = Value::from_tags(tag_sink).unwrap();
+    assert_eq!(value["version"], Value::Number(String::from("1.0")));
+    assert_eq!(value["version"].as_f64(), Some(1.0));
+    assert_eq!(value["config"][0].as_str(), Some("en"));
+    assert!(value["config"][2]["jp"].is_null());
+    assert_eq!(value["disabled"].as_bool(), Some(true));
+    assert_eq!(value["port"].as_u64(), Some(u64::MAX));
+    assert_eq!(value["port"].as_i64(), None);
+    // keys stay in order
+    let keys: Vec<&str> = value.as_object().unwrap().iter().map(|(key, _)| key.as_str()).collect();
+    assert_eq!(keys, vec!["version", "config", "disabled", "port"]);
+
+    // what isn't there is null, and so is anything below it
+    assert!(value["missing"]["deeper"][3].is_null());
+    assert!(value["config"][7].is_null());
+    assert!(value[0].is_null());
+    assert_eq!(value.get("missing"), None);
+    assert_eq!(value["config"].get_index(1), Some(&Value::String(String::from("de"))));
+}
+
+#[test]
+fn test_dom_duplicate_keys() {
+    let value = Value::from_tags(hamberder::parse_str(r#"{"a": 1, "b": 2, "a": 3}"#)).unwrap();
+    assert_eq!(value.as_object().unwrap().len(), 3);
+    assert_eq!(value["a"].as_i64(), Some(3));
+}
+
+#[test]
+fn test_dom_to_tags() {
+    let tags: TagVec = hamberder::parse_str(SOURCE).collect();
+    let value = Value::from_tags(hamberder::parse_str(SOURCE)).unwrap();
+    let again: TagVec = value.to_tags().collect();
+    assert_eq!(again, tags);
+
+    // so a Value can be written out like any other tags
+    let json = writer::to_string(&value["config"].to_tags().collect::<TagVec>(), WriterOptions::compact());
+    assert_eq!(json.unwrap(), r#"["en","de",{"jp":null}]"#);
+
+    let scalar: TagVec = Value::Bool(false).to_tags().collect();
+    assert_eq!(scalar, vec![Tag::FalseLiteral]);
+    let empty: TagVec = Value::Object(Vec::new()).to_tags().collect();
+    assert_eq!(empty, vec![Tag::BeginObject, Tag::EndObject]);
+}
+
+#[test]
+fn test_dom_errors() {
+    let err = Value::from_tags(hamberder::parse_str("[1, 2 3]")).unwrap_err();
+    assert!(matches!(err, Error::Syntax(_, _)));
+    // errors after the value count too
+    let err = Value::from_tags(hamberder::parse_str("[1] [2]")).unwrap_err();
+    assert!(matches!(err, Error::Syntax(_, _)));
+    let err = Value::from_tags(hamberder::parse_str("")).unwrap_err();
+    assert!(matches!(err, Error::Syntax(_, _)));
+
+    let invalid = |tags: Vec<Tag>| matches!(Value::from_tags(tags), Err(Error::Data(_, None)));
+    assert!(invalid(vec![]));
+    assert!(invalid(vec![Tag::BeginArray, Tag::NullLiteral]));
+    assert!(invalid(vec![Tag::BeginArray, Tag::EndObject]));
+    assert!(invalid(vec![Tag::BeginObject, Tag::NullLiteral, Tag::EndObject]));
+    assert!(invalid(vec![Tag::BeginObject, Tag::ObjectKey(String::from("a")), Tag::EndObject]));
+    assert!(invalid(vec![Tag::TrueLiteral, Tag::FalseLiteral]));
+
+    // a multi-document stream gives its first document
+    let value = Value::from_tags(hamberder::parse_ndjson("[1]\n[2]\n")).unwrap();
+    assert_eq!(value[0].as_i64(), Some(1));
+}
@@ -0,0 +1,297 @@
+use hamberder::{error, error::ErrorKind, lexer, parser};
+
+fn first_error(source: &str) -> lexer::ErrorInfo {
+    let options = parser::ParserOptions {
+        lexer: lexer::LexerOptions {
+            max_string_len: Some(8),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    parser::PullParser::with_options(source, options)
+        .find_map(|tag| match tag {
+            parser::Tag::Error(err_info, _) => Some(err_info),
+            _ => None,
+        })
+        .unwrap()
+}
+
+#[test]
+fn test_error_kinds() {
+    let cases = [
+        ("[1, @]", ErrorKind::UnexpectedChar('@')),
+        ("[\"\\q\"]", ErrorKind::InvalidEscape('q')),
+        ("\"\\u12x4\"", ErrorKind::InvalidUnicodeEscape('x')),
+        ("\"\\uD83D\"", ErrorKind::UnpairedSurrogate(String::from("D83D"))),
+        ("[\"abc", ErrorKind::UnterminatedString),
+        ("\"a\nb\"", ErrorKind::MultilineString),
+        ("[\"abcdefghi\"]", ErrorKind::StringTooLong(8)),
+        ("[01]", ErrorKind::LeadingZero),
+        ("-01.5", ErrorKind::LeadingZero),
+        ("{\"a\" 1}", ErrorKind::MissingColon(String::from("a"))),
+        ("{\"a\": }", ErrorKind::MissingValue(String::from("a"))),
+        ("{\"a\": 1 \"b\": 2}", ErrorKind::MissingObjectComma),
+        ("[1 2]", ErrorKind::MissingArrayComma),
+        ("[1,]", ErrorKind::TrailingComma(']')),
+        ("{\"a\": 1,}", ErrorKind::TrailingComma('}')),
+        ("[1, 2", ErrorKind::UnexpectedEof),
+        ("1.", ErrorKind::UnexpectedEof),
+        ("[1.x]", ErrorKind::UnexpectedChar('x')),
+        ("[1.]", ErrorKind::MissingFraction),
+        ("[1e]", ErrorKind::MissingExponentDigits),
+        ("[-]", ErrorKind::MissingDigits('-')),
+        ("", ErrorKind::EmptySource),
+        ("[1] 2", ErrorKind::TrailingToken),
+        ("tru", ErrorKind::UnrecognizedToken),
+        (":", ErrorKind::ExpectedValue),
+    ];
+    for (source, kind) in cases {
+        let err_info = first_error(source);
+        assert_eq!(err_info.kind, kind, "{}", source);
+        // the message is just the kind spelled out
+        assert_eq!(err_info.message, kind.to_string(), "{}", source);
+    }
+}
+
+#[test]
+fn test_error_codes() {
+    // these must never change
+    assert_eq!(ErrorKind::UnexpectedChar('x').code(), "HB0001");
+    assert_eq!(ErrorKind::InvalidEscape('x').code(), "HB0003");
+    assert_eq!(ErrorKind::InvalidUnicodeEscape('x').code(), "HB0004");
+    assert_eq!(ErrorKind::UnterminatedString.code(), "HB0008");
+    assert_eq!(ErrorKind::EmptySource.code(), "HB0013");
+    assert_eq!(ErrorKind::MissingColon(String::new()).code(), "HB0026");
+    assert_eq!(ErrorKind::TrailingComma(']').code(), "HB0035");
+    assert_eq!(ErrorKind::UnexpectedEof.code(), "HB0036");
+    assert_eq!(ErrorKind::LeadingZero.code(), "HB0039");
+    assert_eq!(ErrorKind::Other(String::new()).code(), "HB0051");
+}
+
+#[test]
+fn test_error_display() {
+    assert_eq!(
+        ErrorKind::DuplicateKey(String::from("a"), 2, 7).to_string(),
+        "Duplicate key 'a', first seen on line 2 at offset 7"
+    );
+    assert_eq!(
+        ErrorKind::UnpairedSurrogate(String::from("DE00")).to_string(),
+        "Unpaired UTF-16 low surrogate '\\uDE00'"
+    );
+    let boxed: Box<dyn std::error::Error> = Box::new(ErrorKind::MissingArrayComma);
+    assert_eq!(boxed.to_string(), "Expected comma or closing bracket");
+
+    // errors from from_lexeme are kinds too
+    assert_eq!(
+        parser::Number::from_lexeme("1x", parser::OutOfRange::Error),
+        Err(ErrorKind::NotANumber(String::from("1x")))
+    );
+}
+
+#[test]
+fn test_unrecognized_token_fragment() {
+    // the fragment is what there was of the literal, up to and including the char that
+    // didn't fit
+    for (source, fragment) in [("[trux]", "trux"), ("[1, nul", "nul"), ("{\"a\": fAlse}", "fA")] {
+        let err_info = first_error(source);
+        assert_eq!(err_info.kind, ErrorKind::UnrecognizedToken, "{}", source);
+        assert_eq!(err_info.fragment.as_deref(), Some(fragment), "{}", source);
+    }
+}
+
+#[test]
+fn test_leading_zero() {
+    for source in ["0", "-0", "0.5", "[0, 10, 0e01, 1.05]"] {
+        let tags: parser::TagVec = hamberder::parse_str(source).collect();
+        assert!(
+            !tags.iter().any(|tag| matches!(tag, parser::Tag::Error(_, _))),
+            "{}",
+            source
+        );
+    }
+    // when recovering the number still comes through
+    let options = parser::ParserOptions {
+        recover: true,
+        ..Default::default()
+    };
+    let tags: parser::TagVec = parser::PullParser::with_options("[007, 8]", options).collect();
+    assert_eq!(tags.len(), 5);
+    assert_eq!(tags[2], parser::Tag::Number(String::from("007")));
+    // they can be let through, and that has nothing to do with strict
+    let options = parser::ParserOptions {
+        strict: false,
+        ..Default::default()
+    };
+    let tags: parser::TagVec = parser::PullParser::with_options("[007, 8] x", options).collect();
+    assert!(matches!(
+        &tags[1],
+        parser::Tag::Error(err_info, _) if err_info.kind == ErrorKind::LeadingZero
+    ));
+    let options = parser::ParserOptions {
+        leading_zeros: true,
+        ..Default::default()
+    };
+    let tags: parser::TagVec = parser::PullParser::with_options("[007, 8]", options).collect();
+    assert!(!tags.iter().any(|tag| matches!(tag, parser::Tag::Error(_, _))));
+    assert_eq!(tags[1], parser::Tag::Number(String::from("007")));
+}
+
+fn render_first_error(source: &str, options: &error::RenderOptions) -> String {
+    hamberder::parse_str(source)
+        .into_positioned()
+        .find_map(|(tag, info)| match tag {
+            parser::Tag::Error(err_info, _) => Some(error::render_error_with_options(
+                source, &err_info, &info, options,
+            )),
+            _ => None,
+        })
+        .unwrap()
+}
+
+#[test]
+fn test_render_error() {
+    let source = "{\n  \"name\": \"x\",\n\t\"tags\": [1 2],\n  \"a\": 3,\r\n  \"b\": 4\n}\n";
+    assert_eq!(
+        render_first_error(source, &Default::default()),
+        concat!(
+            "error[HB0029]: Expected comma or closing bracket\n",
+            " --> line 3, column 13\n",
+            "  |\n",
+            "1 | {\n",
+            "2 |   \"name\": \"x\",\n",
+            "3 | \t\"tags\": [1 2],\n",
+            "  | \t           ^\n",
+            "4 |   \"a\": 3,\n",
+            "5 |   \"b\": 4\n",
+        )
+    );
+    let options = error::RenderOptions {
+        context_lines: 0,
+        color: true,
+    };
+    assert_eq!(
+        render_first_error("[\"a\\qb\"]", &options),
+        concat!(
+            "\x1b[1;31merror[HB0003]\x1b[0m: \x1b[1mUnsupported escaped character 'q'. Only 'b', 'f', 'n', 'r', 't', 'u<4-digit hex>', '\\', or '\"' are allowed\x1b[0m\n",
+            " \x1b[1;34m-->\x1b[0m line 1, column 2\n",
+            "\x1b[1;34m  |\x1b[0m\n",
+            "\x1b[1;34m1 |\x1b[0m [\"a\\qb\"]\n",
+            "\x1b[1;34m  |\x1b[0m  \x1b[1;31m^^^\x1b[0m\n",
+        )
+    );
+    // the end of the source is a position too
+    assert_eq!(
+        render_first_error("[1,\n 2", &Default::default()),
+        concat!(
+            "error[HB0036]: Encountered end of stream, but more tokens expected\n",
+            " --> line 2, column 3\n",
+            "  |\n",
+            "1 | [1,\n",
+            "2 |  2\n",
+            "  |   ^\n",
+        )
+    );
+}
+
+#[test]
+fn test_render_error_from_recent_lines() {
+    let (str_tx, str_rx) = std::sync::mpsc::channel();
+    for chunk in ["[1,\n2,\n", "3,\n4 5,\n6", "]\n"] {
+        str_tx.send(String::from(chunk)).unwrap();
+    }
+    drop(str_tx);
+    let options = parser::ParserOptions {
+        lexer: lexer::LexerOptions {
+            recent_lines: 3,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut parser = parser::PullParser::with_options(str_rx, options);
+    let mut rendered = None;
+    while let Some((tag, info)) = parser.next_positioned() {
+        if let parser::Tag::Error(err_info, _) = tag {
+            let recent_lines = parser.recent_lines().unwrap();
+            rendered = Some(recent_lines.render_error(&err_info, &info, &Default::default()));
+        }
+    }
+    // only the lines of the first two chunks made it in, and only 3 of them
+    assert_eq!(
+        rendered.unwrap(),
+        concat!(
+            "error[HB0029]: Expected comma or closing bracket\n",
+            " --> line 4, column 3\n",
+            "  |\n",
+            "3 | 3,\n",
+            "4 | 4 5,\n",
+            "  |   ^\n",
+            "5 | 6\n",
+        )
+    );
+
+    // without the line the error is on there's no snippet
+    let mut lexer = lexer::Lexer::with_options(lexer::LexerOptions {
+        recent_lines: 1,
+        ..Default::default()
+    });
+    lexer.push_str("[1,\n2,\n3");
+    let recent_lines = lexer.recent_lines().unwrap();
+    assert_eq!(recent_lines.lines().collect::<Vec<_>>(), vec![(3, 7, "3")]);
+    let err_info = lexer::ErrorInfo::from(error::ErrorKind::ExpectedValue);
+    let info = lexer::TokenInfo {
+        line_number: 1,
+        char_position: 2,
+        start: 1,
+        length: 1,
+    };
+    assert_eq!(
+        recent_lines.render_error(&err_info, &info, &Default::default()),
+        "error[HB0049]: Expected a value\n --> line 1, column 1\n"
+    );
+}
+
+#[test]
+fn test_error_type() {
+    let tags: parser::TagVec = hamberder::parse_str("[1,\n 2 3]").collect();
+    assert!(hamberder::Error::from_tag(&tags[0]).is_none());
+    let err = hamberder::Error::from_tag(tags.last().unwrap()).unwrap();
+    assert!(matches!(err, hamberder::Error::Syntax(_, _)));
+    assert_eq!(err.kind(), Some(&ErrorKind::MissingArrayComma));
+    assert_eq!(err.info().unwrap().line_number, 2);
+    assert_eq!(
+        err.to_string(),
+        "Expected comma or closing bracket at line 2, column 5"
+    );
+
+    let tags: parser::TagVec = hamberder::parse_reader(&b"[\"\xff\"]"[..]).collect();
+    let err = tags.iter().find_map(hamberder::Error::from_tag).unwrap();
+    assert!(matches!(err, hamberder::Error::Utf8(_, _)));
+
+    match hamberder::parse_file("no/such/file.json") {
+        Err(hamberder::Error::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::NotFound),
+        _ => panic!("expected an I/O error"),
+    }
+}
+
+#[test]
+fn test_internal_error() {
+    // a string token without its quotes can only come from somewhere else than our
+    // lexer. it's reported instead of taking the parser thread down
+    let (token_tx, token_rx) = std::sync::mpsc::channel();
+    let (tag_tx, tag_rx) = std::sync::mpsc::channel();
+    parser::parse(token_rx, tag_tx);
+    let info = lexer::TokenInfo {
+        line_number: 1,
+        char_position: 1,
+        start: 0,
+        length: 1,
+    };
+    token_tx
+        .send((lexer::MaybeToken::StringLiteral(String::from("x")), info))
+        .unwrap();
+    drop(token_tx);
+    let tags: parser::TagVec = tag_rx.iter().collect();
+    let err = tags.iter().find_map(hamberder::Error::from_tag).unwrap();
+    assert!(matches!(err, hamberder::Error::Internal(_)));
+    assert_eq!(ErrorKind::Internal(String::new()).code(), "HB0052");
+}
@@ -0,0 +1,262 @@
+use hamberder::{lexer, parser};
+
+fn json5_options() -> parser::ParserOptions {
+    parser::ParserOptions {
+        lexer: lexer::LexerOptions {
+            dialect: lexer::Dialect::Json5,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+fn parse_json5(source: &str) -> parser::TagVec {
+    parser::PullParser::with_options(source, json5_options()).collect()
+}
+
+fn number(text: &str) -> parser::Tag {
+    parser::Tag::Number(String::from(text))
+}
+
+#[test]
+fn test_json5_config_file() {
+    let (str_tx, str_rx) = std::sync::mpsc::channel();
+    let tag_rx = hamberder::parse_json5(str_rx);
+    str_tx
+        .send(String::from(
+            "// settings\n{\n  name: 'hamb\\\n\"erder\"', /* the \n name */\n  $v_2: 0x1F,\n  list: [+1, .5, 5., -Infinity, NaN,],\n  true: null,\n}\n",
+        ))
+        .unwrap();
+    drop(str_tx);
+    let tags: parser::TagVec = tag_rx.iter().collect();
+    assert_eq!(
+        tags,
+        vec![
+            parser::Tag::BeginObject,
+            parser::Tag::ObjectKey(String::from("name")),
+            parser::Tag::StringLiteral(String::from("hamb\"erder\"")),
+            parser::Tag::ObjectKey(String::from("$v_2")),
+            number("31"),
+            parser::Tag::ObjectKey(String::from("list")),
+            parser::Tag::BeginArray,
+            number("1"),
+            number("0.5"),
+            number("5.0"),
+            number("-Infinity"),
+            number("NaN"),
+            parser::Tag::EndArray,
+            parser::Tag::ObjectKey(String::from("true")),
+            parser::Tag::NullLiteral,
+            parser::Tag::EndObject,
+        ]
+    );
+}
+
+#[test]
+fn test_json5_numbers() {
+    assert_eq!(
+        parse_json5("[1.e2, .5E-1, 0xff, -0XA, 1e3]"),
+        vec![
+            parser::Tag::BeginArray,
+            number("1.0E2"),
+            number("0.5E-1"),
+            number("255"),
+            number("-10"),
+            number("1E3"),
+            parser::Tag::EndArray,
+        ]
+    );
+    // a dot after whitespace starts a new number rather than a fraction
+    let tags = parse_json5("[1 .5]");
+    assert_eq!(tags.len(), 3);
+    assert!(matches!(&tags[2], parser::Tag::Error(err_info, _) if err_info.message == "Expected comma or closing bracket"));
+    // like any other number, there's no limit to it as text
+    assert_eq!(
+        parse_json5("-0x10000000000000000"),
+        vec![number("-18446744073709551616")]
+    );
+
+    let options = parser::ParserOptions {
+        numbers: parser::NumberMode::Typed(parser::OutOfRange::Error),
+        ..json5_options()
+    };
+    let tags: parser::TagVec = parser::PullParser::with_options("-Infinity", options).collect();
+    assert_eq!(
+        tags,
+        vec![parser::Tag::TypedNumber(
+            parser::Number::Float(f64::NEG_INFINITY),
+            String::from("-Infinity")
+        )]
+    );
+}
+
+#[test]
+fn test_json5_infinity_and_nan() {
+    // json can't write these, so they keep their sign and their name
+    assert_eq!(
+        parse_json5("[-Infinity, +Infinity, -NaN, +NaN]"),
+        vec![
+            parser::Tag::BeginArray,
+            number("-Infinity"),
+            number("Infinity"),
+            number("-NaN"),
+            number("NaN"),
+            parser::Tag::EndArray,
+        ]
+    );
+    let nan = parser::Number::from_lexeme("-NaN", parser::OutOfRange::Error);
+    assert!(matches!(nan, Ok(parser::Number::Float(value)) if value.is_nan() && value.is_sign_negative()));
+
+    let tags = typed_json5("-NaN", parser::OutOfRange::Error);
+    match &tags[..] {
+        [parser::Tag::TypedNumber(parser::Number::Float(value), lexeme)] => {
+            assert!(value.is_nan() && value.is_sign_negative());
+            assert_eq!(lexeme, "-NaN");
+        }
+        tags => panic!("unexpected {:?}", tags),
+    }
+    assert!(hamberder::writer::to_string(&parse_json5("-NaN"), Default::default()).is_err());
+}
+
+fn typed_json5(source: &str, out_of_range: parser::OutOfRange) -> parser::TagVec {
+    let options = parser::ParserOptions {
+        numbers: parser::NumberMode::Typed(out_of_range),
+        ..json5_options()
+    };
+    parser::PullParser::with_options(source, options).collect()
+}
+
+#[test]
+fn test_json5_typed_numbers() {
+    // the text is the number as it was written
+    assert_eq!(
+        typed_json5("[0x1F, .5, 5., +1]", parser::OutOfRange::Error),
+        vec![
+            parser::Tag::BeginArray,
+            parser::Tag::TypedNumber(parser::Number::Int(31), String::from("0x1F")),
+            parser::Tag::TypedNumber(parser::Number::Float(0.5), String::from(".5")),
+            parser::Tag::TypedNumber(parser::Number::Float(5.0), String::from("5.")),
+            parser::Tag::TypedNumber(parser::Number::Int(1), String::from("+1")),
+            parser::Tag::EndArray,
+        ]
+    );
+    // hex that doesn't fit goes by OutOfRange too
+    let tags = typed_json5("0x10000000000000000", parser::OutOfRange::Error);
+    assert!(matches!(&tags[0], parser::Tag::Error(err_info, _) if err_info.message == "The hexadecimal number 0x10000000000000000 is out of range"));
+    assert_eq!(
+        typed_json5("[0x10000000000000000, -0x8000000000000001]", parser::OutOfRange::Saturate),
+        vec![
+            parser::Tag::BeginArray,
+            parser::Tag::TypedNumber(parser::Number::UInt(u64::MAX), String::from("0x10000000000000000")),
+            parser::Tag::TypedNumber(parser::Number::Int(i64::MIN), String::from("-0x8000000000000001")),
+            parser::Tag::EndArray,
+        ]
+    );
+    assert_eq!(
+        typed_json5("0x10000000000000000", parser::OutOfRange::KeepAsString),
+        vec![parser::Tag::TypedNumber(
+            parser::Number::BigNumber(String::from("0x10000000000000000")),
+            String::from("0x10000000000000000")
+        )]
+    );
+}
+
+#[test]
+fn test_json5_escapes() {
+    assert_eq!(
+        parse_json5(r#"['\v\0\x41', "\a\c\ \é", '\0a']"#),
+        vec![
+            parser::Tag::BeginArray,
+            parser::Tag::StringLiteral(String::from("\u{b}\0A")),
+            parser::Tag::StringLiteral(String::from("ac é")),
+            parser::Tag::StringLiteral(String::from("\0a")),
+            parser::Tag::EndArray,
+        ]
+    );
+    let tags = parse_json5(r#"'\x4g'"#);
+    assert!(matches!(&tags[0], parser::Tag::Error(err_info, _) if err_info.message == "The character 'g' is not valid in this context. Only hexadecimal digits (0-9, a-f, A-F) may follow \\x"));
+    // no octal escapes, and digits can't be escaped either
+    for source in [r#"'\01'"#, r#"'\1'"#] {
+        let tags = parse_json5(source);
+        assert!(
+            matches!(&tags[0], parser::Tag::Error(err_info, _) if err_info.message.starts_with("Unsupported escaped character '1'")),
+            "{}",
+            source
+        );
+    }
+}
+
+fn first_error_info(source: &str) -> Option<lexer::TokenInfo> {
+    parser::PullParser::with_options(source, json5_options())
+        .into_positioned()
+        .find_map(|(tag, info)| match tag {
+            parser::Tag::Error(_, _) => Some(info),
+            _ => None,
+        })
+}
+
+#[test]
+fn test_json5_escape_error_positions() {
+    // after a line continuation, the column starts over
+    assert_eq!(
+        first_error_info("{a: 'x\\\n  y\\uDE00z'}"),
+        Some(lexer::TokenInfo {
+            line_number: 2,
+            char_position: 10,
+            start: 11,
+            length: 6
+        })
+    );
+    // in front of one, it's still on the first line
+    assert_eq!(
+        first_error_info("{a: 'x\\uDE00\\\r\ny'}"),
+        Some(lexer::TokenInfo {
+            line_number: 1,
+            char_position: 13,
+            start: 6,
+            length: 6
+        })
+    );
+}
+
+#[test]
+fn test_json5_comments_and_positions() {
+    let tags: Vec<parser::PositionedTag> =
+        parser::PullParser::with_options("/* a\r\n b */ [1, // c\r\n2]", json5_options())
+            .into_positioned()
+            .collect();
+    assert_eq!(tags.len(), 4);
+    assert_eq!(tags[0].1.start, 12);
+    assert_eq!(tags[0].1.line_number, 2);
+    assert_eq!(tags[2].0, number("2"));
+    assert_eq!(tags[2].1.start, 22);
+    assert_eq!(tags[2].1.line_number, 3);
+
+    let tags = parse_json5("[1] /* never closed");
+    assert!(matches!(&tags[3], parser::Tag::Error(err_info, _) if err_info.message == "Encountered end of stream inside a comment"));
+}
+
+#[test]
+fn test_json5_still_rejected() {
+    // none of this is json5 either
+    for source in ["[1,,]", "{a b: 1}", "'abc\ndef'", "[- 1]", "/ 1", "[. 5]", "[5. 3]"] {
+        let tags = parse_json5(source);
+        assert!(
+            matches!(tags.last(), Some(parser::Tag::Error(_, _))),
+            "{}",
+            source
+        );
+    }
+}
+
+#[test]
+fn test_json5_off_by_default() {
+    for source in ["// c\n1", "[1,]", "{a: 1}", "'a'", "0x1", ".5", "+1", "NaN"] {
+        let tags: parser::TagVec = hamberder::parse_str(source).collect();
+        assert!(
+            matches!(tags.last(), Some(parser::Tag::Error(_, _))),
+            "{}",
+            source
+        );
+    }
+}
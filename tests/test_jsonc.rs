@@ -0,0 +1,131 @@
+use hamberder::{lexer, parser};
+
+fn jsonc_options(emit_comments: bool) -> parser::ParserOptions {
+    parser::ParserOptions {
+        lexer: lexer::LexerOptions {
+            comments: true,
+            ..Default::default()
+        },
+        emit_comments,
+        ..Default::default()
+    }
+}
+
+fn comment(text: &str) -> parser::Tag {
+    parser::Tag::Comment(String::from(text))
+}
+
+#[test]
+fn test_jsonc_settings_file() {
+    let (str_tx, str_rx) = std::sync::mpsc::channel();
+    let tag_rx = hamberder::parse_jsonc(str_rx);
+    str_tx
+        .send(String::from(
+            "{\n  // the font\n  \"editor.fontSize\": 14, /* px */\n  \"files.exclude\": [\"a\",],\n}\n",
+        ))
+        .unwrap();
+    drop(str_tx);
+    let tags: parser::TagVec = tag_rx.iter().collect();
+    assert_eq!(
+        tags,
+        vec![
+            parser::Tag::BeginObject,
+            parser::Tag::ObjectKey(String::from("editor.fontSize")),
+            parser::Tag::Number(String::from("14")),
+            parser::Tag::ObjectKey(String::from("files.exclude")),
+            parser::Tag::BeginArray,
+            parser::Tag::StringLiteral(String::from("a")),
+            parser::Tag::EndArray,
+            parser::Tag::EndObject,
+        ]
+    );
+}
+
+#[test]
+fn test_jsonc_emit_comments() {
+    let source = "// head\r\n[1/* one */, 2.5 // two\n, /* multi\r\nline */ true] // tail";
+    let tags: Vec<parser::PositionedTag> =
+        parser::PullParser::with_options(source, jsonc_options(true))
+            .into_positioned()
+            .collect();
+    let tag_kinds: Vec<&parser::Tag> = tags.iter().map(|(tag, _)| tag).collect();
+    assert_eq!(
+        tag_kinds,
+        vec![
+            &comment("// head"),
+            &parser::Tag::BeginArray,
+            &parser::Tag::Number(String::from("1")),
+            &comment("/* one */"),
+            &parser::Tag::Number(String::from("2.5")),
+            &comment("// two"),
+            &comment("/* multi\r\nline */"),
+            &parser::Tag::TrueLiteral,
+            &parser::Tag::EndArray,
+            &comment("// tail"),
+        ]
+    );
+    assert_eq!(tags[0].1.start, 0);
+    assert_eq!(tags[0].1.length, 7);
+    assert_eq!(tags[3].1.start, 11);
+    assert_eq!(tags[3].1.length, 9);
+    assert_eq!(tags[6].1.start, 35);
+    assert_eq!(tags[6].1.line_number, 4);
+    assert_eq!(tags[7].1.line_number, 4);
+}
+
+#[test]
+fn test_jsonc_trailing_commas_toggle() {
+    // comments on their own don't allow trailing commas
+    let tags: parser::TagVec =
+        parser::PullParser::with_options("[1,]", jsonc_options(false)).collect();
+    assert!(matches!(tags.last(), Some(parser::Tag::Error(_, _))));
+    let options = parser::ParserOptions {
+        trailing_commas: true,
+        ..Default::default()
+    };
+    let tags: parser::TagVec =
+        parser::PullParser::with_options("{\"a\":[1,],}", options.clone()).collect();
+    assert_eq!(tags.len(), 6);
+    // and trailing commas on their own don't allow comments
+    let tags: parser::TagVec = parser::PullParser::with_options("[1] // c", options).collect();
+    assert!(matches!(tags.last(), Some(parser::Tag::Error(_, _))));
+}
+
+#[test]
+fn test_jsonc_comments_with_last_wins() {
+    let options = parser::ParserOptions {
+        duplicate_keys: parser::DuplicateKeys::LastWins,
+        ..jsonc_options(true)
+    };
+    let tags: parser::TagVec =
+        parser::PullParser::with_options("[{/* a */\"k\":1,\"k\":2}]", options).collect();
+    assert_eq!(
+        tags,
+        vec![
+            parser::Tag::BeginArray,
+            parser::Tag::BeginObject,
+            comment("/* a */"),
+            parser::Tag::ObjectKey(String::from("k")),
+            parser::Tag::Number(String::from("2")),
+            parser::Tag::EndObject,
+            parser::Tag::EndArray,
+        ]
+    );
+}
+
+#[test]
+fn test_jsonc_broken_comments() {
+    for (source, message) in [
+        ("[1] /* open", "Encountered end of stream inside a comment"),
+        ("[1] / 2", "Expected '/' or '*' after '/'"),
+        ("-// c\n1", "A comment is not allowed here"),
+    ] {
+        let tags: parser::TagVec =
+            parser::PullParser::with_options(source, jsonc_options(true)).collect();
+        assert!(
+            matches!(tags.last(), Some(parser::Tag::Error(err_info, _)) if err_info.message == message),
+            "{}",
+            source
+        );
+    }
+}
@@ -1,3 +1,6 @@
+// the older tests fail with assert!(false)
+#![allow(clippy::assertions_on_constants)]
+
 use hamberder::lexer;
 
 #[test]
@@ -146,6 +149,16 @@ fn test_lexer_line_nums() {
     );
 }
 
+#[test]
+fn test_lexer_crlf_line_nums() {
+    // the LF after a CRLF starts a line of its own
+    let tokens: Vec<lexer::TokenPair> = lexer::Tokens::new("[1,\r\n\n2,\r\r\n3]").collect();
+    assert_eq!(tokens[3].0, lexer::MaybeToken::Integer(String::from("2")));
+    assert_eq!(tokens[3].1.line_number, 3);
+    assert_eq!(tokens[5].0, lexer::MaybeToken::Integer(String::from("3")));
+    assert_eq!(tokens[5].1.line_number, 5);
+}
+
 #[test]
 fn test_empty_quoted_string() {
     let (string_tx, string_rx) = std::sync::mpsc::channel();
@@ -699,3 +712,141 @@ fn test_lexer_object() {
         assert_eq!(&expected_tokens[i], tok);
     }
 }
+
+#[test]
+fn test_lexer_split_utf8() {
+    let (bytes_tx, bytes_rx) = std::sync::mpsc::channel();
+    let source = "\"€uro\"".as_bytes();
+    // the euro sign is 3 bytes long, so this cuts it up twice
+    bytes_tx.send(source[..2].to_vec()).unwrap();
+    bytes_tx.send(source[2..3].to_vec()).unwrap();
+    bytes_tx.send(source[3..].to_vec()).unwrap();
+    drop(bytes_tx); // force closed
+    let tokens: Vec<(lexer::MaybeToken, lexer::TokenInfo)> =
+        lexer::Tokens::new(bytes_rx).collect();
+    assert_eq!(tokens.len(), 1);
+    assert_eq!(
+        tokens[0].0,
+        lexer::MaybeToken::StringLiteral(String::from("\"€uro\""))
+    );
+    assert_eq!(
+        tokens[0].1,
+        lexer::TokenInfo {
+            line_number: 1,
+            char_position: 7,
+            start: 0,
+            length: 6
+        }
+    );
+}
+
+#[test]
+fn test_lexer_invalid_utf8() {
+    let source: &[u8] = b"[\"ab\xffc\"]";
+    let tokens: Vec<(lexer::MaybeToken, lexer::TokenInfo)> = lexer::Tokens::new(source).collect();
+    assert_eq!(tokens.len(), 2);
+    assert_eq!(tokens[0].0, lexer::MaybeToken::LeftBracket);
+    if let lexer::MaybeToken::Error(err_info) = &tokens[1].0 {
+        assert_eq!(err_info.message, "Invalid UTF-8 sequence at byte offset 4");
+    } else {
+        panic!();
+    }
+}
+
+#[test]
+fn test_lexer_truncated_utf8() {
+    let source: &[u8] = b"\"\xe2\x82";
+    let tokens: Vec<(lexer::MaybeToken, lexer::TokenInfo)> = lexer::Tokens::new(source).collect();
+    assert_eq!(tokens.len(), 1);
+    if let lexer::MaybeToken::Error(err_info) = &tokens[0].0 {
+        assert_eq!(
+            err_info.message,
+            "The source ends in the middle of a UTF-8 sequence at byte offset 1"
+        );
+    } else {
+        panic!();
+    }
+}
+
+fn lex_with_limits(source: &str, options: lexer::LexerOptions) -> Vec<lexer::TokenPair> {
+    lexer::Tokens::with_lexer(lexer::Lexer::with_options(options), source).collect()
+}
+
+fn error_message(token_pair: &lexer::TokenPair) -> &str {
+    match &token_pair.0 {
+        lexer::MaybeToken::Error(err_info) => &err_info.message,
+        _ => panic!(),
+    }
+}
+
+#[test]
+fn test_lexer_max_string_len() {
+    let options = lexer::LexerOptions {
+        max_string_len: Some(3),
+        ..Default::default()
+    };
+    let tokens = lex_with_limits("[\"abc\", \"\\\"a\", \"é\"]", options.clone());
+    assert_eq!(tokens.len(), 7);
+    let tokens = lex_with_limits("[\"abcd\", 1]", options);
+    assert_eq!(tokens.len(), 2);
+    assert_eq!(
+        error_message(&tokens[1]),
+        "The string exceeds the maximum length of 3 bytes"
+    );
+    assert_eq!(tokens[1].1.start, 1);
+}
+
+#[test]
+fn test_lexer_max_number_len() {
+    let options = lexer::LexerOptions {
+        max_number_len: Some(4),
+        ..Default::default()
+    };
+    let tokens = lex_with_limits("1234.5678e1234", options.clone());
+    assert_eq!(tokens.len(), 5);
+    let tokens = lex_with_limits("[1, 12345]", options);
+    assert_eq!(tokens.len(), 4);
+    assert_eq!(
+        error_message(&tokens[3]),
+        "The number exceeds the maximum length of 4 digits"
+    );
+    assert_eq!(tokens[3].1.start, 4);
+}
+
+#[test]
+fn test_lexer_max_total_bytes() {
+    let mut lexer = lexer::Lexer::with_options(lexer::LexerOptions {
+        max_total_bytes: Some(8),
+        ..Default::default()
+    });
+    // the limit applies across chunks, and the skipped line doesn't reset it
+    lexer.set_resume_at_next_line(true);
+    lexer.push_str("[@]\n");
+    lexer.push_bytes("[\"é\"".as_bytes());
+    lexer.push_str("]");
+    lexer.finish();
+    let tokens: Vec<lexer::TokenPair> = std::iter::from_fn(|| lexer.next_token()).collect();
+    assert_eq!(tokens.len(), 4);
+    assert_eq!(error_message(&tokens[1]), "Encountered an unexpected character '@'");
+    assert_eq!(tokens[2].0, lexer::MaybeToken::LeftBracket);
+    assert_eq!(
+        error_message(&tokens[3]),
+        "The source exceeds the maximum size of 8 bytes"
+    );
+}
+
+#[test]
+fn test_lexer_max_tokens() {
+    let options = lexer::LexerOptions {
+        max_tokens: Some(3),
+        ..Default::default()
+    };
+    assert_eq!(lex_with_limits("[1]", options.clone()).len(), 3);
+    let tokens = lex_with_limits("[1, 2]", options);
+    assert_eq!(tokens.len(), 4);
+    assert_eq!(
+        error_message(&tokens[3]),
+        "The source exceeds the maximum of 3 tokens"
+    );
+    assert_eq!(tokens[3].1.start, 4);
+}
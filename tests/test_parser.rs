@@ -1,4 +1,7 @@
-use hamberder::{lexer, parser};
+// the older tests fail with assert!(false)
+#![allow(clippy::assertions_on_constants)]
+
+use hamberder::{error, lexer, parser};
 //use std::sync::mpsc;
 
 #[test]
@@ -6,6 +9,7 @@ fn test_parse_error() {
     let err_info = lexer::ErrorInfo {
         message: String::from("bla bla bla"),
         fragment: None,
+        kind: error::ErrorKind::Other(String::from("bla bla bla")),
     };
     let dummy_token = lexer::MaybeToken::Error(err_info.clone());
     let dummy_token_info = lexer::TokenInfo {
@@ -845,4 +849,95 @@ fn test_parse_array() {
     assert_eq!(tags[4], parser::Tag::BeginObject);
     assert_eq!(tags[5], parser::Tag::EndObject);
     assert_eq!(tags[6], parser::Tag::EndArray);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_parse_positioned_number() {
+    let (token_tx, token_rx) = std::sync::mpsc::channel();
+    let (tag_tx, tag_rx) = std::sync::mpsc::channel();
+    parser::parse_positioned(token_rx, tag_tx);
+    token_tx
+        .send((
+            lexer::MaybeToken::MinusSign,
+            lexer::TokenInfo {
+                line_number: 2,
+                char_position: 5,
+                start: 7,
+                length: 1,
+            },
+        ))
+        .unwrap();
+    token_tx
+        .send((
+            lexer::MaybeToken::Integer(String::from("12")),
+            lexer::TokenInfo {
+                line_number: 2,
+                char_position: 7,
+                start: 8,
+                length: 2,
+            },
+        ))
+        .unwrap();
+    token_tx
+        .send((
+            lexer::MaybeToken::Exponent,
+            lexer::TokenInfo {
+                line_number: 2,
+                char_position: 8,
+                start: 10,
+                length: 1,
+            },
+        ))
+        .unwrap();
+    token_tx
+        .send((
+            lexer::MaybeToken::Integer(String::from("3")),
+            lexer::TokenInfo {
+                line_number: 2,
+                char_position: 9,
+                start: 11,
+                length: 1,
+            },
+        ))
+        .unwrap();
+    drop(token_tx);
+    let tags: Vec<parser::PositionedTag> = tag_rx.iter().collect();
+    assert_eq!(tags.len(), 1);
+    assert_eq!(
+        tags[0],
+        (
+            parser::Tag::Number(String::from("-12E3")),
+            lexer::TokenInfo {
+                line_number: 2,
+                char_position: 9,
+                start: 7,
+                length: 5,
+            }
+        )
+    );
+}
+
+#[test]
+fn test_parse_root_not_a_value() {
+    let (token_tx, token_rx) = std::sync::mpsc::channel();
+    let (tag_tx, tag_rx) = std::sync::mpsc::channel();
+    parser::parse(token_rx, tag_tx);
+    token_tx
+        .send((
+            lexer::MaybeToken::RightBracket,
+            lexer::TokenInfo {
+                line_number: 1,
+                char_position: 2,
+                start: 0,
+                length: 1,
+            },
+        ))
+        .unwrap();
+    drop(token_tx);
+    let tags: Vec<parser::Tag> = tag_rx.iter().collect();
+    assert_eq!(tags.len(), 1);
+    if let parser::Tag::Error(_, _) = tags[0] {
+    } else {
+        panic!();
+    }
+}
@@ -0,0 +1,128 @@
+use hamberder::{
+    parser::{self, Tag},
+    path::{self, PathSegment, PathTracker},
+};
+
+fn paths(source: &str) -> Vec<String> {
+    path::with_paths(hamberder::parse_str(source)).map(|(_, path)| path.to_string()).collect()
+}
+
+#[test]
+fn test_paths_pointers() {
+    let source = r#"{"version": 1, "config": ["en", {"jp": [true]}, []], "user": {"email": "a@b"}}"#;
+    assert_eq!(
+        paths(source),
+        vec![
+            "",
+            "/version",
+            "/version",
+            "/config",
+            "/config",
+            "/config/0",
+            "/config/1",
+            "/config/1/jp",
+            "/config/1/jp",
+            "/config/1/jp/0",
+            "/config/1/jp",
+            "/config/1",
+            "/config/2",
+            "/config/2",
+            "/config",
+            "/user",
+            "/user",
+            "/user/email",
+            "/user/email",
+            "/user",
+            "",
+        ]
+    );
+    assert_eq!(paths("42"), vec![""]);
+    assert_eq!(paths("[[], [[1]]]"), vec!["", "/0", "/0", "/1", "/1/0", "/1/0/0", "/1/0", "/1", ""]);
+}
+
+#[test]
+fn test_paths_escaping() {
+    assert_eq!(paths(r#"{"a/b~c": 1, "": 2}"#), vec!["", "/a~1b~0c", "/a~1b~0c", "/", "/", ""]);
+    assert_eq!(path::pointer(&[PathSegment::Key(String::from("~1")), PathSegment::Index(3)]), "/~01/3");
+}
+
+#[test]
+fn test_paths_filter() {
+    let (str_tx, str_rx) = std::sync::mpsc::channel();
+    let tag_sink = hamberder::parse_with_paths(str_rx);
+    str_tx
+        .send(String::from(r#"[{"user": {"email": "a@b", "name": "A"}}, {"user": {"#))
+        .unwrap();
+    str_tx.send(String::from(r#""email": "c@d"}}]"#)).unwrap();
+    drop(str_tx);
+    let emails: Vec<Tag> = tag_sink
+        .iter()
+        .filter(|(tag, path)| path.ends_with("/user/email") && !matches!(tag, Tag::ObjectKey(_)))
+        .map(|(tag, _)| tag)
+        .collect();
+    assert_eq!(
+        emails,
+        vec![
+            Tag::StringLiteral(String::from("a@b")),
+            Tag::StringLiteral(String::from("c@d"))
+        ]
+    );
+}
+
+#[test]
+fn test_paths_with_options() {
+    let options = parser::ParserOptions {
+        documents: parser::DocumentMode::Lines,
+        ..Default::default()
+    };
+    let tags: Vec<path::PathTag> =
+        hamberder::parse_with_paths_with_options("{\"a\": 1}\n[2]", options).iter().collect();
+    let paths: Vec<&str> = tags.iter().map(|(_, path)| &**path).collect();
+    assert_eq!(paths, vec!["", "/a", "/a", "", "", "", "/0", "", ""]);
+    // a key and its value share the one pointer
+    assert!(std::sync::Arc::ptr_eq(&tags[1].1, &tags[2].1));
+    assert_eq!(tags[8].0, Tag::EndDocument(1));
+}
+
+#[test]
+fn test_paths_documents() {
+    // every document starts over at the root, and errors are wherever the parser was.
+    // the missing comma is in the array, after its first element
+    let tags: Vec<path::PathTag> =
+        path::with_paths(hamberder::parse_ndjson("[1, 2]\n{\"a\": [3 4]}\n5\n")).collect();
+    let paths: Vec<&str> = tags.iter().map(|(_, path)| &**path).collect();
+    assert_eq!(
+        paths,
+        vec!["", "/0", "/1", "", "", "", "/a", "/a", "/a/0", "/a", "", "", ""]
+    );
+    assert!(matches!(tags[9].0, Tag::Error(_, _)));
+
+    let options = parser::ParserOptions {
+        duplicate_keys: parser::DuplicateKeys::LastWins,
+        ..Default::default()
+    };
+    let tags = parser::PullParser::with_options(r#"{"a": [1], "b": 2, "a": [3, 4]}"#, options);
+    let paths: Vec<String> = path::with_paths(tags).map(|(_, path)| path.to_string()).collect();
+    assert_eq!(paths, vec!["", "/b", "/b", "/a", "/a", "/a/0", "/a/1", "/a", ""]);
+}
+
+#[test]
+fn test_paths_tracker() {
+    let mut tracker = PathTracker::new();
+    for tag in hamberder::parse_str(r#"{"a": [{"b": null"#) {
+        tracker.update(&tag);
+        if tag == Tag::NullLiteral {
+            break;
+        }
+    }
+    assert_eq!(
+        tracker.segments(),
+        &[
+            PathSegment::Key(String::from("a")),
+            PathSegment::Index(0),
+            PathSegment::Key(String::from("b"))
+        ]
+    );
+    assert_eq!(tracker.depth(), 3);
+    assert_eq!(tracker.pointer(), "/a/0/b");
+}
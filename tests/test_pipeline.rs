@@ -0,0 +1,241 @@
+use hamberder::{parser, Error};
+
+// keeps the parser busy with an array that never ends, until the lexer stops asking
+fn endless_array() -> hamberder::lexer::UTF8Source {
+    let (str_tx, str_rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        if str_tx.send(String::from("[")).is_err() {
+            return;
+        }
+        while str_tx.send(String::from("1, 2, 3, ")).is_ok() {}
+    });
+    str_rx
+}
+
+#[test]
+fn test_pipeline_completes() {
+    let (tag_rx, handle) =
+        hamberder::parse_cancellable("[1, [true]]", parser::ParserOptions::default());
+    let tags: parser::TagVec = tag_rx.iter().collect();
+    assert_eq!(tags.len(), 6);
+    assert!(handle.join().is_ok());
+
+    // syntax errors are in the tag stream, not a failure of the pipeline
+    let (tag_rx, handle) = hamberder::parse_cancellable("[1 2]", parser::ParserOptions::default());
+    assert!(matches!(tag_rx.iter().last(), Some(parser::Tag::Error(_, _))));
+    assert!(handle.join().is_ok());
+}
+
+#[test]
+fn test_pipeline_cancel() {
+    let (tag_rx, handle) =
+        hamberder::parse_cancellable(endless_array(), parser::ParserOptions::default());
+    assert_eq!(tag_rx.recv().unwrap(), parser::Tag::BeginArray);
+    assert_eq!(tag_rx.recv().unwrap(), parser::Tag::Number(String::from("1")));
+    let cancel = handle.cancel_handle();
+    std::thread::spawn(move || cancel.cancel()).join().unwrap();
+    // whatever was already on its way drains out, then the sink closes
+    assert!(tag_rx.iter().all(|tag| matches!(tag, parser::Tag::Number(_))));
+    assert!(matches!(handle.join(), Err(Error::Cancelled)));
+}
+
+#[test]
+fn test_pipeline_cancel_after_completion() {
+    // cancelling a parse that's already over changes nothing
+    let (tag_rx, handle) =
+        hamberder::parse_cancellable("[1, [true]]", parser::ParserOptions::default());
+    assert_eq!(tag_rx.iter().count(), 6);
+    while !handle.is_finished() {
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+    handle.cancel();
+    assert!(handle.join().is_ok());
+
+    // and neither does cancelling one that already failed
+    let source = hamberder::lexer::ReadSource::new(FailingReader);
+    let (tag_rx, handle) = hamberder::parse_cancellable(source, parser::ParserOptions::default());
+    assert!(matches!(tag_rx.recv().unwrap(), parser::Tag::Error(_, _)));
+    handle.cancel();
+    assert!(matches!(handle.join(), Err(Error::Io(_))));
+}
+
+#[test]
+fn test_pipeline_cancel_idle_source() {
+    // the producer is still around but has nothing more to send. cancelling mustn't
+    // have to wait for it
+    let (str_tx, str_rx) = std::sync::mpsc::channel();
+    str_tx.send(String::from("[1, ")).unwrap();
+    let (tag_rx, handle) = hamberder::parse_cancellable(str_rx, parser::ParserOptions::default());
+    assert_eq!(tag_rx.recv().unwrap(), parser::Tag::BeginArray);
+    assert_eq!(tag_rx.recv().unwrap(), parser::Tag::Number(String::from("1")));
+    handle.cancel();
+    let started = std::time::Instant::now();
+    while !handle.is_finished() {
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+    assert!(matches!(handle.join(), Err(Error::Cancelled)));
+    assert!(tag_rx.recv().is_err());
+    drop(str_tx);
+}
+
+#[test]
+fn test_pipeline_dropped_sink() {
+    let (tag_rx, handle) =
+        hamberder::parse_cancellable(endless_array(), parser::ParserOptions::default());
+    assert_eq!(tag_rx.recv().unwrap(), parser::Tag::BeginArray);
+    drop(tag_rx);
+    // the parser notices on its next tag and exits without a fuss
+    assert!(handle.join().is_ok());
+}
+
+struct FailingReader;
+
+impl std::io::Read for FailingReader {
+    fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+        Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "gone"))
+    }
+}
+
+#[test]
+fn test_pipeline_read_failure() {
+    let source = hamberder::lexer::ReadSource::new(FailingReader);
+    let (tag_rx, handle) = hamberder::parse_cancellable(source, parser::ParserOptions::default());
+    assert!(matches!(tag_rx.recv().unwrap(), parser::Tag::Error(_, _)));
+    assert!(matches!(handle.join(), Err(Error::Io(_))));
+}
+
+#[test]
+fn test_pipeline_file() {
+    let path = std::env::temp_dir().join(format!("hamberder_cancel_{}.json", std::process::id()));
+    let contents = format!("[{}1]", "1, ".repeat(100_000));
+    std::fs::write(&path, &contents).unwrap();
+    let (tag_rx, handle) = hamberder::parse_file_cancellable(path.to_str().unwrap()).unwrap();
+    assert_eq!(tag_rx.recv().unwrap(), parser::Tag::BeginArray);
+    handle.cancel();
+    // the lexer stops before its next chunk, long before the end of the file
+    assert!(tag_rx.iter().count() < 100_000);
+    assert!(matches!(handle.join(), Err(Error::Cancelled)));
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(matches!(
+        hamberder::parse_file_cancellable("no/such/file.json"),
+        Err(Error::Io(_))
+    ));
+}
+
+fn with_tag_capacity(capacity: Option<usize>) -> hamberder::pipeline::PipelineOptions {
+    hamberder::pipeline::PipelineOptions {
+        tag_capacity: capacity,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_pipeline_backpressure() {
+    let source = format!("[{}1]", "1, ".repeat(100_000));
+    let (str_tx, str_rx) = std::sync::mpsc::channel();
+    str_tx.send(source.clone()).unwrap();
+    drop(str_tx);
+    let (tag_rx, handle) = hamberder::parse_cancellable(str_rx, with_tag_capacity(Some(16)));
+    // nobody is reading, so the parser has to wait
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    assert!(!handle.is_finished());
+    assert_eq!(tag_rx.iter().count(), 100_003);
+    assert!(handle.join().is_ok());
+
+    let (str_tx, str_rx) = std::sync::mpsc::channel();
+    str_tx.send(source).unwrap();
+    drop(str_tx);
+    let (tag_rx, handle) = hamberder::parse_cancellable(str_rx, with_tag_capacity(None));
+    assert!(handle.join().is_ok());
+    assert_eq!(tag_rx.iter().count(), 100_003);
+}
+
+#[test]
+fn test_pipeline_cancel_while_full() {
+    // cancelling gets a parser out of waiting on a full sink, even if nobody ever
+    // reads from it again
+    let (tag_rx, handle) =
+        hamberder::parse_cancellable(endless_array(), with_tag_capacity(Some(4)));
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    handle.cancel();
+    assert!(matches!(handle.join(), Err(Error::Cancelled)));
+    assert!(tag_rx.iter().count() <= 4);
+}
+
+#[test]
+fn test_pipeline_drop_full_sink() {
+    // a parser waiting for room notices when the sink goes away, cancel handle or not
+    let (tag_rx, handle) =
+        hamberder::parse_cancellable(endless_array(), with_tag_capacity(Some(4)));
+    let cancel = handle.cancel_handle();
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    assert!(!handle.is_finished());
+    drop(tag_rx);
+    assert!(handle.join().is_ok());
+    assert!(!cancel.is_cancelled());
+}
+
+#[test]
+fn test_pipeline_batches() {
+    let source = "{\"a\": [1, 2, 3], \"b\": {\"c\": null}}";
+    let expected: parser::TagVec = hamberder::parse_str(source).collect();
+    for batch_size in [1, 2, 5, 1000] {
+        let options = hamberder::pipeline::PipelineOptions {
+            batch_size,
+            ..Default::default()
+        };
+        let (tag_rx, handle) = hamberder::parse_cancellable(source, options);
+        assert_eq!(tag_rx.into_iter().collect::<parser::TagVec>(), expected);
+        assert!(handle.join().is_ok());
+    }
+}
+
+#[test]
+fn test_pipeline_unbatched_cancel_while_full() {
+    // a batch size of 1 sends the tags on their own, and cancelling still gets a parser
+    // out of waiting for room
+    let options = hamberder::pipeline::PipelineOptions {
+        tag_capacity: Some(4),
+        batch_size: 1,
+        ..Default::default()
+    };
+    let (tag_rx, handle) = hamberder::parse_cancellable(endless_array(), options);
+    assert_eq!(tag_rx.recv().unwrap(), parser::Tag::BeginArray);
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    handle.cancel();
+    assert!(matches!(handle.join(), Err(Error::Cancelled)));
+    assert!(tag_rx.iter().count() <= 4);
+}
+
+#[test]
+fn test_pipeline_partial_batch() {
+    // whatever is ready goes out before the parser waits on the source, full batch or not
+    let (str_tx, str_rx) = std::sync::mpsc::channel();
+    let tag_rx = hamberder::parse(str_rx);
+    str_tx.send(String::from("[1, 2")).unwrap();
+    assert_eq!(tag_rx.recv().unwrap(), parser::Tag::BeginArray);
+    assert_eq!(tag_rx.recv().unwrap(), parser::Tag::Number(String::from("1")));
+    assert!(tag_rx.try_recv().is_err());
+    str_tx.send(String::from(", 3]")).unwrap();
+    drop(str_tx);
+    assert_eq!(tag_rx.iter().count(), 3);
+}
+
+#[test]
+fn test_pipeline_batched_stages() {
+    let (str_tx, str_rx) = std::sync::mpsc::channel();
+    let (token_tx, token_rx) = std::sync::mpsc::channel();
+    let (tag_tx, tag_rx) = std::sync::mpsc::channel();
+    hamberder::lexer::lex_batches(str_rx, token_tx, Default::default());
+    parser::parse_batches(token_rx, tag_tx, Default::default(), 4);
+    str_tx.send(String::from("[true, {\"a\": \"b\"},")).unwrap();
+    str_tx.send(String::from(" null, 12]")).unwrap();
+    drop(str_tx);
+    let tag_sink = hamberder::pipeline::BatchReceiver::new(tag_rx);
+    let tags: parser::TagVec = tag_sink.iter().collect();
+    let expected: parser::TagVec =
+        hamberder::parse_str("[true, {\"a\": \"b\"}, null, 12]").collect();
+    assert_eq!(tags, expected);
+}
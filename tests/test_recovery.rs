@@ -0,0 +1,250 @@
+use hamberder::{lexer, parser};
+
+fn recovering() -> parser::ParserOptions {
+    parser::ParserOptions {
+        recover: true,
+        ..Default::default()
+    }
+}
+
+fn parse_recovering(source: &str) -> parser::TagVec {
+    parser::PullParser::with_options(source, recovering()).collect()
+}
+
+fn error_messages(tags: &[parser::Tag]) -> Vec<&str> {
+    tags.iter()
+        .filter_map(|tag| match tag {
+            parser::Tag::Error(err_info, _) => Some(err_info.message.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn without_errors(tags: &[parser::Tag]) -> Vec<&parser::Tag> {
+    tags.iter()
+        .filter(|tag| !matches!(tag, parser::Tag::Error(_, _)))
+        .collect()
+}
+
+fn number(text: &str) -> parser::Tag {
+    parser::Tag::Number(String::from(text))
+}
+
+fn key(name: &str) -> parser::Tag {
+    parser::Tag::ObjectKey(String::from(name))
+}
+
+#[test]
+fn test_recovery_reports_every_error() {
+    let source = "{\n  \"name\" \"hamberder\",\n  \"tags\": [1, , 3 4],\n  \"size\": -,\n  \"ok\": tru\n}";
+    let tags = parse_recovering(source);
+    assert_eq!(
+        error_messages(&tags),
+        vec![
+            "Expected ':' after the key 'name'",
+            "Syntax error. Encountered unexpected ',' in array",
+            "Expected comma or closing bracket",
+            "Syntax error. Expected digits after '-'",
+            "Unrecognized token",
+        ]
+    );
+    assert_eq!(
+        without_errors(&tags),
+        vec![
+            &parser::Tag::BeginObject,
+            &key("name"),
+            &parser::Tag::StringLiteral(String::from("hamberder")),
+            &key("tags"),
+            &parser::Tag::BeginArray,
+            &number("1"),
+            &parser::Tag::Recovered,
+            &number("3"),
+            &number("4"),
+            &parser::Tag::EndArray,
+            &key("size"),
+            &parser::Tag::Recovered,
+            &key("ok"),
+            &parser::Tag::Recovered,
+            &parser::Tag::EndObject,
+        ]
+    );
+
+    // without recovery, the first error is the last tag
+    let tags: parser::TagVec = hamberder::parse_str(source).collect();
+    assert_eq!(error_messages(&tags), vec!["Expected ':' after the key 'name'"]);
+    assert!(matches!(tags.last(), Some(parser::Tag::Error(_, _))));
+}
+
+#[test]
+fn test_recovery_skips_to_next_element() {
+    // everything up to the next ',' or ']' at the same depth goes, nested or not
+    let tags = parse_recovering("[1, {\"a\": [2, 3] : 4}, 5]");
+    assert_eq!(
+        error_messages(&tags),
+        vec!["Expected comma or closing curly brace"]
+    );
+    assert_eq!(
+        without_errors(&tags),
+        vec![
+            &parser::Tag::BeginArray,
+            &number("1"),
+            &parser::Tag::BeginObject,
+            &key("a"),
+            &parser::Tag::BeginArray,
+            &number("2"),
+            &number("3"),
+            &parser::Tag::EndArray,
+            &parser::Tag::EndObject,
+            &number("5"),
+            &parser::Tag::EndArray,
+        ]
+    );
+
+    let tags: Vec<parser::PositionedTag> =
+        parser::PullParser::with_options("[1, -x, 2]", recovering())
+            .into_positioned()
+            .collect();
+    assert_eq!(tags[3].0, parser::Tag::Recovered);
+    assert_eq!(tags[3].1.start, 6);
+    assert_eq!(tags[4].0, number("2"));
+}
+
+#[test]
+fn test_recovery_missing_commas() {
+    let tags = parse_recovering("{\"a\": 1 \"b\": [true false]}");
+    assert_eq!(
+        error_messages(&tags),
+        vec![
+            "Expected comma or closing curly brace",
+            "Expected comma or closing bracket"
+        ]
+    );
+    assert_eq!(without_errors(&tags).len(), 9);
+
+    // a missing comma between two pairs is an error with or without recovery
+    let tags: parser::TagVec = hamberder::parse_str("{\"a\": 1 \"b\": 2}").collect();
+    assert!(matches!(&tags[3], parser::Tag::Error(err_info, _) if err_info.message == "Expected comma or closing curly brace"));
+    assert_eq!(tags.len(), 4);
+}
+
+#[test]
+fn test_recovery_broken_strings() {
+    let tags = parse_recovering("[\"abc\n, \"a\\qb\", \"\\u12x4\"]");
+    assert_eq!(
+        error_messages(&tags),
+        vec![
+            "Multi-line strings are not allowed",
+            "Unsupported escaped character 'q'. Only 'b', 'f', 'n', 'r', 't', 'u<4-digit hex>', '\\', or '\"' are allowed",
+            "The character 'x' is not valid in this context. Only hexadecimal digits (0-9, a-f, A-F) may follow \\u",
+        ]
+    );
+    // the string is closed at the end of its line, and broken escapes are left out
+    assert_eq!(
+        without_errors(&tags),
+        vec![
+            &parser::Tag::BeginArray,
+            &parser::Tag::StringLiteral(String::from("abc")),
+            &parser::Tag::StringLiteral(String::from("aqb")),
+            &parser::Tag::StringLiteral(String::from("x4")),
+            &parser::Tag::EndArray,
+        ]
+    );
+
+    // the object isn't closed either
+    let tags = parse_recovering("{\"a\": \"never closed");
+    assert_eq!(
+        error_messages(&tags),
+        vec![
+            "Encountered end of stream inside a string",
+            "Encountered end of stream, but more tokens expected"
+        ]
+    );
+    assert_eq!(
+        without_errors(&tags),
+        vec![
+            &parser::Tag::BeginObject,
+            &key("a"),
+            &parser::Tag::StringLiteral(String::from("never closed")),
+            &parser::Tag::EndObject,
+        ]
+    );
+}
+
+#[test]
+fn test_recovery_unbalanced_brackets() {
+    // the ']' closes the array the object was in
+    let tags = parse_recovering("[{\"a\": 1], 2]");
+    assert_eq!(
+        error_messages(&tags),
+        vec!["Expected comma or closing curly brace"]
+    );
+    assert_eq!(
+        without_errors(&tags),
+        vec![
+            &parser::Tag::BeginArray,
+            &parser::Tag::BeginObject,
+            &key("a"),
+            &number("1"),
+            &parser::Tag::EndObject,
+            &parser::Tag::EndArray,
+        ]
+    );
+
+    // whatever is still open when the source ends gets closed, with a single error
+    let tags = parse_recovering("{\"a\": [[1, {\"b\":");
+    assert_eq!(
+        error_messages(&tags),
+        vec!["Encountered end of stream, but more tokens expected"]
+    );
+    assert_eq!(
+        without_errors(&tags),
+        vec![
+            &parser::Tag::BeginObject,
+            &key("a"),
+            &parser::Tag::BeginArray,
+            &parser::Tag::BeginArray,
+            &number("1"),
+            &parser::Tag::BeginObject,
+            &key("b"),
+            &parser::Tag::Recovered,
+            &parser::Tag::EndObject,
+            &parser::Tag::EndArray,
+            &parser::Tag::EndArray,
+            &parser::Tag::EndObject,
+        ]
+    );
+}
+
+#[test]
+fn test_recovery_with_documents() {
+    let options = parser::ParserOptions {
+        documents: parser::DocumentMode::Lines,
+        ..recovering()
+    };
+    let tags: parser::TagVec =
+        parser::PullParser::with_options("[1 2]\n{\"a\" 3}\n", options).collect();
+    assert_eq!(
+        error_messages(&tags),
+        vec!["Expected comma or closing bracket", "Expected ':' after the key 'a'"]
+    );
+    assert_eq!(tags.len(), 12);
+    assert_eq!(tags[5], parser::Tag::EndDocument(0));
+    assert_eq!(tags[11], parser::Tag::EndDocument(1));
+}
+
+#[test]
+fn test_recovery_lexer() {
+    let mut lexer = lexer::Lexer::new();
+    lexer.set_recover(true);
+    let tokens: Vec<lexer::TokenPair> =
+        lexer::Tokens::with_lexer(lexer, "[nul, @1, 2 .5]").collect();
+    let kinds: Vec<&lexer::MaybeToken> = tokens.iter().map(|(token, _)| token).collect();
+    assert!(matches!(kinds[1], lexer::MaybeToken::Error(_)));
+    assert_eq!(kinds[2], &lexer::MaybeToken::Comma);
+    assert!(matches!(kinds[3], lexer::MaybeToken::Error(_)));
+    assert_eq!(kinds[4], &lexer::MaybeToken::Integer(String::from("1")));
+    assert!(matches!(kinds[7], lexer::MaybeToken::Error(_)));
+    assert_eq!(kinds[8], &lexer::MaybeToken::Integer(String::from("5")));
+    assert_eq!(tokens[8].1.start, 13);
+    assert_eq!(kinds.len(), 10);
+}
@@ -0,0 +1,152 @@
+use hamberder::{
+    parser::Tag,
+    select::{self, Selected, Selector},
+    Error,
+};
+
+const SOURCE: &str = r#"{
+    "name": "export",
+    "items": [
+        {"id": 1, "price": 10, "tags": {"price": "none"}},
+        {"id": 2, "price": {"net": 8, "gross": 9.5}},
+        {"id": 3},
+        {"id": 4, "price": 40}
+    ]
+}"#;
+
+// the path of every match, along with its tags written out compactly
+fn selected(source: &str, expression: &str) -> Vec<(String, String)> {
+    let mut matches: Vec<(String, String)> = Vec::new();
+    let mut tags = Vec::new();
+    let finish = |matches: &mut Vec<(String, String)>, tags: &mut Vec<Tag>| {
+        if let Some((_, json)) = matches.last_mut() {
+            *json = hamberder::writer::to_string(&*tags, Default::default()).unwrap();
+        }
+        tags.clear();
+    };
+    for selected in select::select(hamberder::parse_str(source), expression).unwrap() {
+        match selected {
+            Selected::Path(path) => {
+                finish(&mut matches, &mut tags);
+                matches.push((path, String::new()));
+            }
+            Selected::Tag(tag) => tags.push(tag),
+        }
+    }
+    finish(&mut matches, &mut tags);
+    matches
+}
+
+fn pair(path: &str, json: &str) -> (String, String) {
+    (path.to_string(), json.to_string())
+}
+
+#[test]
+fn test_select_child() {
+    assert_eq!(
+        selected(SOURCE, "$.items[*].price"),
+        vec![
+            pair("/items/0/price", "10"),
+            pair("/items/1/price", r#"{"net":8,"gross":9.5}"#),
+            pair("/items/3/price", "40"),
+        ]
+    );
+    assert_eq!(selected(SOURCE, "$['name']"), vec![pair("/name", "\"export\"")]);
+    assert_eq!(selected(SOURCE, "$.items[1].price.gross"), vec![pair("/items/1/price/gross", "9.5")]);
+    assert_eq!(selected(SOURCE, "$.items[7]"), vec![]);
+    assert_eq!(selected(SOURCE, "$.missing.price"), vec![]);
+    assert_eq!(selected("[1, 2]", "$"), vec![pair("", "[1,2]")]);
+    assert_eq!(selected(r#"{"a.b": {"c": 1}}"#, r#"$["a.b"].*"#), vec![pair("/a.b/c", "1")]);
+}
+
+#[test]
+fn test_select_slices() {
+    let ids = |expression: &str| -> Vec<String> {
+        selected(SOURCE, expression).into_iter().map(|(_, json)| json).collect()
+    };
+    assert_eq!(ids("$.items[1:3].id"), vec!["2", "3"]);
+    assert_eq!(ids("$.items[:2].id"), vec!["1", "2"]);
+    assert_eq!(ids("$.items[2:].id"), vec!["3", "4"]);
+    assert_eq!(ids("$.items[::2].id"), vec!["1", "3"]);
+    assert_eq!(ids("$.items[1::2].id"), vec!["2", "4"]);
+    assert_eq!(ids("$.items[3:1].id"), Vec::<String>::new());
+}
+
+#[test]
+fn test_select_descendants() {
+    assert_eq!(
+        selected(SOURCE, "$..price"),
+        vec![
+            pair("/items/0/price", "10"),
+            pair("/items/0/tags/price", "\"none\""),
+            pair("/items/1/price", r#"{"net":8,"gross":9.5}"#),
+            pair("/items/3/price", "40"),
+        ]
+    );
+    assert_eq!(
+        selected(SOURCE, "$.items..net"),
+        vec![pair("/items/1/price/net", "8")]
+    );
+    assert_eq!(
+        selected("[[1, [2]], [3]]", "$..[0]"),
+        vec![pair("/0", "[1,[2]]"), pair("/1/0", "3")]
+    );
+    // a match inside another one is part of it and doesn't come out again
+    assert_eq!(
+        selected(r#"{"a": {"a": 1}}"#, "$..a"),
+        vec![pair("/a", r#"{"a":1}"#)]
+    );
+    assert_eq!(
+        selected(r#"{"x": [{"y": 1}, 2]}"#, "$..*"),
+        vec![pair("/x", r#"[{"y":1},2]"#)]
+    );
+}
+
+#[test]
+fn test_select_streaming() {
+    // matches come out as soon as their tags are there
+    let (str_tx, str_rx) = std::sync::mpsc::channel();
+    let tag_sink = hamberder::parse(str_rx);
+    str_tx.send(String::from(r#"{"items": [{"price": 1}, "#)).unwrap();
+    let mut selection = select::select(tag_sink, "$.items[*].price").unwrap();
+    assert_eq!(selection.next(), Some(Selected::Path(String::from("/items/0/price"))));
+    assert_eq!(selection.next(), Some(Selected::Tag(Tag::Number(String::from("1")))));
+    str_tx.send(String::from(r#"{"price": 2}]}"#)).unwrap();
+    drop(str_tx);
+    assert_eq!(selection.next(), Some(Selected::Path(String::from("/items/1/price"))));
+    assert_eq!(selection.next(), Some(Selected::Tag(Tag::Number(String::from("2")))));
+    assert_eq!(selection.next(), None);
+
+    // every document is matched on its own
+    let selector = Selector::parse("$.id").unwrap();
+    let matches: Vec<Selected> =
+        select::with_selector(hamberder::parse_ndjson("{\"id\": 1}\n{\"id\": 2}\n"), selector).collect();
+    assert_eq!(matches.len(), 4);
+    assert_eq!(matches[2], Selected::Path(String::from("/id")));
+}
+
+#[test]
+fn test_select_errors() {
+    // errors in the stream go out wherever they are
+    let matches: Vec<Selected> =
+        select::select(hamberder::parse_str(r#"{"skipped": [1 2], "id": 3}"#), "$.id").unwrap().collect();
+    assert_eq!(matches.len(), 1);
+    assert!(matches!(matches[0], Selected::Tag(Tag::Error(_, _))));
+
+    let invalid = |expression: &str| matches!(Selector::parse(expression), Err(Error::Selector(_)));
+    assert!(invalid("items"));
+    assert!(invalid("$."));
+    assert!(invalid("$.."));
+    assert!(invalid("$.items[-1]"));
+    assert!(invalid("$.items[-2:]"));
+    assert!(invalid("$.items[::0]"));
+    assert!(invalid("$.items[?(@.price > 1)]"));
+    assert!(invalid("$.items[0,1]"));
+    assert!(invalid("$.items[x]"));
+    assert!(invalid("$.items["));
+    assert!(invalid("$['a]"));
+    assert!(invalid("$[ ']"));
+    assert!(invalid("$.[0]"));
+    assert!(invalid("$[]"));
+    assert!(!invalid(r#"$['it\'s'][0:1:1]..x.*"#));
+}
@@ -0,0 +1,211 @@
+#![cfg(feature = "serde")]
+
+use hamberder::{de, parser::Tag, ser, Error};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Deserialize, Debug, PartialEq)]
+struct Row {
+    id: u32,
+    first_name: String,
+    last_name: String,
+    lat: f64,
+    long: f64,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+enum Shape {
+    Empty,
+    Circle(f64),
+    Rect { w: u8, h: u8 },
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+struct Config {
+    version: f32,
+    name: Option<String>,
+    tags: Vec<String>,
+    shapes: Vec<Shape>,
+    limits: HashMap<String, i64>,
+}
+
+#[test]
+fn test_serde_struct() {
+    // field order doesn't matter, and fields the struct doesn't have are skipped
+    let source = r#"{
+        "shapes": ["Empty", {"Circle": 1.5}, {"Rect": {"h": 2, "w": 3}}],
+        "unknown": {"deeply": [1, {"nested": null}]},
+        "limits": {"max": 10, "min": -10},
+        "tags": ["a", "b"],
+        "name": null,
+        "version": 1.0
+    }"#;
+    let config: Config = de::from_str(source).unwrap();
+    assert_eq!(config.version, 1.0);
+    assert_eq!(config.name, None);
+    assert_eq!(config.tags, vec!["a", "b"]);
+    assert_eq!(
+        config.shapes,
+        vec![Shape::Empty, Shape::Circle(1.5), Shape::Rect { w: 3, h: 2 }]
+    );
+    assert_eq!(config.limits["min"], -10);
+}
+
+#[test]
+fn test_serde_from_tag_sink() {
+    let (str_tx, str_rx) = std::sync::mpsc::channel();
+    let tag_sink = hamberder::parse(str_rx);
+    str_tx.send(String::from("[1, 18446744073709551615, ")).unwrap();
+    str_tx.send(String::from("1e400, 99999999999999999999]")).unwrap();
+    drop(str_tx);
+    let result: Result<Vec<f64>, Error> = de::from_tags(tag_sink);
+    assert!(result.is_err());
+
+    let values: (i8, u64, f64) =
+        de::from_tags(hamberder::parse_str("[-1, 18446744073709551615, 99999999999999999999]"))
+            .unwrap();
+    assert_eq!(values, (-1, u64::MAX, 1e20));
+}
+
+#[test]
+fn test_serde_iter_array() {
+    let (str_tx, str_rx) = std::sync::mpsc::channel();
+    let tag_sink = hamberder::parse(str_rx);
+    str_tx
+        .send(String::from(r#"[{"id":1,"first_name":"Audy","last_name":"Taborre","lat":-17.3,"#))
+        .unwrap();
+    let mut rows = de::iter_array::<Row, _>(tag_sink.iter());
+    // the first row comes out as soon as it's there, the rest of the array isn't needed
+    str_tx.send(String::from(r#""long":31.5}, "#)).unwrap();
+    let first = rows.next().unwrap().unwrap();
+    assert_eq!(first.first_name, "Audy");
+    str_tx
+        .send(String::from(r#"{"long":2,"lat":1,"last_name":"Ng","first_name":"Bert","id":2}]"#))
+        .unwrap();
+    drop(str_tx);
+    assert_eq!(rows.next().unwrap().unwrap().id, 2);
+    assert!(rows.next().is_none());
+
+    let rows: Vec<Result<u8, Error>> = de::iter_array(hamberder::parse_str("[1, 2, 300, 4]")).collect();
+    assert_eq!(rows.len(), 3);
+    assert!(rows[2].is_err());
+
+    let rows: Vec<Result<u8, Error>> = de::iter_array(hamberder::parse_str("[]")).collect();
+    assert!(rows.is_empty());
+    let rows: Vec<Result<u8, Error>> = de::iter_array(hamberder::parse_str("{}")).collect();
+    assert!(matches!(rows[..], [Err(Error::Data(_, _))]));
+}
+
+#[test]
+fn test_serde_errors() {
+    // with positioned tags, a value of the wrong type says where it is
+    let err = de::from_str::<Row>("{\"id\": 1,\n \"first_name\": 2}").unwrap_err();
+    match &err {
+        Error::Data(message, Some(info)) => {
+            assert!(message.contains("expected a string"));
+            assert_eq!(info.line_number, 2);
+            assert_eq!(info.start, 25);
+        }
+        _ => panic!("unexpected {:?}", err),
+    }
+    let err = de::from_str::<Row>("{\"id\": 1}").unwrap_err();
+    assert!(err.to_string().starts_with("missing field `first_name`"));
+
+    // syntax errors come from the tag stream, positions and all
+    let err = de::from_str::<Vec<u8>>("[1, 2 3]").unwrap_err();
+    assert!(matches!(err, Error::Syntax(_, _)));
+    assert_eq!(err.info().unwrap().start, 6);
+
+    // so do the ones after the value
+    let err = de::from_str::<Vec<u8>>("[1] [2]").unwrap_err();
+    assert!(matches!(err, Error::Syntax(_, _)));
+
+    let err = de::from_str::<(u8, u8)>("[1, 2, 3]").unwrap_err();
+    assert!(matches!(err, Error::Data(_, Some(_))));
+}
+
+fn data_error_position(err: Error) -> (usize, usize) {
+    match err {
+        Error::Data(_, Some(info)) => (info.line_number, info.char_position),
+        err => panic!("unexpected {:?}", err),
+    }
+}
+
+#[test]
+fn test_serde_error_positions() {
+    // every way in that parses by itself keeps the positions
+    let source = "[1,\n 2,\n \"three\"]";
+    let err = de::from_str::<Vec<u8>>(source).unwrap_err();
+    assert_eq!(data_error_position(err), (3, 9));
+    let err = de::from_reader::<Vec<u8>, _>(source.as_bytes()).unwrap_err();
+    assert_eq!(data_error_position(err), (3, 9));
+
+    let path = std::env::temp_dir().join(format!("hamberder_de_{}.json", std::process::id()));
+    std::fs::write(&path, source).unwrap();
+    let path = path.to_str().unwrap();
+    let err = de::from_file::<Vec<u8>>(path).unwrap_err();
+    assert_eq!(data_error_position(err), (3, 9));
+    let rows: Vec<Result<u8, Error>> =
+        de::iter_array(hamberder::parse_file_positioned(path).unwrap()).collect();
+    assert_eq!(rows.len(), 3);
+    assert_eq!(data_error_position(rows.into_iter().nth(2).unwrap().unwrap_err()), (3, 9));
+    std::fs::remove_file(path).unwrap();
+
+    assert!(matches!(de::from_file::<u8>("no/such/file.json"), Err(Error::Io(_))));
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+struct Drawing {
+    title: String,
+    scale: f64,
+    shapes: Vec<Shape>,
+    layers: BTreeMap<String, Option<(bool, char)>>,
+    unit: (),
+}
+
+#[test]
+fn test_serde_serialize() {
+    let drawing = Drawing {
+        title: String::from("a \"b\"\n"),
+        scale: 2.0,
+        shapes: vec![Shape::Empty, Shape::Circle(1.5), Shape::Rect { w: 3, h: 2 }],
+        layers: BTreeMap::from([(String::from("1"), Some((true, 'x'))), (String::from("2"), None)]),
+        unit: (),
+    };
+    let json = ser::to_string(&drawing).unwrap();
+    assert_eq!(
+        json,
+        concat!(
+            r#"{"title":"a \"b\"\n","scale":2.0,"#,
+            r#""shapes":["Empty",{"Circle":1.5},{"Rect":{"w":3,"h":2}}],"#,
+            r#""layers":{"1":[true,"x"],"2":null},"unit":null}"#
+        )
+    );
+    let again: Drawing = de::from_str(&json).unwrap();
+    assert_eq!(again, drawing);
+    let pretty = ser::to_string_pretty(&drawing).unwrap();
+    assert_eq!(de::from_str::<Drawing>(&pretty).unwrap(), drawing);
+    assert!(pretty.starts_with("{\n  \"title\": "));
+
+    assert_eq!(
+        ser::to_tags(&Shape::Circle(0.5)).unwrap(),
+        vec![
+            Tag::BeginObject,
+            Tag::ObjectKey(String::from("Circle")),
+            Tag::Number(String::from("0.5")),
+            Tag::EndObject,
+        ]
+    );
+    assert_eq!(ser::to_string(&[f64::NAN, 1e300]).unwrap(), "[null,1e300]");
+    // an f32 is written as an f32, and reads back the same
+    assert_eq!(ser::to_string(&(0.1f32, f32::INFINITY, 1e30f32)).unwrap(), "[0.1,null,1e30]");
+    assert_eq!(de::from_str::<f32>(&ser::to_string(&0.1f32).unwrap()).unwrap(), 0.1f32);
+    let out = ser::to_writer(Vec::new(), &vec![1u8], Default::default()).unwrap();
+    assert_eq!(out, b"[1]");
+
+    // keys have to be strings in json
+    let map = BTreeMap::from([(1, 'a'), (2, 'b')]);
+    assert_eq!(ser::to_string(&map).unwrap(), r#"{"1":"a","2":"b"}"#);
+    let map = HashMap::from([(vec![1], 1)]);
+    assert!(matches!(ser::to_string(&map), Err(Error::Data(_, None))));
+}
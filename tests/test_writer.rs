@@ -0,0 +1,124 @@
+use hamberder::{
+    parser::{Tag, TagVec},
+    writer::{self, TagWriter, WriterOptions},
+    Error,
+};
+
+const SOURCE: &str = r#"{
+    "version":  1.0,
+    "config": ["en", "de", {"jp": null}],
+    "empty": {}, "none": [],
+    "disabled": true
+}"#;
+
+#[test]
+fn test_writer_compact() {
+    let tags: TagVec = hamberder::parse_str(SOURCE).collect();
+    let json = writer::to_string(&tags, WriterOptions::compact()).unwrap();
+    assert_eq!(
+        json,
+        r#"{"version":1.0,"config":["en","de",{"jp":null}],"empty":{},"none":[],"disabled":true}"#
+    );
+    // and back again
+    let again: TagVec = hamberder::parse_str(&json).collect();
+    assert_eq!(again, tags);
+}
+
+#[test]
+fn test_writer_pretty() {
+    let tags: TagVec = hamberder::parse_str(SOURCE).collect();
+    let json = writer::to_string(&tags, WriterOptions::pretty()).unwrap();
+    assert_eq!(
+        json,
+        r#"{
+  "version": 1.0,
+  "config": [
+    "en",
+    "de",
+    {
+      "jp": null
+    }
+  ],
+  "empty": {},
+  "none": [],
+  "disabled": true
+}"#
+    );
+
+    let options = WriterOptions {
+        indent: Some(String::from("\t")),
+    };
+    let json = writer::to_string(&hamberder::parse_str("[[1]]").collect::<TagVec>(), options);
+    assert_eq!(json.unwrap(), "[\n\t[\n\t\t1\n\t]\n]");
+}
+
+#[test]
+fn test_writer_escapes() {
+    let tags = vec![
+        Tag::BeginObject,
+        Tag::ObjectKey(String::from("say \"hi\"")),
+        Tag::StringLiteral(String::from("a\\b/c\n\r\t\u{8}\u{c}\u{1}\u{7f}é😀")),
+        Tag::EndObject,
+    ];
+    let json = writer::to_string(&tags, WriterOptions::compact()).unwrap();
+    assert_eq!(json, "{\"say \\\"hi\\\"\":\"a\\\\b/c\\n\\r\\t\\b\\f\\u0001\u{7f}é😀\"}");
+    let again: TagVec = hamberder::parse_str(&json).collect();
+    assert_eq!(again, tags);
+}
+
+#[test]
+fn test_writer_numbers() {
+    let number = |lexeme: &str| {
+        writer::to_string(&[Tag::Number(lexeme.to_string())], WriterOptions::compact()).ok()
+    };
+    assert_eq!(number("-2.5E3").unwrap(), "-2.5E3");
+    assert_eq!(number("18446744073709551616").unwrap(), "18446744073709551616");
+    assert_eq!(number("+.5").unwrap(), "0.5");
+    assert_eq!(number("-0x10000000000000000").unwrap(), "-18446744073709551616");
+    assert_eq!(number("Infinity"), None);
+    assert_eq!(number("NaN"), None);
+
+    let tags: TagVec = hamberder::parse_json5("[0x1F, +.5, 1., 'x',]").iter().collect();
+    let json = writer::to_string(&tags, WriterOptions::compact());
+    assert_eq!(json.unwrap(), "[31,0.5,1.0,\"x\"]");
+}
+
+#[test]
+fn test_writer_documents() {
+    let mut tags: TagVec = hamberder::parse_ndjson("{\"a\":1}\n[true]\n").iter().collect();
+    // comments are dropped
+    tags.insert(4, Tag::Comment(String::from("// skipped")));
+    let json = writer::to_string(&tags, WriterOptions::compact());
+    assert_eq!(json.unwrap(), "{\"a\":1}\n[true]\n");
+}
+
+#[test]
+fn test_writer_invalid() {
+    // the error, if there is one
+    let invalid = |tags: &[Tag]| {
+        let mut writer = TagWriter::new(Vec::new());
+        match writer.write_tags(tags) {
+            Ok(()) => writer.finish().err(),
+            Err(err) => Some(err),
+        }
+    };
+    let key = || Tag::ObjectKey(String::from("a"));
+    assert!(matches!(invalid(&[Tag::EndArray]), Some(Error::Write(_))));
+    assert!(matches!(invalid(&[Tag::BeginArray, Tag::EndObject]), Some(Error::Write(_))));
+    assert!(matches!(invalid(&[key()]), Some(Error::Write(_))));
+    assert!(matches!(invalid(&[Tag::BeginObject, Tag::NullLiteral]), Some(Error::Write(_))));
+    assert!(matches!(
+        invalid(&[Tag::BeginObject, key(), key()]),
+        Some(Error::Write(_))
+    ));
+    assert!(matches!(
+        invalid(&[Tag::BeginObject, key(), Tag::EndObject]),
+        Some(Error::Write(_))
+    ));
+    assert!(matches!(invalid(&[Tag::TrueLiteral, Tag::TrueLiteral]), Some(Error::Write(_))));
+    assert!(matches!(invalid(&[Tag::BeginArray]), Some(Error::Write(_))));
+    assert!(matches!(invalid(&[Tag::Recovered]), Some(Error::Write(_))));
+
+    let tags: TagVec = hamberder::parse_str("[true false]").collect();
+    assert!(matches!(invalid(&tags), Some(Error::Syntax(_, _))));
+}